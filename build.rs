@@ -0,0 +1,34 @@
+use sha3::{Digest as _, Keccak256};
+
+// crates whose sources determine the circuits
+const CIRCUIT_CRATES: [&str; 3] = ["mining_circuit_v1", "intmax2-zkp", "plonky2"];
+
+// Sets CIRCUIT_SOURCES_DIGEST to a digest of the locked revisions of the circuit crates, which
+// keys the circuit cache. Empty without a Cargo.lock, which disables the cache.
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let digest = match std::fs::read_to_string("Cargo.lock") {
+        Ok(cargo_lock) => {
+            let mut hasher = Keccak256::new();
+            for name in CIRCUIT_CRATES {
+                let source = locked_source(&cargo_lock, name).unwrap_or_default();
+                hasher.update(name.as_bytes());
+                hasher.update(source.as_bytes());
+            }
+            hex::encode(&hasher.finalize()[..8])
+        }
+        Err(_) => String::new(),
+    };
+    println!("cargo:rustc-env=CIRCUIT_SOURCES_DIGEST={}", digest);
+}
+
+// the `source` of the package in Cargo.lock, which pins the git revision
+fn locked_source<'a>(cargo_lock: &'a str, name: &str) -> Option<&'a str> {
+    let name_line = format!("name = \"{}\"", name);
+    cargo_lock
+        .split("[[package]]")
+        .find(|package| package.lines().any(|line| line.trim() == name_line))?
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("source = "))
+        .map(|source| source.trim_matches('"'))
+}
@@ -4,7 +4,7 @@ use alloy::{
 };
 
 use crate::{
-    external_api::contracts::utils::NormalProvider,
+    external_api::contracts::{gas_price_oracle::GasPriceOracleContract, utils::NormalProvider},
     services::{
         assets_status::AssetsStatus, claim::MAX_CLAIMS, utils::insufficient_balance_instruction,
     },
@@ -27,8 +27,14 @@ pub async fn validate_deposit_address_balance(
     let settings = Settings::load()?;
     let gas_price = U256::from(provider.get_gas_price().await?);
     let single_deposit_gas = U256::from(settings.blockchain.single_deposit_gas);
-    let min_balance =
-        (mining_unit + gas_price * single_deposit_gas) * U256::from(remaining_deposits);
+    let single_deposit_l1_fee = GasPriceOracleContract::new(provider.clone())
+        .estimate_l1_fee_for_calldata_size(
+            settings.blockchain.int1_address.parse()?,
+            settings.blockchain.single_deposit_calldata_size,
+        )
+        .await?;
+    let min_balance = (mining_unit + gas_price * single_deposit_gas + single_deposit_l1_fee)
+        * U256::from(remaining_deposits);
     insufficient_balance_instruction(provider, deposit_address, min_balance, "deposit").await?;
     Ok(())
 }
@@ -37,13 +43,33 @@ pub async fn validate_withdrawal_address_balance(
     provider: &NormalProvider,
     assets_status: &AssetsStatus,
     withdrawal_address: Address,
+    remaining_forwards: u64, // token transfers forwarding the claimed ITX still to come
 ) -> anyhow::Result<()> {
     let remaining_claims = assets_status.short_term_not_claimed_indices.len();
     let num_claim_tx = remaining_claims.div_ceil(MAX_CLAIMS);
     let settings = Settings::load()?;
     let gas_price = U256::from(provider.get_gas_price().await?);
     let single_claim_gas = U256::from(settings.blockchain.single_claim_gas);
-    let min_balance = single_claim_gas * gas_price * U256::from(num_claim_tx);
+    let oracle = GasPriceOracleContract::new(provider.clone());
+    let single_claim_l1_fee = oracle
+        .estimate_l1_fee_for_calldata_size(
+            settings.blockchain.minter_address.parse()?,
+            settings.blockchain.single_claim_calldata_size,
+        )
+        .await?;
+    let mut min_balance =
+        (single_claim_gas * gas_price + single_claim_l1_fee) * U256::from(num_claim_tx);
+    if remaining_forwards > 0 {
+        let token_transfer_l1_fee = oracle
+            .estimate_l1_fee_for_calldata_size(
+                settings.blockchain.token_address.parse()?,
+                settings.blockchain.single_token_transfer_calldata_size,
+            )
+            .await?;
+        min_balance += (U256::from(settings.blockchain.single_token_transfer_gas) * gas_price
+            + token_transfer_l1_fee)
+            * U256::from(remaining_forwards);
+    }
     insufficient_balance_instruction(provider, withdrawal_address, min_balance, "withdrawal")
         .await?;
     Ok(())
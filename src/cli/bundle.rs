@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+
+use alloy::primitives::B256;
+use anyhow::ensure;
+
+use crate::{
+    cli::console::{print_status, print_warning},
+    services::{
+        bundle::{
+            check_job_roots, generate_bundle, journal_job, prove_job, wrap_job, BundleJob,
+            JobBundle,
+        },
+        claim::{claim::run_claim_jobs, MAX_CLAIMS},
+        mining::withdrawal::run_withdrawal_jobs,
+    },
+    state::{key::Key, prover::Prover, state::State},
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct BundleOptions {
+    /// Job bundle file read or written by the bundle modes
+    #[arg(long, default_value = "bundle.json")]
+    pub bundle: PathBuf,
+
+    /// Also wrap the proofs with Gnark in prove-bundle mode
+    #[arg(long)]
+    pub with_gnark: bool,
+
+    /// Let submit-bundle prove the jobs of the bundle that are not proved yet on this machine
+    #[arg(long)]
+    pub prove_locally: bool,
+}
+
+/// Writes the witnesses of the pending withdrawals and claims to the bundle file.
+pub async fn generate_bundle_mode(
+    state: &mut State,
+    withdrawal_private_key: B256,
+    options: &BundleOptions,
+) -> anyhow::Result<()> {
+    let key = Key::new(withdrawal_private_key, 0);
+    let assets_status = state.sync_and_fetch_assets(&key).await?;
+    let withdrawals = assets_status.get_not_withdrawn_events();
+    let mut claims = Vec::new();
+    for is_short_term in [true, false] {
+        for events in assets_status
+            .get_not_claimed_events(is_short_term)
+            .chunks(MAX_CLAIMS)
+        {
+            claims.push((is_short_term, events.to_vec()));
+        }
+    }
+    if withdrawals.is_empty() && claims.is_empty() {
+        println!("No withdrawals or claims to bundle.");
+        return Ok(());
+    }
+    let bundle = generate_bundle(state, &key, withdrawals, claims).await?;
+    bundle.save(&options.bundle)?;
+    print_status(format!(
+        "Wrote {} jobs to {:?}. Run prove-bundle with this file on the proving machine.",
+        bundle.jobs.len(),
+        options.bundle
+    ));
+    Ok(())
+}
+
+/// Proves the jobs of the bundle file in place. Needs no keys or RPC access.
+pub async fn prove_bundle_mode(options: &BundleOptions) -> anyhow::Result<()> {
+    let mut bundle = JobBundle::load(&options.bundle)?;
+    let prover = Prover::new();
+    let address = bundle.withdrawal_address;
+    for i in 0..bundle.jobs.len() {
+        let job = &mut bundle.jobs[i];
+        print_status(format!("[{}] proving with plonky2", job.name()));
+        prove_job(&prover, job)?;
+        if options.with_gnark {
+            print_status(format!("[{}] wrapping with gnark", job.name()));
+            wrap_job(address, job).await?;
+        }
+        // save after each job so that an interrupted run continues where it stopped
+        bundle.save(&options.bundle)?;
+    }
+    print_status(format!(
+        "Proved {} jobs in {:?}. Run submit-bundle with this file on the original machine.",
+        bundle.jobs.len(),
+        options.bundle
+    ));
+    Ok(())
+}
+
+/// Checks the jobs of the proved bundle against the contracts and completes them. Other jobs in
+/// the journal are left for the usual modes.
+pub async fn submit_bundle_mode(
+    state: &State,
+    withdrawal_private_key: B256,
+    options: &BundleOptions,
+) -> anyhow::Result<()> {
+    let bundle = JobBundle::load(&options.bundle)?;
+    let key = Key::new(withdrawal_private_key, 0);
+    ensure!(
+        bundle.withdrawal_address == key.withdrawal_address,
+        "Bundle was generated for withdrawal address {:?}",
+        bundle.withdrawal_address
+    );
+    let unproved = bundle
+        .jobs
+        .iter()
+        .filter(|job| !job.is_proved())
+        .map(|job| job.name())
+        .collect::<Vec<_>>();
+    ensure!(
+        unproved.is_empty() || options.prove_locally,
+        "Jobs {} are not proved yet. Run prove-bundle first, or pass --prove-locally to prove them here",
+        unproved.join(", ")
+    );
+    let mut withdrawals = Vec::new();
+    let mut claims = Vec::new();
+    for job in &bundle.jobs {
+        if let Err(e) = check_job_roots(state, job).await {
+            print_warning(format!("Skipping {}: {:#}", job.name(), e));
+            continue;
+        }
+        if !job.is_proved() {
+            print_status(format!(
+                "{} is not proved yet and is proved here",
+                job.name()
+            ));
+        }
+        journal_job(job)?;
+        match job {
+            BundleJob::Withdrawal { name, .. } => withdrawals.push(name.clone()),
+            BundleJob::Claim { name, .. } => claims.push(name.clone()),
+        }
+    }
+    run_withdrawal_jobs(state, &key, withdrawals).await?;
+    run_claim_jobs(state, &key, claims).await?;
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use alloy::{
     primitives::{
@@ -13,12 +17,13 @@ use dialoguer::{Confirm, Input, Password, Select};
 use strum::IntoEnumIterator as _;
 
 use crate::{
-    external_api::contracts::utils::{get_address_from_private_key, get_provider},
+    external_api::contracts::utils::{get_address_from_private_key, get_provider, RpcEndpoint},
     utils::{
         config::Settings,
-        encryption::{decrypt, encrypt},
+        encryption::{check_format, decrypt, encrypt, is_legacy_format},
         env_config::EnvConfig,
         env_validation::{get_allowed_mining_times, validate_rpc_url},
+        keystore::{import_keystore, ImportKeystoreError},
         network::{get_network, Network},
     },
 };
@@ -39,7 +44,7 @@ pub fn select_network() -> anyhow::Result<Network> {
 }
 
 pub async fn new_config(network: Network) -> anyhow::Result<EnvConfig> {
-    let rpc_url: String = input_rpc_url().await?;
+    let rpc_urls = input_rpc_urls().await?;
     let default_env = Settings::load()?.env;
     let use_default = Confirm::new()
         .with_prompt(format!("Use default settings for max gas price ({} gwei), mining unit ({} ETH) and mining times ({})?", default_env.default_max_gas_price, default_env.default_mining_unit, default_env.default_mining_times))
@@ -59,33 +64,39 @@ pub async fn new_config(network: Network) -> anyhow::Result<EnvConfig> {
         let mining_times = input_mining_times()?;
         (max_gas_price, mining_unit, mining_times)
     };
-    let withdrawal_private_key: B256 = input_withdrawal_private_key(&rpc_url).await?;
+    let (withdrawal_private_key, keystore_path) =
+        input_withdrawal_private_key(&rpc_urls[0]).await?;
     let withdrawal_address = get_address_from_private_key(withdrawal_private_key);
-    let (encrypt, keys, encrypted_keys) = input_encryption(withdrawal_private_key)?;
+    let (encrypt, keys, encrypted_keys, keystore_path) =
+        input_encryption(withdrawal_private_key, keystore_path.as_deref())?;
+    let forward_to = input_forward_to(withdrawal_address)?;
     let config = EnvConfig {
         network,
-        rpc_url,
+        rpc_urls,
         max_gas_price,
         encrypt,
         withdrawal_address,
         withdrawal_private_key: keys,
         encrypted_withdrawal_private_key: encrypted_keys,
+        withdrawal_keystore_path: keystore_path,
         mining_unit,
         mining_times,
+        forward_to,
     };
     Ok(config)
 }
 
 pub async fn modify_config(config: &EnvConfig) -> anyhow::Result<EnvConfig> {
-    let key = recover_withdrawal_private_key(config)?;
+    let mut config = config.clone();
+    let key = recover_withdrawal_private_key(&mut config)?;
     let modify_rpc = Confirm::new()
-        .with_prompt(format!("Modify RPC URL {}?", config.rpc_url))
+        .with_prompt(format!("Modify RPC URLs {}?", config.rpc_urls.join(", ")))
         .default(false)
         .interact()?;
-    let rpc_url = if modify_rpc {
-        input_rpc_url().await?
+    let rpc_urls = if modify_rpc {
+        input_rpc_urls().await?
     } else {
-        config.rpc_url.clone()
+        config.rpc_urls.clone()
     };
     let modify_max_gas_price = Confirm::new()
         .with_prompt(format!(
@@ -107,27 +118,66 @@ pub async fn modify_config(config: &EnvConfig) -> anyhow::Result<EnvConfig> {
         ))
         .default(false)
         .interact()?;
-    let withdrawal_private_key = if modify_withdrawal_address {
-        input_withdrawal_private_key(&rpc_url).await?
+    let (withdrawal_private_key, keystore_path) = if modify_withdrawal_address {
+        input_withdrawal_private_key(&rpc_urls[0]).await?
     } else {
-        key
+        (key, config.withdrawal_keystore_path.clone())
     };
-    let (encrypt, keys, encrypted_keys) = input_encryption(withdrawal_private_key)?;
+    let (encrypt, keys, encrypted_keys, keystore_path) =
+        input_encryption(withdrawal_private_key, keystore_path.as_deref())?;
     let withdrawal_address = get_address_from_private_key(withdrawal_private_key);
+    let modify_forward_to = Confirm::new()
+        .with_prompt(format!(
+            "Modify forwarding address of claimed ITX ({})?",
+            config
+                .forward_to
+                .map(|address| format!("{:?}", address))
+                .unwrap_or("not set".to_string())
+        ))
+        .default(false)
+        .interact()?;
+    let forward_to = if modify_forward_to {
+        input_forward_to(withdrawal_address)?
+    } else {
+        config.forward_to
+    };
     let config = EnvConfig {
         network: config.network,
-        rpc_url,
+        rpc_urls,
         max_gas_price,
         encrypt,
         withdrawal_address,
         withdrawal_private_key: keys,
         encrypted_withdrawal_private_key: encrypted_keys,
+        withdrawal_keystore_path: keystore_path,
         mining_unit: config.mining_unit,
         mining_times: config.mining_times,
+        forward_to,
     };
     Ok(config)
 }
 
+/// Asks for the primary RPC URL and optionally secondary ones used as fallbacks.
+async fn input_rpc_urls() -> anyhow::Result<Vec<String>> {
+    let mut rpc_urls = vec![input_rpc_url().await?];
+    loop {
+        let add_secondary = Confirm::new()
+            .with_prompt("Add a secondary RPC provider used when the others fail or lag behind?")
+            .default(false)
+            .interact()?;
+        if !add_secondary {
+            break;
+        }
+        let rpc_url = input_rpc_url().await?;
+        if rpc_urls.contains(&rpc_url) {
+            println!("{}", style("This RPC URL is already added").red());
+            continue;
+        }
+        rpc_urls.push(rpc_url);
+    }
+    Ok(rpc_urls)
+}
+
 async fn input_rpc_url() -> anyhow::Result<String> {
     loop {
         let items = ["Alchemy", "Infura", "Other"];
@@ -188,10 +238,10 @@ async fn input_custom_url() -> anyhow::Result<String> {
     let rpc_url: String = Input::new()
         .with_prompt(format!("Custom RPC of {}", get_network()))
         .validate_with(|rpc_url: &String| {
-            if rpc_url.starts_with("http") {
+            if RpcEndpoint::parse(rpc_url).is_ok() {
                 Ok(())
             } else {
-                Err("Invalid RPC URL")
+                Err("Invalid RPC URL: expected http(s)://, ws(s):// or an IPC socket path")
             }
         })
         .interact()?;
@@ -248,13 +298,31 @@ fn input_mining_times() -> anyhow::Result<u64> {
     Ok(mining_times)
 }
 
-async fn input_withdrawal_private_key(rpc_url: &str) -> anyhow::Result<B256> {
+/// Returns the withdrawal private key, and the keystore path if it was imported from a keystore file.
+async fn input_withdrawal_private_key(rpc_url: &str) -> anyhow::Result<(B256, Option<PathBuf>)> {
     loop {
-        let withdrawal_private_key: String = Password::new()
-            .with_prompt(format!("Withdrawal private key of {}", get_network()))
-            .validate_with(|input: &String| validate_private_key_with_duplication_check(&[], input))
+        let items = ["Enter private key", "Import from keystore file"];
+        let selection = Select::new()
+            .with_prompt("Choose how to input the withdrawal private key")
+            .items(&items)
+            .default(0)
             .interact()?;
-        let withdrawal_private_key: B256 = withdrawal_private_key.parse()?;
+        let (withdrawal_private_key, keystore_path) = match selection {
+            0 => {
+                let withdrawal_private_key: String = Password::new()
+                    .with_prompt(format!("Withdrawal private key of {}", get_network()))
+                    .validate_with(|input: &String| {
+                        validate_private_key_with_duplication_check(&[], input)
+                    })
+                    .interact()?;
+                (withdrawal_private_key.parse::<B256>()?, None)
+            }
+            1 => {
+                let (key, path) = input_keystore()?;
+                (key, Some(path))
+            }
+            _ => unreachable!(),
+        };
         let withdrawal_address = get_address_from_private_key(withdrawal_private_key);
         println!("Withdrawal Address: {:?}", withdrawal_address);
 
@@ -279,10 +347,10 @@ async fn input_withdrawal_private_key(rpc_url: &str) -> anyhow::Result<B256> {
                     .default(false)
                     .interact()?;
                 if confirm {
-                    break Ok(withdrawal_private_key);
+                    break Ok((withdrawal_private_key, keystore_path));
                 }
             } else {
-                break Ok(withdrawal_private_key);
+                break Ok((withdrawal_private_key, keystore_path));
             }
         }
     }
@@ -310,9 +378,74 @@ fn is_withdrawal_address_duplicated(withdrawal_address: Address) -> anyhow::Resu
     Ok(false)
 }
 
+fn input_forward_to(withdrawal_address: Address) -> anyhow::Result<Option<Address>> {
+    let do_forward = Confirm::new()
+        .with_prompt("Do you want to forward claimed ITX to another address automatically?")
+        .default(false)
+        .interact()?;
+    if !do_forward {
+        return Ok(None);
+    }
+    let forward_to: String = Input::new()
+        .with_prompt("Address to forward claimed ITX to")
+        .validate_with(|input: &String| {
+            let result: Result<Address, _> = input.parse();
+            match result {
+                Ok(address) => {
+                    if address == withdrawal_address {
+                        return Err("Cannot forward to the withdrawal address");
+                    }
+                    Ok(())
+                }
+                Err(_) => Err("Invalid address"),
+            }
+        })
+        .interact()?;
+    Ok(Some(forward_to.parse().unwrap())) // safe to unwrap because of the validation
+}
+
+fn input_keystore() -> anyhow::Result<(B256, PathBuf)> {
+    loop {
+        let path: String = Input::new()
+            .with_prompt("Path to the keystore file")
+            .validate_with(|path: &String| {
+                if Path::new(path).is_file() {
+                    Ok(())
+                } else {
+                    Err("Keystore file not found")
+                }
+            })
+            .interact()?;
+        let path = PathBuf::from(path);
+        let password = Password::new()
+            .with_prompt("Keystore password")
+            .interact()?;
+        match import_keystore(&path, &password) {
+            Ok(key) => break Ok((key, path)),
+            Err(e) => {
+                let colored_message = format!("{}", style(e).red());
+                println!("{}", colored_message);
+            }
+        }
+    }
+}
+
 fn input_encryption(
     withdrawal_private_key: B256,
-) -> anyhow::Result<(bool, Option<B256>, Option<Vec<u8>>)> {
+    keystore_path: Option<&Path>,
+) -> anyhow::Result<(bool, Option<B256>, Option<Vec<u8>>, Option<PathBuf>)> {
+    if let Some(keystore_path) = keystore_path {
+        let use_keystore = Confirm::new()
+            .with_prompt(format!(
+                "Use the keystore file {:?} as the key source?",
+                keystore_path
+            ))
+            .default(true)
+            .interact()?;
+        if use_keystore {
+            return Ok((true, None, None, Some(keystore_path.to_path_buf())));
+        }
+    }
     let do_encrypt = Confirm::new()
         .with_prompt("Do you set password to encrypt private keys?")
         .default(true)
@@ -332,7 +465,7 @@ fn input_encryption(
     } else {
         None
     };
-    Ok((do_encrypt, key, encrypted_key))
+    Ok((do_encrypt, key, encrypted_key, None))
 }
 
 fn validate_private_key_with_duplication_check(
@@ -354,27 +487,116 @@ fn validate_private_key_with_duplication_check(
     }
 }
 
-pub fn recover_withdrawal_private_key(config: &EnvConfig) -> anyhow::Result<B256> {
-    let key = if !config.encrypt {
+/// Recovers the withdrawal private key from the config, asking for the password if needed.
+/// An encrypted key in the legacy format is re-encrypted with the current format on success.
+pub fn recover_withdrawal_private_key(config: &mut EnvConfig) -> anyhow::Result<B256> {
+    let key = if let Some(keystore_path) = &config.withdrawal_keystore_path {
+        if !keystore_path.is_file() {
+            bail!("Keystore file not found at {:?}", keystore_path);
+        }
+        loop {
+            let password = Password::new()
+                .with_prompt("Keystore password")
+                .interact()?;
+            match import_keystore(keystore_path, &password) {
+                Ok(key) => break key,
+                Err(ImportKeystoreError::InvalidPassword) => {
+                    let colored_message = format!("{}", style("Invalid password").red());
+                    println!("{}", colored_message);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    } else if !config.encrypt {
         config.withdrawal_private_key.unwrap()
     } else {
-        loop {
+        let encrypted_key = config.encrypted_withdrawal_private_key.clone().unwrap();
+        check_format(&encrypted_key)?;
+        let (key, password) = loop {
             let password = Password::new().with_prompt("Password").interact()?;
-            match decrypt(
-                &password,
-                config.encrypted_withdrawal_private_key.as_ref().unwrap(),
-            ) {
-                Ok(key) => break key,
+            match decrypt(&password, &encrypted_key) {
+                Ok(key) => break (key, password),
                 Err(_) => {
                     let colored_message = format!("{}", style("Invalid password").red());
                     println!("{}", colored_message);
                 }
             }
+        };
+        if is_legacy_format(&encrypted_key) {
+            migrate_encrypted_key(config, &encrypted_key, &password, key)?;
         }
+        key
     };
     Ok(key)
 }
 
+fn migrate_encrypted_key(
+    config: &mut EnvConfig,
+    legacy_encrypted_key: &[u8],
+    password: &str,
+    key: B256,
+) -> anyhow::Result<()> {
+    config.encrypted_withdrawal_private_key = Some(encrypt(password, &key)?);
+    let saved_index = match config.find_saved_index()? {
+        Some(index) => {
+            let saved = EnvConfig::load_from_file(config.network, index)?;
+            // only rewrite the file if it holds the same legacy key as the loaded config
+            if saved.encrypted_withdrawal_private_key.as_deref() == Some(legacy_encrypted_key) {
+                Some(index)
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+    if let Some(index) = saved_index {
+        config.save_to_file(index)?;
+        log::info!("Migrated encrypted private key of config #{}", index);
+        println!(
+            "The encrypted private key of config #{} was migrated to the new encryption format.",
+            index
+        );
+    } else {
+        let message = format!(
+            "ENCRYPTED_WITHDRAWAL_PRIVATE_KEY uses a deprecated encryption format. Please replace it with: {}",
+            hex::encode(config.encrypted_withdrawal_private_key.as_ref().unwrap())
+        );
+        println!(
+            "{} {}",
+            style("WARNING:").yellow().bold(),
+            style(message).yellow()
+        );
+    }
+    config.export_to_env()?;
+    Ok(())
+}
+
+pub fn change_password(config: &EnvConfig, withdrawal_private_key: B256) -> anyhow::Result<()> {
+    if config.withdrawal_keystore_path.is_some() {
+        bail!("The private key is stored in a keystore file. Please change the password of the keystore file with your keystore tool.");
+    }
+    if !config.encrypt {
+        bail!("The private key is not encrypted. Please modify the config to set a password.");
+    }
+    let password = Password::new()
+        .with_prompt("New password")
+        .with_confirmation("Confirm new password", "Passwords do not match")
+        .interact()?;
+    let mut config = config.clone();
+    config.encrypted_withdrawal_private_key = Some(encrypt(&password, &withdrawal_private_key)?);
+    if let Some(index) = config.find_saved_index()? {
+        config.save_to_file(index)?;
+        println!("Password of config #{} changed.", index);
+    } else {
+        println!(
+            "Password changed. Please set ENCRYPTED_WITHDRAWAL_PRIVATE_KEY to: {}",
+            hex::encode(config.encrypted_withdrawal_private_key.as_ref().unwrap())
+        );
+    }
+    config.export_to_env()?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
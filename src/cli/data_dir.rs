@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::ensure;
+
+use crate::{
+    cli::console::print_status,
+    utils::{
+        file::{
+            get_data_path, get_default_data_path, get_portable_data_path, move_dir, set_data_path,
+            DATA_DIR_ENV,
+        },
+        instance_lock::InstanceLock,
+    },
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DataDirOptions {
+    /// Directory of the configs, logs, job journal and caches, instead of ~/.mining-cli. Can
+    /// also be set with MINING_CLI_DATA_DIR
+    #[arg(long, conflicts_with = "portable")]
+    pub data_dir: Option<PathBuf>,
+
+    /// Keep the data in mining-cli-data next to the executable. Used without this flag once
+    /// that directory exists
+    #[arg(long)]
+    pub portable: bool,
+
+    /// Data directory moved by migrate-data-dir mode, ~/.mining-cli if not set
+    #[arg(long)]
+    pub migrate_from: Option<PathBuf>,
+}
+
+impl DataDirOptions {
+    /// Fixes the data directory for the rest of the process, so that it doesn't change when
+    /// the environment does.
+    pub fn apply(&self) -> anyhow::Result<()> {
+        let data_path = if let Some(data_dir) = &self.data_dir {
+            std::path::absolute(data_dir)?
+        } else if self.portable {
+            get_portable_data_path()?
+        } else {
+            get_data_path()?
+        };
+        set_data_path(data_path)
+    }
+}
+
+/// Moves the data directory `--migrate-from` to the data directory chosen by the other flags.
+/// Runs before anything is written to either directory.
+pub fn migrate_data_dir_mode(options: &DataDirOptions) -> anyhow::Result<()> {
+    let from = match &options.migrate_from {
+        Some(from) => std::path::absolute(from)?,
+        None => get_default_data_path()?,
+    };
+    let to = get_data_path()?;
+    ensure!(from.is_dir(), "Data directory {:?} does not exist", from);
+    ensure!(
+        from != to,
+        "{:?} is already the data directory. Choose the new one with --data-dir, --portable or {}",
+        from,
+        DATA_DIR_ENV
+    );
+    ensure!(
+        !to.starts_with(&from),
+        "Cannot move {:?} into itself at {:?}",
+        from,
+        to
+    );
+    ensure!(
+        !to.exists() || is_empty_dir(&to)?,
+        "{:?} already exists and is not empty",
+        to
+    );
+    // fails if an instance is running on the old directory
+    let lock_path = from.join("mining-cli.lock");
+    let resource = format!("the data directory {:?}", from);
+    let lock = InstanceLock::acquire(&lock_path, &resource)?;
+    // a directory with an open file can't be moved on Windows, so the lock is released there and
+    // taken again once a copy is complete, before the old directory is removed. Elsewhere it is
+    // held through the move and moves along with the directory
+    #[cfg(windows)]
+    drop(lock);
+    if to.exists() {
+        std::fs::remove_dir(&to)?;
+    }
+    print_status(format!("Moving {:?} to {:?}", from, to));
+    move_dir(&from, &to, || {
+        if cfg!(windows) {
+            drop(InstanceLock::acquire(&lock_path, &resource)?);
+        }
+        Ok(())
+    })?;
+    #[cfg(not(windows))]
+    drop(lock);
+    print_status("Data directory moved");
+    if options.data_dir.is_some() {
+        println!(
+            "Pass --data-dir {:?} or set {} on every run to use it.",
+            to, DATA_DIR_ENV
+        );
+    }
+    Ok(())
+}
+
+fn is_empty_dir(path: &Path) -> anyhow::Result<bool> {
+    Ok(std::fs::read_dir(path)?.next().is_none())
+}
@@ -1,16 +1,17 @@
 use alloy::{
-    primitives::{Address, B256},
+    primitives::{Address, B256, U256},
     providers::Provider as _,
 };
-use dialoguer::{Confirm, Select};
+use dialoguer::{Confirm, Input, MultiSelect, Password};
 
 use crate::{
     external_api::contracts::utils::NormalProvider,
     services::{
-        balance_transfer::balance_transfer,
+        balance_transfer::{plan_sweep, sweep},
         utils::{is_address_used, pretty_format_u256},
     },
     state::key::Key,
+    utils::keystore::{default_keystore_dir, export_keystore},
 };
 
 pub async fn export_deposit_accounts(
@@ -33,51 +34,57 @@ pub async fn export_deposit_accounts(
     );
     println!("Private Key: {:?}", key.deposit_private_key);
 
+    let do_export = Confirm::new()
+        .with_prompt("Do you want to export these keys as keystore files?")
+        .default(false)
+        .interact()?;
+    if do_export {
+        keystore_export_instruction(withdrawal_private_key, 1)?;
+    }
+
     let do_transfer = Confirm::new()
         .with_prompt("Do you want to make transfers from this account?")
         .default(true)
         .interact()?;
     if do_transfer {
-        transfer_instruction(provider, withdrawal_private_key).await?;
+        sweep_instruction(provider, withdrawal_private_key, 1).await?;
     }
     Ok(())
 }
 
-async fn transfer_instruction(
-    provider: &NormalProvider,
+/// Writes the withdrawal key and the deposit keys `#0..up_to_key_number` as V3 keystore files
+fn keystore_export_instruction(
     withdrawal_private_key: B256,
+    up_to_key_number: u64,
 ) -> anyhow::Result<()> {
-    let key = Key::new(withdrawal_private_key, 0);
-    let to_address: Address = dialoguer::Input::<String>::new()
-        .with_prompt("Enter the address to transfer to")
-        .validate_with(|input: &String| {
-            let result: Result<Address, _> = input.parse();
-            match result {
-                Ok(to_address) => {
-                    if to_address == key.withdrawal_address {
-                        return Err("Cannot transfer to the withdrawal address".to_string());
-                    }
-                    // The deposit address is encrypted, so it is theoretically impossible to check for duplicates.
-                    Ok(())
-                }
-                Err(_) => Err("Invalid address".to_string()),
-            }
-        })
-        .interact()?
-        .parse()
-        .unwrap(); // safe to unwrap because of the validation
-    let is_ok = Confirm::new()
-        .with_prompt(format!(
-            "Are you sure to transfer all ETH from {:?} to {:?}",
-            key.deposit_address, to_address,
-        ))
-        .report(false)
-        .default(true)
+    let default_dir = default_keystore_dir()?;
+    let dir: String = Input::new()
+        .with_prompt("Directory to export keystore files to")
+        .default(default_dir.to_string_lossy().to_string())
         .interact()?;
-    if !is_ok {
-        return Ok(());
-    } else {
-        balance_transfer(provider, key.deposit_private_key, to_address).await?;
+    let dir = std::path::PathBuf::from(dir);
+    let password = Password::new()
+        .with_prompt("Password to encrypt keystore files")
+        .with_confirmation("Confirm password", "Passwords do not match")
+        .interact()?;
+
+    let withdrawal_key = Key::new(withdrawal_private_key, 0);
+    let path = export_keystore(
+        &dir,
+        &format!("withdrawal-{:?}.json", withdrawal_key.withdrawal_address),
+        withdrawal_private_key,
+        &password,
+    )?;
+    println!("Withdrawal keystore exported to {:?}", path);
+    for key_number in 0..up_to_key_number {
+        let key = Key::new(withdrawal_private_key, key_number);
+        let path = export_keystore(
+            &dir,
+            &format!("deposit-{}-{:?}.json", key_number, key.deposit_address),
+            key.deposit_private_key,
+            &password,
+        )?;
+        println!("Deposit #{} keystore exported to {:?}", key_number, path);
     }
     Ok(())
 }
@@ -108,79 +115,128 @@ pub async fn legacy_export_deposit_accounts(
         key_number += 1;
     }
 
+    let do_export = Confirm::new()
+        .with_prompt("Do you want to export these keys as keystore files?")
+        .default(false)
+        .interact()?;
+    if do_export {
+        keystore_export_instruction(withdrawal_private_key, key_number)?;
+    }
+
     let do_transfer = Confirm::new()
         .with_prompt("Do you want to make transfers from these accounts?")
         .default(true)
         .interact()?;
 
     if do_transfer {
-        legacy_transfer_instruction(provider, withdrawal_private_key, key_number).await?;
+        sweep_instruction(provider, withdrawal_private_key, key_number).await?;
     }
     Ok(())
 }
 
-async fn legacy_transfer_instruction(
+/// Transfers all ETH from the selected deposit accounts `#0..up_to_key_number`,
+/// showing a per-account preview of fees and amounts before confirming.
+async fn sweep_instruction(
     provider: &NormalProvider,
     withdrawal_private_key: B256,
     up_to_key_number: u64,
 ) -> anyhow::Result<()> {
-    let deposit_addresses = (0..up_to_key_number)
-        .map(|i| Key::new(withdrawal_private_key, i).deposit_address)
+    let keys = (0..up_to_key_number)
+        .map(|i| Key::new(withdrawal_private_key, i))
+        .collect::<Vec<Key>>();
+    let deposit_addresses = keys
+        .iter()
+        .map(|key| key.deposit_address)
         .collect::<Vec<Address>>();
+    let withdrawal_address = Key::new(withdrawal_private_key, 0).withdrawal_address;
 
-    loop {
-        let items = (0..up_to_key_number)
-            .map(|i| {
-                let key = Key::new(withdrawal_private_key, i);
-                format!("#{} {:?}", i, key.deposit_address)
-            })
+    let selected = if keys.len() == 1 {
+        vec![0]
+    } else {
+        let items = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| format!("#{} {:?}", i, key.deposit_address))
             .collect::<Vec<String>>();
-        let selected = Select::new()
-            .with_prompt("Select a deposit account to transfer from")
+        MultiSelect::new()
+            .with_prompt("Select deposit accounts to transfer from (space to toggle)")
             .items(&items)
-            .default(0)
-            .interact()? as u64;
-        let key = Key::new(withdrawal_private_key, selected);
-        let to_address: Address = dialoguer::Input::<String>::new()
-            .with_prompt("Enter the address to transfer to")
-            .validate_with(|input: &String| {
-                let result: Result<Address, _> = input.parse();
-                match result {
-                    Ok(to_address) => {
-                        if to_address == key.withdrawal_address {
-                            return Err("Cannot transfer to the withdrawal address".to_string());
-                        }
-                        if deposit_addresses.contains(&to_address) {
-                            return Err("Cannot transfer to a deposit address".to_string());
-                        }
-                        Ok(())
+            .defaults(&vec![true; items.len()])
+            .interact()?
+    };
+    if selected.is_empty() {
+        return Ok(());
+    }
+
+    let to_address: Address = Input::<String>::new()
+        .with_prompt("Enter the address to transfer to")
+        .validate_with(|input: &String| {
+            let result: Result<Address, _> = input.parse();
+            match result {
+                Ok(to_address) => {
+                    if to_address == withdrawal_address {
+                        return Err("Cannot transfer to the withdrawal address".to_string());
+                    }
+                    if deposit_addresses.contains(&to_address) {
+                        return Err("Cannot transfer to a deposit address".to_string());
                     }
-                    Err(_) => Err("Invalid address".to_string()),
+                    Ok(())
                 }
-            })
-            .interact()?
-            .parse()
-            .unwrap(); // safe to unwrap because of the validation
-        let is_ok = Confirm::new()
-            .with_prompt(format!(
-                "Are you sure to transfer all ETH from #{} {:?} to {:?}",
-                selected, key.deposit_address, to_address,
-            ))
-            .report(false)
-            .default(true)
-            .interact()?;
-        if !is_ok {
-            continue;
+                Err(_) => Err("Invalid address".to_string()),
+            }
+        })
+        .interact()?
+        .parse()
+        .unwrap(); // safe to unwrap because of the validation
+
+    let mut plans = Vec::new();
+    let mut total_amount = U256::default();
+    for &i in &selected {
+        let plan = plan_sweep(provider, keys[i].deposit_private_key, to_address).await?;
+        println!(
+            "#{} {:?}: balance {} ETH, max fee {} ETH (L1 fee {} ETH), transfer {} ETH",
+            i,
+            plan.from,
+            pretty_format_u256(plan.balance),
+            pretty_format_u256(plan.max_fee()),
+            pretty_format_u256(plan.l1_fee),
+            pretty_format_u256(plan.amount),
+        );
+        if plan.is_sendable() {
+            total_amount += plan.amount;
+            plans.push((i, plan));
         } else {
-            balance_transfer(provider, key.deposit_private_key, to_address).await?;
-        }
-        let do_more = Confirm::new()
-            .with_prompt("Do you want to make more transfers?")
-            .default(false)
-            .interact()?;
-        if !do_more {
-            break;
+            println!(
+                "#{} is skipped because the balance does not cover the fee",
+                i
+            );
         }
     }
+    if plans.is_empty() {
+        println!("No account has enough balance to transfer.");
+        return Ok(());
+    }
+    let is_ok = Confirm::new()
+        .with_prompt(format!(
+            "Are you sure to transfer {} ETH in total from {} account(s) to {:?}",
+            pretty_format_u256(total_amount),
+            plans.len(),
+            to_address,
+        ))
+        .report(false)
+        .default(true)
+        .interact()?;
+    if !is_ok {
+        return Ok(());
+    }
+    for (i, plan) in plans {
+        sweep(provider, keys[i].deposit_private_key, &plan).await?;
+        println!(
+            "Transferred {} ETH from #{} {:?}",
+            pretty_format_u256(plan.amount),
+            i,
+            plan.from
+        );
+    }
     Ok(())
 }
@@ -0,0 +1,125 @@
+use chrono::{Local, TimeZone as _};
+use dialoguer::Select;
+
+use crate::{
+    services::utils::pretty_format_u256,
+    utils::tx_ledger::{TxLedger, TxLedgerEntry, TxStatus},
+};
+
+const STATUS_FILTERS: [Option<TxStatus>; 5] = [
+    None,
+    Some(TxStatus::Pending),
+    Some(TxStatus::Confirmed),
+    Some(TxStatus::Failed),
+    Some(TxStatus::Dropped),
+];
+
+/// Lists the transactions recorded in the ledger. In interactive mode, the list can be
+/// filtered by status and each entry can be opened for details.
+pub fn history(is_interactive: bool) -> anyhow::Result<()> {
+    let ledger = TxLedger::load()?;
+    if ledger.entries.is_empty() {
+        println!("No transactions recorded yet.");
+        return Ok(());
+    }
+    if !is_interactive {
+        for entry in ledger.entries.iter().rev() {
+            println!("{}", format_summary(entry));
+        }
+        return Ok(());
+    }
+
+    let filter_items = STATUS_FILTERS
+        .iter()
+        .map(|filter| match filter {
+            Some(status) => status.to_string(),
+            None => "all".to_string(),
+        })
+        .collect::<Vec<String>>();
+    let filter = Select::new()
+        .with_prompt("Filter by status")
+        .items(&filter_items)
+        .default(0)
+        .interact()?;
+    let entries = ledger
+        .entries
+        .iter()
+        .rev()
+        .filter(|entry| STATUS_FILTERS[filter].map_or(true, |status| entry.status == status))
+        .collect::<Vec<&TxLedgerEntry>>();
+    if entries.is_empty() {
+        println!("No transactions with this status.");
+        return Ok(());
+    }
+
+    let mut items = entries
+        .iter()
+        .map(|entry| format_summary(entry))
+        .collect::<Vec<String>>();
+    items.push("Back".to_string());
+    loop {
+        let selected = Select::new()
+            .with_prompt("Select a transaction to show details")
+            .items(&items)
+            .default(0)
+            .interact()?;
+        if selected == entries.len() {
+            return Ok(());
+        }
+        print_details(entries[selected]);
+    }
+}
+
+fn format_time(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn format_summary(entry: &TxLedgerEntry) -> String {
+    format!(
+        "#{} {} {} {} (nonce {}) {:?}",
+        entry.id,
+        format_time(entry.created_at),
+        entry.name,
+        entry.status,
+        entry.nonce,
+        entry
+            .mined_tx_hash
+            .or(entry.tx_hashes.last().copied())
+            .unwrap_or_default()
+    )
+}
+
+fn print_details(entry: &TxLedgerEntry) {
+    println!();
+    println!("Name: {}", entry.name);
+    println!("Status: {}", entry.status);
+    println!("From: {:?}", entry.from);
+    println!("Nonce: {}", entry.nonce);
+    if let Some(deposit_id) = entry.deposit_id {
+        println!("Deposit ID: {}", deposit_id);
+    }
+    println!("Value: {} ETH", pretty_format_u256(entry.value));
+    println!("Gas limit: {}", entry.gas_limit);
+    println!(
+        "Max fee per gas: {} wei, max priority fee per gas: {} wei",
+        entry.max_fee_per_gas, entry.max_priority_fee_per_gas
+    );
+    if let Some(fee_paid) = entry.fee_paid {
+        println!("Fee paid: {} ETH", pretty_format_u256(fee_paid));
+    }
+    for (i, tx_hash) in entry.tx_hashes.iter().enumerate() {
+        let mark = if Some(*tx_hash) == entry.mined_tx_hash {
+            " (mined)"
+        } else {
+            ""
+        };
+        println!("Tx hash #{}: {:?}{}", i, tx_hash, mark);
+    }
+    println!("Created at: {}", format_time(entry.created_at));
+    println!("Updated at: {}", format_time(entry.updated_at));
+    println!();
+}
@@ -2,20 +2,31 @@ use std::io::{self, Read as _};
 
 use ::console::{style, Term};
 use alloy::primitives::B256;
-use configure::recover_withdrawal_private_key;
+use bundle::BundleOptions;
+use configure::{change_password, recover_withdrawal_private_key};
 use console::clear_console;
 use mode_selection::legacy_select_mode;
 use term_of_use::make_agreement;
 
 use crate::{
-    external_api::contracts::utils::get_address_from_private_key,
-    services::{claim_loop, exit_loop, legacy_claim_loop, mining_loop},
-    state::{mode::RunMode, state::State},
+    external_api::{
+        contracts::{
+            handlers::reconcile_pending_transactions, utils::get_address_from_private_key,
+        },
+        intmax::plonky2_prover::PROVER_TOKEN_ENV,
+    },
+    services::{
+        claim_loop, exit_loop, legacy_claim_loop, mining_loop, prover_worker::run_prover_worker,
+    },
+    state::{mode::RunMode, prover::ProverCircuit, state::State},
     utils::{
         cache::clear_github_cache,
+        config::Settings,
         env_config::EnvConfig,
         env_validation::validate_env_config,
+        instance_lock::lock_withdrawal_address,
         network::{get_network, is_legacy, Network},
+        shutdown::{is_shutdown_requested, listen_for_ctrl_c},
         update,
     },
 };
@@ -23,29 +34,48 @@ use crate::{
 pub mod accounts_status;
 pub mod availability;
 pub mod balance_validation;
+pub mod bundle;
 pub mod configure;
 pub mod console;
+pub mod data_dir;
 pub mod export_deposit_accounts;
+pub mod history;
 pub mod interactive;
 pub mod mode_selection;
+pub mod settings;
 pub mod term_of_use;
 
-pub async fn run(mode: Option<RunMode>) -> anyhow::Result<()> {
+pub async fn run(mode: Option<RunMode>, bundle_options: &BundleOptions) -> anyhow::Result<()> {
     make_agreement()?;
 
     let is_interactive = mode.is_none();
 
+    // proving needs neither keys nor RPC access
+    if mode == Some(RunMode::ProveBundle) {
+        return bundle::prove_bundle_mode(bundle_options).await;
+    }
+    if mode == Some(RunMode::ProverWorker) {
+        return prover_worker().await;
+    }
+
     if is_interactive {
         interactive::interactive().await?;
     }
 
-    let config = EnvConfig::import_from_env()?;
-    let withdrawal_private_key = recover_withdrawal_private_key(&config)?;
+    let mut config = EnvConfig::import_from_env()?;
+    let withdrawal_private_key = recover_withdrawal_private_key(&mut config)?;
     if config.withdrawal_address != get_address_from_private_key(withdrawal_private_key) {
         anyhow::bail!("Withdrawal address does not match the address derived from the private key");
     }
     validate_env_config(&config).await?;
     config.export_to_env()?;
+    // held until the mode loop ends, so that instances with other data directories don't send
+    // transactions from the same keys
+    let _withdrawal_address_lock = if Settings::load()?.service.lock_withdrawal_address {
+        Some(lock_withdrawal_address(config.withdrawal_address)?)
+    } else {
+        None
+    };
 
     if is_legacy() {
         print_legacy_warning();
@@ -58,7 +88,12 @@ pub async fn run(mode: Option<RunMode>) -> anyhow::Result<()> {
         mode.unwrap()
     };
 
-    let mut state = State::new(&config.rpc_url);
+    // waits stop on Ctrl+C from here on, after the prompts that read the keys
+    listen_for_ctrl_c();
+    let mut state = State::new(&config.rpc_urls).await?;
+    warm_up_prover(&state, mode);
+    state.refresh_rpc_health().await?;
+    reconcile_pending_transactions(&state.provider).await?;
 
     // prints the status of the accounts if mutable mode
     if mode == RunMode::Mining || mode == RunMode::Claim || mode == RunMode::Exit {
@@ -72,6 +107,7 @@ pub async fn run(mode: Option<RunMode>) -> anyhow::Result<()> {
         &config,
         withdrawal_private_key,
         is_interactive,
+        bundle_options,
     )
     .await?;
     Ok(())
@@ -83,6 +119,7 @@ async fn mode_loop(
     config: &EnvConfig,
     withdrawal_private_key: B256,
     is_interactive: bool,
+    bundle_options: &BundleOptions,
 ) -> anyhow::Result<()> {
     loop {
         match mode {
@@ -98,9 +135,9 @@ async fn mode_loop(
             }
             RunMode::Claim => {
                 if is_legacy() {
-                    legacy_claim_loop(state, withdrawal_private_key).await?;
+                    legacy_claim_loop(state, withdrawal_private_key, config.forward_to).await?;
                 } else {
-                    claim_loop(state, withdrawal_private_key).await?;
+                    claim_loop(state, withdrawal_private_key, config.forward_to).await?;
                 }
                 press_enter_to_continue();
             }
@@ -124,6 +161,14 @@ async fn mode_loop(
                 }
                 press_enter_to_continue();
             }
+            RunMode::ChangePassword => {
+                change_password(config, withdrawal_private_key)?;
+                press_enter_to_continue();
+            }
+            RunMode::History => {
+                history::history(is_interactive)?;
+                press_enter_to_continue();
+            }
             RunMode::CheckUpdate => {
                 update::update()?;
                 press_enter_to_continue();
@@ -137,16 +182,50 @@ async fn mode_loop(
                 }
                 press_enter_to_continue();
             }
+            RunMode::GenerateBundle => {
+                bundle::generate_bundle_mode(state, withdrawal_private_key, bundle_options).await?;
+            }
+            RunMode::ProveBundle => {
+                bundle::prove_bundle_mode(bundle_options).await?;
+            }
+            RunMode::SubmitBundle => {
+                bundle::submit_bundle_mode(state, withdrawal_private_key, bundle_options).await?;
+            }
+            RunMode::ProverWorker => {
+                prover_worker().await?;
+            }
+            RunMode::MigrateDataDir => unreachable!("migrate-data-dir runs before set up"),
+            RunMode::Config => unreachable!("config runs before set up"),
         };
-        if !is_interactive {
+        if !is_interactive || is_shutdown_requested() {
             // if not in interactive mode, we only run once
             break;
         }
+        // the next mode may not prove, so free the circuits until they are needed again
+        state.prover.release_all();
         *mode = legacy_select_mode()?;
+        warm_up_prover(state, *mode);
     }
     Ok(())
 }
 
+async fn prover_worker() -> anyhow::Result<()> {
+    let listen_address = Settings::load()?.prover.worker_listen_address;
+    let token = std::env::var(PROVER_TOKEN_ENV).map_err(|_| {
+        anyhow::anyhow!("{} must be set to run the prover worker", PROVER_TOKEN_ENV)
+    })?;
+    run_prover_worker(&listen_address, token).await
+}
+
+// starts building the circuits of the modes that prove, while the trees are synced
+fn warm_up_prover(state: &State, mode: RunMode) {
+    match mode {
+        RunMode::Mining | RunMode::Exit => state.prover.warm_up(ProverCircuit::Withdrawal),
+        RunMode::Claim => state.prover.warm_up(ProverCircuit::Claim),
+        _ => {}
+    }
+}
+
 pub fn press_enter_to_continue() {
     println!("Press Enter to continue...");
     let mut buffer = [0; 1];
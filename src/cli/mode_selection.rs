@@ -20,6 +20,16 @@ pub fn legacy_select_mode() -> anyhow::Result<RunMode> {
             style("Export:").bold(),
             style("export deposit private keys").dim()
         ),
+        format!(
+            "{} {}",
+            style("Change Password:").bold(),
+            style("change the password of the encrypted private key").dim()
+        ),
+        format!(
+            "{} {}",
+            style("History:").bold(),
+            style("show transactions sent by this CLI").dim()
+        ),
         format!(
             "{} {}",
             style("Check Update:").bold(),
@@ -42,8 +52,10 @@ pub fn legacy_select_mode() -> anyhow::Result<RunMode> {
         0 => RunMode::Claim,
         1 => RunMode::Exit,
         2 => RunMode::Export,
-        3 => RunMode::CheckUpdate,
-        4 => RunMode::ClearCache,
+        3 => RunMode::ChangePassword,
+        4 => RunMode::History,
+        5 => RunMode::CheckUpdate,
+        6 => RunMode::ClearCache,
         _ => unreachable!(),
     };
     Ok(mode)
@@ -0,0 +1,57 @@
+use clap::ValueEnum;
+
+use crate::{
+    cli::console::print_warning,
+    utils::{
+        config::{effective_settings, env_overrides, user_config_path},
+        network::get_network,
+    },
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ConfigAction {
+    Show, // print the user config file, or the effective settings with --effective
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConfigOptions {
+    /// What to do in config mode
+    #[arg(value_enum, default_value = "show")]
+    pub action: ConfigAction,
+
+    /// Print the settings merged from the defaults, the user config file and the environment,
+    /// with the source of each value
+    #[arg(long)]
+    pub effective: bool,
+}
+
+pub fn config_mode(options: &ConfigOptions) -> anyhow::Result<()> {
+    let network = get_network();
+    match options.action {
+        ConfigAction::Show if options.effective => {
+            let settings = effective_settings(network)?;
+            let width = settings
+                .iter()
+                .map(|(key, _, _)| key.len())
+                .max()
+                .unwrap_or(0);
+            for (key, value, source) in &settings {
+                println!("{:width$} = {}  # {}", key, value, source, width = width);
+            }
+            for (key, _, name) in env_overrides() {
+                if !settings.iter().any(|(k, _, _)| *k == key) {
+                    print_warning(format!("{} does not match any setting", name));
+                }
+            }
+        }
+        ConfigAction::Show => {
+            let path = user_config_path(network);
+            println!("# {}", path.display());
+            match std::fs::read_to_string(&path) {
+                Ok(content) => print!("{}", content),
+                Err(_) => println!("# not created yet"),
+            }
+        }
+    }
+    Ok(())
+}
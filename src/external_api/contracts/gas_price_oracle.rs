@@ -0,0 +1,93 @@
+use super::{error::BlockchainError, utils::NormalProvider};
+use crate::utils::network::get_network;
+use alloy::{
+    consensus::{SignableTransaction as _, TxEip1559},
+    primitives::{address, Address, Bytes, TxKind, U256},
+    providers::Provider as _,
+    sol,
+};
+use rand::RngCore as _;
+
+sol!(
+    #[sol(rpc)]
+    GasPriceOracle,
+    "abi/GasPriceOracle.json",
+);
+
+/// Address of the `GasPriceOracle` predeploy on OP-stack chains such as Base
+pub const GAS_PRICE_ORACLE_ADDRESS: Address =
+    address!("0x420000000000000000000000000000000000000F");
+
+// The L1 data fee follows the L1 base fee, which may rise between estimation and inclusion
+const L1_FEE_MARGIN_PERCENTAGE: u64 = 20;
+
+pub fn add_l1_fee_margin(l1_fee: U256) -> U256 {
+    l1_fee * U256::from(100 + L1_FEE_MARGIN_PERCENTAGE) / U256::from(100)
+}
+
+#[derive(Debug, Clone)]
+pub struct GasPriceOracleContract {
+    pub provider: NormalProvider,
+    pub address: Address,
+}
+
+impl GasPriceOracleContract {
+    pub fn new(provider: NormalProvider) -> Self {
+        Self {
+            provider,
+            address: GAS_PRICE_ORACLE_ADDRESS,
+        }
+    }
+
+    /// Returns the L1 data fee charged for a transaction with the given unsigned RLP encoding
+    pub async fn get_l1_fee(&self, data: Vec<u8>) -> Result<U256, BlockchainError> {
+        let oracle = GasPriceOracle::new(self.address, self.provider.clone());
+        let fee = oracle.getL1Fee(data.into()).call().await?;
+        Ok(fee)
+    }
+
+    /// Returns the L1 data fee of the given transaction
+    pub async fn get_l1_fee_of_tx(&self, tx: &TxEip1559) -> Result<U256, BlockchainError> {
+        self.get_l1_fee(tx.encoded_for_signing()).await
+    }
+
+    /// Estimates the L1 data fee of calling `to` with `input`, with a margin.
+    /// Returns zero on chains that are not OP-stack.
+    pub async fn estimate_l1_fee(
+        &self,
+        to: Address,
+        value: U256,
+        input: Bytes,
+    ) -> Result<U256, BlockchainError> {
+        if !get_network().is_op_stack() {
+            return Ok(U256::ZERO);
+        }
+        // Nonce and fee fields only change the encoded size by a few bytes, so upper bounds are used
+        let tx = TxEip1559 {
+            chain_id: self.provider.get_chain_id().await?,
+            nonce: u32::MAX as u64,
+            gas_limit: u32::MAX as u64,
+            max_fee_per_gas: u64::MAX as u128,
+            max_priority_fee_per_gas: u64::MAX as u128,
+            to: TxKind::Call(to),
+            value,
+            access_list: Default::default(),
+            input,
+        };
+        let l1_fee = self.get_l1_fee_of_tx(&tx).await?;
+        Ok(add_l1_fee_margin(l1_fee))
+    }
+
+    /// Estimates the L1 data fee of a transaction whose calldata is `calldata_size` bytes, for
+    /// balance requirements before the calldata is known. Random bytes are used as they do not
+    /// compress, which gives an upper bound.
+    pub async fn estimate_l1_fee_for_calldata_size(
+        &self,
+        to: Address,
+        calldata_size: usize,
+    ) -> Result<U256, BlockchainError> {
+        let mut input = vec![0u8; calldata_size];
+        rand::thread_rng().fill_bytes(&mut input);
+        self.estimate_l1_fee(to, U256::ZERO, input.into()).await
+    }
+}
@@ -1,13 +1,18 @@
-use crate::{cli::console::print_status, services::utils::insufficient_balance_instruction};
+use crate::{
+    cli::console::{print_status, print_warning},
+    services::utils::insufficient_balance_instruction,
+    utils::tx_ledger::{BroadcastRecord, TxLedger, TxStatus},
+};
 
 use super::{
     error::BlockchainError,
-    utils::{NormalProvider, ProviderWithSigner},
+    gas_price_oracle::GasPriceOracleContract,
+    utils::{get_next_base_fee, wait_for_new_block, NormalProvider, ProviderWithSigner},
 };
 use alloy::{
     consensus::{Transaction as _, TxEip1559},
-    primitives::{TxHash, U256},
-    providers::{PendingTransactionError, Provider as _, WalletProvider},
+    primitives::{Address, TxHash, U256},
+    providers::{PendingTransactionError, Provider, WalletProvider},
     rpc::types::TransactionRequest,
 };
 use std::time::Duration;
@@ -15,6 +20,10 @@ use std::time::Duration;
 const TIMEOUT: Duration = Duration::from_secs(120);
 const MAX_GAS_BUMP_ATTEMPTS: u32 = 3;
 const GAS_BUMP_PERCENTAGE: u64 = 25; // Should be above 10 to avoid replacement transaction underpriced error
+const RECONCILE_POLL_INTERVAL_IN_SEC: u64 = 10;
+
+// value of a replacement sending what is left after its fees, given its max fee per gas
+type RemainderAt<'a> = &'a (dyn Fn(u128) -> U256 + Sync);
 
 pub async fn send_transaction_with_gas_bump(
     provider: &NormalProvider,
@@ -22,6 +31,45 @@ pub async fn send_transaction_with_gas_bump(
     tx_request: TransactionRequest,
     tx_name: &str,
     from_name: &str,
+    deposit_id: Option<u64>,
+) -> Result<TxHash, BlockchainError> {
+    send_with_gas_bump(
+        provider, signer, tx_request, tx_name, from_name, deposit_id, None,
+    )
+    .await
+}
+
+/// Sends a transaction of everything left after its fees, such as a balance sweep. Replacements
+/// follow the base fee instead of the estimated max fee and send `remainder_at` of their max fee
+/// per gas, so that they never cost more than the balance.
+pub async fn send_remainder_with_gas_bump(
+    provider: &NormalProvider,
+    signer: ProviderWithSigner,
+    tx_request: TransactionRequest,
+    tx_name: &str,
+    from_name: &str,
+    remainder_at: RemainderAt<'_>,
+) -> Result<TxHash, BlockchainError> {
+    send_with_gas_bump(
+        provider,
+        signer,
+        tx_request,
+        tx_name,
+        from_name,
+        None,
+        Some(remainder_at),
+    )
+    .await
+}
+
+async fn send_with_gas_bump(
+    provider: &NormalProvider,
+    signer: ProviderWithSigner,
+    tx_request: TransactionRequest,
+    tx_name: &str,
+    from_name: &str,
+    deposit_id: Option<u64>,
+    remainder_at: Option<RemainderAt<'_>>,
 ) -> Result<TxHash, BlockchainError> {
     let sendable_tx = signer.fill(tx_request.clone()).await?;
     let tx_envelope = sendable_tx.try_into_envelope().unwrap();
@@ -37,14 +85,27 @@ pub async fn send_transaction_with_gas_bump(
         tx_eip1559.max_fee_per_gas,
         tx_eip1559.max_priority_fee_per_gas
     );
-    match signer
-        .send_tx_envelope(tx_envelope)
-        .await?
-        .with_timeout(Some(TIMEOUT))
-        .watch()
-        .await
-    {
+    let ledger_id = ledger_record_broadcast(BroadcastRecord {
+        name: tx_name.to_string(),
+        from: signer.default_signer_address(),
+        nonce: tx_eip1559.nonce,
+        tx_hash,
+        gas_limit: tx_eip1559.gas_limit,
+        max_fee_per_gas: tx_eip1559.max_fee_per_gas,
+        max_priority_fee_per_gas: tx_eip1559.max_priority_fee_per_gas,
+        value: tx_eip1559.value,
+        deposit_id,
+    });
+    let pending_tx = match signer.send_tx_envelope(tx_envelope).await {
+        Ok(pending_tx) => pending_tx,
+        Err(e) => {
+            ledger_record_unsent(ledger_id);
+            return Err(e.into());
+        }
+    };
+    match pending_tx.with_timeout(Some(TIMEOUT)).watch().await {
         Ok(tx_hash) => {
+            ledger_record_settled(provider, ledger_id, tx_hash).await;
             log::info!(
                 "Transaction sent: {:?} with tx hash: {:?}",
                 tx_name.to_string(),
@@ -59,7 +120,15 @@ pub async fn send_transaction_with_gas_bump(
         }
         Err(PendingTransactionError::TxWatcher(_)) => {
             // timeout, so we need to bump the gas
-            let tx_hash = resend_tx_with_gas_bump(signer, tx_hash, &tx_eip1559, tx_name).await?;
+            let tx_hash = resend_tx_with_gas_bump(
+                signer,
+                tx_hash,
+                &tx_eip1559,
+                tx_name,
+                ledger_id,
+                remainder_at,
+            )
+            .await?;
             print_status(format!(
                 "{} transaction sent successfully with hash: {:?}",
                 tx_name.to_string(),
@@ -72,7 +141,15 @@ pub async fn send_transaction_with_gas_bump(
                 let estimate_gas = provider.estimate_gas(tx_request.clone()).await?;
                 let gas_price = provider.get_gas_price().await?;
                 let value = tx_request.value.unwrap_or_default();
-                let necessary_balance = U256::from(estimate_gas) * U256::from(gas_price) + value;
+                let l1_fee = GasPriceOracleContract::new(provider.clone())
+                    .estimate_l1_fee(
+                        tx_eip1559.to().unwrap_or_default(),
+                        value,
+                        tx_eip1559.input.clone(),
+                    )
+                    .await?;
+                let necessary_balance =
+                    U256::from(estimate_gas) * U256::from(gas_price) + value + l1_fee;
                 insufficient_balance_instruction(
                     provider,
                     signer.default_signer_address(),
@@ -101,6 +178,8 @@ async fn resend_tx_with_gas_bump(
     initial_tx_hash: TxHash,
     tx_eip1559: &TxEip1559,
     tx_name: &str,
+    ledger_id: Option<u64>,
+    remainder_at: Option<RemainderAt<'_>>,
 ) -> Result<TxHash, BlockchainError> {
     log::info!("Resending transaction: {tx_name}");
     let mut pending_tx_hashes = vec![initial_tx_hash];
@@ -116,6 +195,7 @@ async fn resend_tx_with_gas_bump(
                     "Previous tx settled with hash: {:?}",
                     tx_receipt.transaction_hash
                 );
+                ledger_record_settled(&signer, ledger_id, tx_receipt.transaction_hash).await;
                 if tx_receipt.status() {
                     return Ok(tx_receipt.transaction_hash);
                 } else {
@@ -129,21 +209,41 @@ async fn resend_tx_with_gas_bump(
         // bump gas
         let fee_estimation = signer.estimate_eip1559_fees().await?;
 
-        let (new_max_priority_fee_per_gas, new_max_fee_per_gas) =
-            if fee_estimation.max_priority_fee_per_gas > current_tx.max_priority_fee_per_gas {
-                // use the estimated fee which is higher than the current fee
-                (
-                    fee_estimation.max_priority_fee_per_gas,
-                    fee_estimation.max_fee_per_gas,
-                )
-            } else {
-                // bump the gas by a percentage
-                (
-                    current_tx.max_priority_fee_per_gas * (100 + GAS_BUMP_PERCENTAGE as u128) / 100,
-                    current_tx.max_fee_per_gas * (100 + GAS_BUMP_PERCENTAGE as u128) / 100,
-                )
-            };
+        let (new_max_priority_fee_per_gas, new_max_fee_per_gas) = if remainder_at.is_some() {
+            // follow the base fee closely, since every wei of max fee is kept back from the value
+            let priority_fee = fee_estimation.max_priority_fee_per_gas.max(
+                current_tx.max_priority_fee_per_gas * (100 + GAS_BUMP_PERCENTAGE as u128) / 100,
+            );
+            let max_fee = (get_next_base_fee(&signer).await? + priority_fee)
+                .max(current_tx.max_fee_per_gas * (100 + GAS_BUMP_PERCENTAGE as u128) / 100);
+            (priority_fee, max_fee)
+        } else if fee_estimation.max_priority_fee_per_gas > current_tx.max_priority_fee_per_gas {
+            // use the estimated fee which is higher than the current fee
+            (
+                fee_estimation.max_priority_fee_per_gas,
+                fee_estimation.max_fee_per_gas,
+            )
+        } else {
+            // bump the gas by a percentage
+            (
+                current_tx.max_priority_fee_per_gas * (100 + GAS_BUMP_PERCENTAGE as u128) / 100,
+                current_tx.max_fee_per_gas * (100 + GAS_BUMP_PERCENTAGE as u128) / 100,
+            )
+        };
 
+        let value = match remainder_at {
+            Some(remainder_at) => {
+                let value = remainder_at(new_max_fee_per_gas);
+                if value.is_zero() {
+                    return Err(BlockchainError::InsufficientFunds(format!(
+                        "balance does not cover the bumped fee of {}",
+                        tx_name
+                    )));
+                }
+                value
+            }
+            None => current_tx.value,
+        };
         let new_tx_request = TransactionRequest::default()
             .max_priority_fee_per_gas(new_max_priority_fee_per_gas)
             .max_fee_per_gas(new_max_fee_per_gas)
@@ -152,7 +252,7 @@ async fn resend_tx_with_gas_bump(
             .nonce(current_tx.nonce)
             .gas_limit(current_tx.gas_limit)
             .input(current_tx.input.into())
-            .value(current_tx.value);
+            .value(value);
 
         // send the new transaction
         let sendable_tx = signer.fill(new_tx_request).await?;
@@ -161,14 +261,38 @@ async fn resend_tx_with_gas_bump(
             "Sending bumped gas tx {tx_name} attempt: {attempt} with new max_fee_per_gas: {new_max_fee_per_gas:?}, new max_priority_fee_per_gas: {new_max_priority_fee_per_gas:?}",
         );
 
-        match signer
-            .send_tx_envelope(tx_envelope.clone())
-            .await?
-            .with_timeout(Some(TIMEOUT))
-            .watch()
-            .await
-        {
+        let replacement_hash = *tx_envelope.tx_hash();
+        if let Some(id) = ledger_id {
+            if let Err(e) = TxLedger::record_replacement(
+                id,
+                replacement_hash,
+                new_max_fee_per_gas,
+                new_max_priority_fee_per_gas,
+            ) {
+                log::warn!("Failed to record replacement in the tx ledger: {}", e);
+            }
+        }
+        let pending_tx = match signer.send_tx_envelope(tx_envelope.clone()).await {
+            Ok(pending_tx) => pending_tx,
+            Err(e) => {
+                // the transactions sent before may still be mined, so only the replacement is
+                // removed
+                if let Some(id) = ledger_id {
+                    if let Err(e) = TxLedger::remove_replacement(
+                        id,
+                        replacement_hash,
+                        current_tx.max_fee_per_gas,
+                        current_tx.max_priority_fee_per_gas,
+                    ) {
+                        log::warn!("Failed to remove replacement from the tx ledger: {}", e);
+                    }
+                }
+                return Err(e.into());
+            }
+        };
+        match pending_tx.with_timeout(Some(TIMEOUT)).watch().await {
             Ok(tx_hash) => {
+                ledger_record_settled(&signer, ledger_id, tx_hash).await;
                 println!("Transaction sent: {tx_hash:?}");
                 return Ok(tx_hash);
             }
@@ -188,3 +312,160 @@ async fn resend_tx_with_gas_bump(
     }
     Err(BlockchainError::MaxTxRetriesReached)
 }
+
+// The ledger is bookkeeping only, so failures to write it are logged and never abort a transaction.
+fn ledger_record_broadcast(record: BroadcastRecord) -> Option<u64> {
+    match TxLedger::record_broadcast(record) {
+        Ok(id) => Some(id),
+        Err(e) => {
+            log::warn!("Failed to record transaction in the tx ledger: {}", e);
+            None
+        }
+    }
+}
+
+fn ledger_record_unsent(ledger_id: Option<u64>) {
+    let Some(id) = ledger_id else {
+        return;
+    };
+    if let Err(e) = TxLedger::record_status(id, TxStatus::Dropped, None, None) {
+        log::warn!(
+            "Failed to record transaction status in the tx ledger: {}",
+            e
+        );
+    }
+}
+
+async fn ledger_record_settled<P: Provider>(provider: &P, ledger_id: Option<u64>, tx_hash: TxHash) {
+    let Some(id) = ledger_id else {
+        return;
+    };
+    let result = async {
+        if let Some(receipt) = provider.get_transaction_receipt(tx_hash).await? {
+            let status = if receipt.status() {
+                TxStatus::Confirmed
+            } else {
+                TxStatus::Failed
+            };
+            let fee = U256::from(receipt.gas_used) * U256::from(receipt.effective_gas_price);
+            TxLedger::record_status(id, status, Some(tx_hash), Some(fee))?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        log::warn!(
+            "Failed to record transaction status in the tx ledger: {}",
+            e
+        );
+    }
+}
+
+/// Settles the ledger entries left pending by a previous run.
+/// If some of our transactions are still in the mempool, waits for them together, up to one
+/// timeout overall, so that their nonces are not reused by new transactions.
+pub async fn reconcile_pending_transactions(provider: &NormalProvider) -> anyhow::Result<()> {
+    let mut pending_entries = match TxLedger::load() {
+        Ok(ledger) => ledger.pending_entries(),
+        Err(e) => {
+            // the ledger is bookkeeping only, so a broken one must not stop the CLI
+            print_warning(format!(
+                "Skipping the check of pending transactions: {:#}",
+                e
+            ));
+            return Ok(());
+        }
+    };
+    for entry in &pending_entries {
+        log::info!(
+            "Reconciling pending transaction {} (nonce {}) from {:?}",
+            entry.name,
+            entry.nonce,
+            entry.from
+        );
+    }
+    let deadline = std::time::Instant::now() + TIMEOUT;
+    loop {
+        let mut still_pending = Vec::new();
+        for entry in pending_entries {
+            if let Some(tx_hash) = find_mined_tx_hash(provider, &entry.tx_hashes).await? {
+                ledger_record_settled(provider, Some(entry.id), tx_hash).await;
+            } else if is_nonce_used(provider, entry.from, entry.nonce).await? {
+                // the nonce was consumed by a transaction that is not in the replacement chain
+                TxLedger::record_status(entry.id, TxStatus::Dropped, None, None)?;
+            } else if is_nonce_free(provider, entry.from, entry.nonce).await?
+                && !is_known_by_node(provider, &entry.tx_hashes).await?
+            {
+                // evicted from the mempool. A node that merely doesn't know the hash is not
+                // enough, since the fallback endpoints may see different mempools
+                TxLedger::record_status(entry.id, TxStatus::Dropped, None, None)?;
+            } else {
+                still_pending.push(entry);
+            }
+        }
+        pending_entries = still_pending;
+        if pending_entries.is_empty() {
+            return Ok(());
+        }
+        if std::time::Instant::now() > deadline {
+            for entry in &pending_entries {
+                print_warning(format!(
+                    "{} transaction from {:?} with nonce {} is still pending: {:?}",
+                    entry.name,
+                    entry.from,
+                    entry.nonce,
+                    entry.tx_hashes.last().unwrap()
+                ));
+            }
+            return Ok(());
+        }
+        print_status(format!(
+            "Waiting for {} pending transactions from the previous run...",
+            pending_entries.len()
+        ));
+        wait_for_new_block(provider, RECONCILE_POLL_INTERVAL_IN_SEC).await?;
+    }
+}
+
+async fn find_mined_tx_hash(
+    provider: &NormalProvider,
+    tx_hashes: &[TxHash],
+) -> Result<Option<TxHash>, BlockchainError> {
+    for tx_hash in tx_hashes.iter().rev() {
+        if provider.get_transaction_receipt(*tx_hash).await?.is_some() {
+            return Ok(Some(*tx_hash));
+        }
+    }
+    Ok(None)
+}
+
+async fn is_nonce_used(
+    provider: &NormalProvider,
+    from: Address,
+    nonce: u64,
+) -> Result<bool, BlockchainError> {
+    let latest_nonce = provider.get_transaction_count(from).latest().await?;
+    Ok(latest_nonce > nonce)
+}
+
+// no transaction with the nonce is waiting in the mempool either
+async fn is_nonce_free(
+    provider: &NormalProvider,
+    from: Address,
+    nonce: u64,
+) -> Result<bool, BlockchainError> {
+    let pending_nonce = provider.get_transaction_count(from).pending().await?;
+    Ok(pending_nonce <= nonce)
+}
+
+async fn is_known_by_node(
+    provider: &NormalProvider,
+    tx_hashes: &[TxHash],
+) -> Result<bool, BlockchainError> {
+    for tx_hash in tx_hashes {
+        if provider.get_transaction_by_hash(*tx_hash).await?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
@@ -101,6 +101,7 @@ impl Int1Contract {
             tx_request,
             "withdrawal",
             "withdrawer",
+            None,
         )
         .await?;
         Ok(tx_hash)
@@ -130,6 +131,7 @@ impl Int1Contract {
             tx_request,
             "cancel_deposit",
             "depositor",
+            Some(deposit_id),
         )
         .await?;
         Ok(tx_hash)
@@ -153,6 +155,7 @@ impl Int1Contract {
             tx_request,
             "deposit_native_token",
             "depositor",
+            None,
         )
         .await?;
         Ok(tx_hash)
@@ -95,6 +95,7 @@ impl MinterContract {
             tx_request,
             "claim_tokens",
             "claim address",
+            None,
         )
         .await?;
         Ok(())
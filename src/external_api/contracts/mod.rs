@@ -1,8 +1,10 @@
 pub mod convert;
 pub mod error;
 pub mod events;
+pub mod gas_price_oracle;
 pub mod handlers;
 pub mod int1;
 pub mod minter;
+pub mod rpc_health;
 pub mod token;
 pub mod utils;
@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+use alloy::providers::{Provider as _, ProviderBuilder};
+use futures::future::join_all;
+
+use super::utils::{connect_client, RpcEndpoint};
+use crate::utils::config::Settings;
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct RpcHealth {
+    pub url: String,
+    pub latency: Duration,
+    pub block_number: u64,
+}
+
+/// Checks the chain id of the endpoint and measures the latency of fetching the latest block number.
+pub async fn check_rpc_health(rpc_url: &str) -> anyhow::Result<RpcHealth> {
+    let result = tokio::time::timeout(HEALTH_CHECK_TIMEOUT, async {
+        let provider = ProviderBuilder::new().connect_client(connect_client(rpc_url).await?);
+        let chain_id = provider.get_chain_id().await?;
+        let expected_chain_id = Settings::load()?.blockchain.chain_id;
+        if chain_id != expected_chain_id {
+            anyhow::bail!(
+                "chain id {} does not match the expected chain id {}",
+                chain_id,
+                expected_chain_id
+            );
+        }
+        let start = Instant::now();
+        let block_number = provider.get_block_number().await?;
+        Ok(RpcHealth {
+            url: rpc_url.to_string(),
+            latency: start.elapsed(),
+            block_number,
+        })
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("health check timed out"))??;
+    Ok(result)
+}
+
+/// Returns the endpoints that respond with the expected chain id and are within
+/// `max_rpc_block_lag` blocks of the highest block seen, keeping the configured order.
+pub async fn select_healthy_rpc_urls(rpc_urls: &[String]) -> anyhow::Result<Vec<String>> {
+    if rpc_urls.len() == 1 {
+        // nothing to fall back to
+        return Ok(rpc_urls.to_vec());
+    }
+    let max_block_lag = Settings::load()?.blockchain.max_rpc_block_lag;
+    let results = join_all(rpc_urls.iter().map(|url| check_rpc_health(url))).await;
+    let mut healthy = Vec::new();
+    for (url, result) in rpc_urls.iter().zip(results) {
+        match result {
+            Ok(health) => {
+                log::info!(
+                    "RPC {} latency {:?} block {}",
+                    mask_url(url),
+                    health.latency,
+                    health.block_number
+                );
+                healthy.push(health);
+            }
+            Err(e) => log::warn!("RPC {} is unhealthy: {}", mask_url(url), e),
+        }
+    }
+    let highest_block = healthy
+        .iter()
+        .map(|health| health.block_number)
+        .max()
+        .ok_or_else(|| anyhow::anyhow!("None of the RPC URLs is reachable"))?;
+    let selected = healthy
+        .into_iter()
+        .filter(|health| {
+            let is_lagging = health.block_number + max_block_lag < highest_block;
+            if is_lagging {
+                log::warn!(
+                    "RPC {} lags behind by {} blocks and is not used",
+                    mask_url(&health.url),
+                    highest_block - health.block_number
+                );
+            }
+            !is_lagging
+        })
+        .map(|health| health.url)
+        .collect();
+    Ok(selected)
+}
+
+// API keys are part of the path of most RPC URLs, so only the host is logged
+fn mask_url(rpc_url: &str) -> String {
+    match RpcEndpoint::parse(rpc_url) {
+        Ok(RpcEndpoint::Http(url)) => url.host_str().unwrap_or_default().to_string(),
+        Ok(RpcEndpoint::Ws(url)) => reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default(),
+        Ok(RpcEndpoint::Ipc(path)) => path.to_string_lossy().to_string(),
+        Err(_) => "<invalid url>".to_string(),
+    }
+}
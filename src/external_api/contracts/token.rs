@@ -1,6 +1,10 @@
-use super::{error::BlockchainError, utils::NormalProvider};
+use super::{
+    error::BlockchainError,
+    handlers::send_transaction_with_gas_bump,
+    utils::{get_address_from_private_key, get_provider_with_signer, NormalProvider},
+};
 use alloy::{
-    primitives::{Address, U256},
+    primitives::{Address, TxHash, B256, U256},
     sol,
 };
 
@@ -9,6 +13,13 @@ sol!(
     ERC20,
     "abi/ERC20.json",
 );
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenMetadata {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
 pub struct TokenContract {
     pub provider: NormalProvider,
     pub address: Address,
@@ -24,4 +35,52 @@ impl TokenContract {
         let balance = contract.balanceOf(address).call().await?;
         Ok(balance)
     }
+
+    /// Returns the ERC-20 symbol and decimals, which are optional in the standard
+    pub async fn get_token_metadata(&self) -> Result<Option<TokenMetadata>, BlockchainError> {
+        let contract = ERC20::new(self.address, self.provider.clone());
+        let symbol = contract.symbol().call().await;
+        let decimals = contract.decimals().call().await;
+        match (symbol, decimals) {
+            (Ok(symbol), Ok(decimals)) => Ok(Some(TokenMetadata { symbol, decimals })),
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn transfer(
+        &self,
+        signer_private_key: B256,
+        to: Address,
+        amount: U256,
+    ) -> Result<TxHash, BlockchainError> {
+        let signer = get_provider_with_signer(&self.provider, signer_private_key);
+        let contract = ERC20::new(self.address, signer.clone());
+        let tx_request = contract.transfer(to, amount).into_transaction_request();
+        let tx_hash = send_transaction_with_gas_bump(
+            &self.provider,
+            signer,
+            tx_request,
+            "token_transfer",
+            "withdrawal address",
+            None,
+        )
+        .await?;
+        Ok(tx_hash)
+    }
+
+    /// Transfers the whole token balance of the signer.
+    /// Returns the transferred amount and the tx hash, or `None` if the balance is zero.
+    pub async fn transfer_all(
+        &self,
+        signer_private_key: B256,
+        to: Address,
+    ) -> Result<Option<(U256, TxHash)>, BlockchainError> {
+        let from = get_address_from_private_key(signer_private_key);
+        let balance = self.get_token_balance(from).await?;
+        if balance.is_zero() {
+            return Ok(None);
+        }
+        let tx_hash = self.transfer(signer_private_key, to, balance).await?;
+        Ok(Some((balance, tx_hash)))
+    }
 }
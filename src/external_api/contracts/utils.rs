@@ -1,5 +1,10 @@
 use super::error::BlockchainError;
+use crate::utils::{
+    shutdown::{stop_requested, Cancelled},
+    time::{sleep_for, sleep_until},
+};
 use alloy::{
+    eips::BlockNumberOrTag,
     network::EthereumWallet,
     primitives::{Address, TxHash, B256},
     providers::{
@@ -7,18 +12,25 @@ use alloy::{
             ChainIdFiller, FillProvider, GasFiller, JoinFill, NonceFiller, SimpleNonceManager,
             WalletFiller,
         },
-        Identity, Provider, ProviderBuilder,
+        Identity, IpcConnect, Provider, ProviderBuilder, WsConnect,
     },
+    pubsub::PubSubConnect as _,
     rpc::{client::RpcClient, types::Transaction},
     signers::local::PrivateKeySigner,
     transports::{
         http::Http,
         layers::{FallbackLayer, RetryBackoffLayer},
+        BoxTransport,
     },
 };
 use futures::{stream, StreamExt as _};
 use reqwest::Url;
-use std::{collections::HashMap, env};
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tower::ServiceBuilder;
 
 // Use simple nonce manager for the nonce filler because it's easier to handle nonce errors.
@@ -50,17 +62,92 @@ pub fn get_provider(rpc_urls: &str) -> Result<NormalProvider, BlockchainError> {
     Ok(provider)
 }
 
-pub fn get_provider_with_fallback(rpc_urls: &[String]) -> Result<NormalProvider, BlockchainError> {
-    let retry_layer = RetryBackoffLayer::new(5, 1000, 100);
-    let transports = rpc_urls
-        .iter()
-        .map(|url| {
-            let url: Url = url.parse().map_err(|e| {
-                BlockchainError::ParseError(format!("Failed to parse URL {url}: {e}"))
+/// RPC endpoint kinds accepted in the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcEndpoint {
+    Http(Url),
+    Ws(String),
+    Ipc(PathBuf),
+}
+
+impl RpcEndpoint {
+    /// Parses `http(s)://` and `ws(s)://` URLs, and IPC socket paths given either as
+    /// `ipc://<path>` or as an absolute path to an existing socket. Other strings are rejected, so
+    /// that a URL without a scheme is not taken for a socket.
+    pub fn parse(rpc_url: &str) -> Result<Self, BlockchainError> {
+        if rpc_url.starts_with("http://") || rpc_url.starts_with("https://") {
+            let url: Url = rpc_url.parse().map_err(|e| {
+                BlockchainError::ParseError(format!("Failed to parse URL {rpc_url}: {e}"))
             })?;
-            Ok(Http::new(url))
-        })
-        .collect::<Result<Vec<_>, BlockchainError>>()?;
+            Ok(Self::Http(url))
+        } else if rpc_url.starts_with("ws://") || rpc_url.starts_with("wss://") {
+            Ok(Self::Ws(rpc_url.to_string()))
+        } else if let Some(path) = rpc_url.strip_prefix("ipc://") {
+            Ok(Self::Ipc(PathBuf::from(path)))
+        } else if Path::new(rpc_url).is_absolute() && Path::new(rpc_url).exists() {
+            Ok(Self::Ipc(PathBuf::from(rpc_url)))
+        } else {
+            Err(BlockchainError::ParseError(format!(
+                "Unsupported RPC URL {rpc_url}: expected http(s)://, ws(s)://, ipc:// or the absolute path of an existing IPC socket"
+            )))
+        }
+    }
+
+    /// WebSocket and IPC connections support subscriptions.
+    pub fn is_pubsub(&self) -> bool {
+        !matches!(self, Self::Http(_))
+    }
+}
+
+async fn connect_transport(rpc_url: &str) -> Result<BoxTransport, BlockchainError> {
+    let transport = match RpcEndpoint::parse(rpc_url)? {
+        RpcEndpoint::Http(url) => BoxTransport::new(Http::new(url)),
+        RpcEndpoint::Ws(url) => BoxTransport::new(WsConnect::new(url).into_service().await?),
+        RpcEndpoint::Ipc(path) => BoxTransport::new(IpcConnect::new(path).into_service().await?),
+    };
+    Ok(transport)
+}
+
+/// Connects to a single endpoint of any kind without retry or fallback layers.
+pub async fn connect_client(rpc_url: &str) -> Result<RpcClient, BlockchainError> {
+    let transport = connect_transport(rpc_url).await?;
+    Ok(RpcClient::new(transport, false))
+}
+
+pub async fn get_provider_with_fallback(
+    rpc_urls: &[String],
+) -> Result<NormalProvider, BlockchainError> {
+    if let [rpc_url] = rpc_urls {
+        if RpcEndpoint::parse(rpc_url)?.is_pubsub() {
+            // Subscriptions are only available when the pub-sub frontend is the client's
+            // transport itself, so a single WebSocket or IPC endpoint is used without layers.
+            // The pub-sub service reconnects on its own.
+            let client = connect_client(rpc_url).await?;
+            let provider = ProviderBuilder::default()
+                .with_gas_estimation()
+                .with_simple_nonce_management()
+                .fetch_chain_id()
+                .connect_client(client);
+            return Ok(provider);
+        }
+    }
+    let retry_layer = RetryBackoffLayer::new(5, 1000, 100);
+    let mut transports = Vec::new();
+    let mut last_error = None;
+    for rpc_url in rpc_urls {
+        // an unreachable WebSocket or IPC endpoint is left out instead of failing the others
+        match connect_transport(rpc_url).await {
+            Ok(transport) => transports.push(transport),
+            Err(e) => {
+                log::warn!("Skipping RPC endpoint {}: {}", rpc_url, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    if transports.is_empty() {
+        return Err(last_error
+            .unwrap_or_else(|| BlockchainError::ParseError("No RPC URL configured".to_string())));
+    }
     let fallback_layer =
         FallbackLayer::default().with_active_transport_count(transports.len().try_into().unwrap());
     let transport = ServiceBuilder::new()
@@ -77,6 +164,47 @@ pub fn get_provider_with_fallback(rpc_urls: &[String]) -> Result<NormalProvider,
     Ok(provider)
 }
 
+/// Shortest wait on a new block, so that chains with sub-second blocks don't turn the polling
+/// loops into busy loops.
+const MIN_NEW_BLOCK_WAIT_IN_SEC: u64 = 5;
+
+/// Waits for the next block when the provider supports subscriptions, otherwise sleeps.
+/// Returns after `poll_interval_in_sec` at the latest in both cases, or `Cancelled` on shutdown.
+pub async fn wait_for_new_block(
+    provider: &NormalProvider,
+    poll_interval_in_sec: u64,
+) -> Result<(), Cancelled> {
+    if provider.client().pubsub_frontend().is_some() {
+        let start = chrono::Utc::now().timestamp() as u64;
+        match provider.subscribe_blocks().await {
+            Ok(mut subscription) => {
+                let timeout = Duration::from_secs(poll_interval_in_sec);
+                tokio::select! {
+                    _ = tokio::time::timeout(timeout, subscription.recv()) => {}
+                    _ = stop_requested() => return Err(Cancelled),
+                }
+                let min_wait = MIN_NEW_BLOCK_WAIT_IN_SEC.min(poll_interval_in_sec);
+                return sleep_until(start + min_wait).await;
+            }
+            Err(e) => log::warn!("Failed to subscribe to new blocks: {}", e),
+        }
+    }
+    sleep_for(poll_interval_in_sec).await
+}
+
+/// Base fee per gas of the next block, which `eth_feeHistory` returns after those of the
+/// requested blocks.
+pub async fn get_next_base_fee<P: Provider>(provider: &P) -> Result<u128, BlockchainError> {
+    let fee_history = provider
+        .get_fee_history(1, BlockNumberOrTag::Latest, &[])
+        .await?;
+    fee_history
+        .base_fee_per_gas
+        .last()
+        .copied()
+        .ok_or_else(|| BlockchainError::ParseError("Fee history without base fee".to_string()))
+}
+
 pub fn get_provider_with_signer(
     provider: &NormalProvider,
     private_key: B256,
@@ -166,3 +294,38 @@ async fn get_batch_transaction_inner(
 
     Ok((fetched_txs, failed_tx_hashes))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RpcEndpoint;
+
+    #[test]
+    fn test_parse_rpc_endpoint() {
+        assert!(matches!(
+            RpcEndpoint::parse("https://base-mainnet.g.alchemy.com/v2/key").unwrap(),
+            RpcEndpoint::Http(_)
+        ));
+        assert!(matches!(
+            RpcEndpoint::parse("wss://base-mainnet.g.alchemy.com/v2/key").unwrap(),
+            RpcEndpoint::Ws(_)
+        ));
+        assert_eq!(
+            RpcEndpoint::parse("ipc:///tmp/geth.ipc").unwrap(),
+            RpcEndpoint::Ipc("/tmp/geth.ipc".into())
+        );
+        // a bare path must be absolute and exist
+        let socket = std::env::temp_dir().join(format!("mining-cli-{}.ipc", std::process::id()));
+        std::fs::write(&socket, b"").unwrap();
+        assert_eq!(
+            RpcEndpoint::parse(socket.to_str().unwrap()).unwrap(),
+            RpcEndpoint::Ipc(socket.clone())
+        );
+        std::fs::remove_file(&socket).unwrap();
+        assert!(RpcEndpoint::parse(socket.to_str().unwrap()).is_err());
+        assert!(RpcEndpoint::parse("localhost:8545").is_err());
+        assert!(RpcEndpoint::parse("ftp://example.com").is_err());
+        assert!(!RpcEndpoint::parse("http://localhost:8545")
+            .unwrap()
+            .is_pubsub());
+    }
+}
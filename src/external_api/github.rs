@@ -1,3 +1,9 @@
+use std::{
+    fs::File,
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
 use chrono::NaiveDate;
 use log::info;
 use regex::Regex;
@@ -5,11 +11,7 @@ use reqwest::{self};
 use serde_json::Value;
 use sha3::Digest as _;
 
-use crate::utils::{
-    bin_parser::{BinDepositTree, BinEligibleTree},
-    config::Settings,
-    file::{create_file_with_content, get_data_path},
-};
+use crate::utils::{config::Settings, file::get_data_path, progress::download_progress_bar};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -21,11 +23,13 @@ pub enum Error {
     CacheError(String),
 }
 
+/// Cached bincode files of the new trees. They are parsed by the caller, which knows whether
+/// to keep the whole trees or only the tracked leaves.
 #[derive(Debug)]
 pub struct BinTrees {
-    pub bin_deposit_tree: Option<BinDepositTree>,
-    pub bin_short_term_eligible_tree: Option<BinEligibleTree>,
-    pub bin_long_term_eligible_tree: Option<BinEligibleTree>,
+    pub deposit_tree_file: Option<PathBuf>,
+    pub short_term_eligible_tree_file: Option<PathBuf>,
+    pub long_term_eligible_tree_file: Option<PathBuf>,
     pub latest_update: NaiveDate,
 }
 
@@ -67,61 +71,41 @@ pub async fn fetch_latest_tree_from_github(last_update: NaiveDate) -> Result<Bin
     let (latest_long_term_eligible_date, latest_long_term_eligible_file) =
         filter_file(&long_term_eligible_pattern, &file_list, last_update);
 
-    let bin_deposit_tree = if let Some(latest_deposit_file) = latest_deposit_file {
+    let deposit_tree_file = if let Some(latest_deposit_file) = latest_deposit_file {
         let download_url =
             latest_deposit_file["download_url"]
                 .as_str()
                 .ok_or(Error::DeserializeError(
                     "no download_url filed in github files".to_string(),
                 ))?;
-        let content = fetch_content(&client, download_url).await?;
-        let bin_deposit_tree: BinDepositTree = bincode::deserialize(&content).map_err(|e| {
-            Error::DeserializeError(format!("failed to deserialize deposit tree: {}", e))
-        })?;
-        Some(bin_deposit_tree)
+        Some(fetch_to_cache(&client, download_url, "deposit tree").await?)
     } else {
         None
     };
 
-    let bin_short_term_eligible_tree = if let Some(latest_short_term_eligible_file) =
-        latest_short_term_eligible_file
-    {
-        let download_url = latest_short_term_eligible_file["download_url"]
-            .as_str()
-            .ok_or(Error::DeserializeError(
-                "no download_url filed in github files".to_string(),
-            ))?;
-        let content = fetch_content(&client, download_url).await?;
-        let bin_eligible_tree: BinEligibleTree = bincode::deserialize(&content).map_err(|e| {
-            Error::DeserializeError(format!(
-                "failed to deserialize short term eligible tree: {}",
-                e
-            ))
-        })?;
-        Some(bin_eligible_tree)
-    } else {
-        None
-    };
-
-    let bin_long_term_eligible_tree = if let Some(latest_long_term_eligible_file) =
-        latest_long_term_eligible_file
-    {
-        let download_url = latest_long_term_eligible_file["download_url"]
-            .as_str()
-            .ok_or(Error::DeserializeError(
-                "no download_url filed in github files".to_string(),
-            ))?;
-        let content = fetch_content(&client, &download_url).await?;
-        let bin_eligible_tree: BinEligibleTree = bincode::deserialize(&content).map_err(|e| {
-            Error::DeserializeError(format!(
-                "failed to deserialize short term eligible tree: {}",
-                e
-            ))
-        })?;
-        Some(bin_eligible_tree)
-    } else {
-        None
-    };
+    let short_term_eligible_tree_file =
+        if let Some(latest_short_term_eligible_file) = latest_short_term_eligible_file {
+            let download_url = latest_short_term_eligible_file["download_url"]
+                .as_str()
+                .ok_or(Error::DeserializeError(
+                    "no download_url filed in github files".to_string(),
+                ))?;
+            Some(fetch_to_cache(&client, download_url, "short term eligible tree").await?)
+        } else {
+            None
+        };
+
+    let long_term_eligible_tree_file =
+        if let Some(latest_long_term_eligible_file) = latest_long_term_eligible_file {
+            let download_url = latest_long_term_eligible_file["download_url"]
+                .as_str()
+                .ok_or(Error::DeserializeError(
+                    "no download_url filed in github files".to_string(),
+                ))?;
+            Some(fetch_to_cache(&client, download_url, "long term eligible tree").await?)
+        } else {
+            None
+        };
     let latest_update = vec![
         latest_deposit_date,
         latest_short_term_eligible_date,
@@ -132,9 +116,9 @@ pub async fn fetch_latest_tree_from_github(last_update: NaiveDate) -> Result<Bin
     .unwrap(); // iter is never empty
 
     Ok(BinTrees {
-        bin_deposit_tree,
-        bin_short_term_eligible_tree,
-        bin_long_term_eligible_tree,
+        deposit_tree_file,
+        short_term_eligible_tree_file,
+        long_term_eligible_tree_file,
         latest_update,
     })
 }
@@ -166,40 +150,47 @@ fn filter_file(
     (latest_date, latest_file)
 }
 
-async fn fetch_content(client: &reqwest::Client, download_url: &str) -> Result<Vec<u8>, Error> {
-    if let Some(cached_content) = read_cache(download_url)
-        .await
-        .map_err(|e| Error::CacheError(format!("failed to read cache: {}", e)))?
-    {
+// Downloads the file into the cache unless it is cached already, and returns its path
+async fn fetch_to_cache(
+    client: &reqwest::Client,
+    download_url: &str,
+    name: &str,
+) -> Result<PathBuf, Error> {
+    let path = cache_path(download_url)
+        .map_err(|e| Error::CacheError(format!("failed to get cache path: {}", e)))?;
+    if path.exists() {
         info!("Using cached content for {}", download_url);
-        return Ok(cached_content);
+        return Ok(path);
     }
-    let content = client
+    let mut response = client
         .get(download_url)
         .header("User-Agent", "Rust-GitHub-File-Reader")
         .send()
         .await
-        .map_err(|_| Error::NetworkError)?
-        .bytes()
-        .await
-        .map_err(|_| Error::DeserializeError("failed to deserialize files as bytes".to_string()))?;
-    write_cache(download_url, &content)
-        .await
+        .map_err(|_| Error::NetworkError)?;
+    let progress =
+        download_progress_bar(response.content_length(), &format!("Downloading {}", name));
+    // written to a temporary file first, so that an interrupted download is not cached
+    let temp_path = path.with_extension("tmp");
+    let mut file = create_file(&temp_path)
         .map_err(|e| Error::CacheError(format!("failed to write cache: {}", e)))?;
-    Ok(content.into())
-}
-
-async fn read_cache(download_url: &str) -> anyhow::Result<Option<Vec<u8>>> {
-    if let Ok(content) = std::fs::read(cache_path(download_url)?) {
-        return Ok(Some(content));
-    } else {
-        return Ok(None);
+    while let Some(chunk) = response.chunk().await.map_err(|_| Error::NetworkError)? {
+        file.write_all(&chunk)
+            .map_err(|e| Error::CacheError(format!("failed to write cache: {}", e)))?;
+        progress.inc(chunk.len() as u64);
     }
+    progress.finish_and_clear();
+    drop(file);
+    std::fs::rename(&temp_path, &path)
+        .map_err(|e| Error::CacheError(format!("failed to write cache: {}", e)))?;
+    Ok(path)
 }
 
-async fn write_cache(download_url: &str, content: &[u8]) -> anyhow::Result<()> {
-    create_file_with_content(&cache_path(download_url)?, content)?;
-    Ok(())
+fn create_file(path: &Path) -> anyhow::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(File::create(path)?)
 }
 
 fn cache_path(download_url: &str) -> anyhow::Result<std::path::PathBuf> {
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::external_api::contracts::error::BlockchainError;
+use crate::{external_api::contracts::error::BlockchainError, utils::shutdown::Cancelled};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -23,4 +23,6 @@ pub enum IntmaxError {
     ServerError(IntmaxErrorResponse),
     #[error("Internal error: {0}")]
     InternalError(String),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
 }
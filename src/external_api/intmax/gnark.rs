@@ -1,14 +1,17 @@
-use alloy::primitives::Address;
+use std::time::UNIX_EPOCH;
+
+use alloy::primitives::{Address, U256};
 use intmax2_zkp::wrapper_config::plonky2_config::PoseidonBN128GoldilocksConfig;
 
 use log::info;
 use plonky2::{
-    field::goldilocks_field::GoldilocksField,
+    field::{goldilocks_field::GoldilocksField, types::PrimeField64 as _},
     plonk::proof::{Proof, ProofWithPublicInputs},
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    cli::console::{print_status, print_warning},
     external_api::intmax::header::VersionHeader as _,
     utils::{
         config::Settings,
@@ -17,7 +20,10 @@ use crate::{
     },
 };
 
-use super::error::{IntmaxError, IntmaxErrorResponse};
+use super::{
+    error::{IntmaxError, IntmaxErrorResponse},
+    gnark_backend::GnarkBackend,
+};
 
 type F = GoldilocksField;
 type C = PoseidonBN128GoldilocksConfig;
@@ -77,6 +83,45 @@ pub struct GnarkProof {
     pub proof: String,
 }
 
+impl GnarkProof {
+    /// Checks that the public inputs of the Gnark proof are those of the wrapped Plonky2 proof,
+    /// which commit to the withdrawal or claim public inputs.
+    pub fn verify_public_inputs(&self, expected: &[F]) -> Result<(), IntmaxError> {
+        let Some(public_inputs) = &self.public_inputs else {
+            return Err(IntmaxError::InternalError(
+                "gnark proof has no public inputs to check".to_string(),
+            ));
+        };
+        if public_inputs.len() != expected.len() {
+            return Err(IntmaxError::InternalError(format!(
+                "gnark proof has {} public inputs, expected {}",
+                public_inputs.len(),
+                expected.len()
+            )));
+        }
+        for (i, (actual, expected)) in public_inputs.iter().zip(expected).enumerate() {
+            let actual_value = parse_public_input(actual)?;
+            let expected_value = U256::from(expected.to_canonical_u64());
+            if actual_value != expected_value {
+                return Err(IntmaxError::InternalError(format!(
+                    "gnark public input #{} is {}, expected {}",
+                    i, actual_value, expected_value
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// public inputs are decimal or 0x-prefixed hex strings
+fn parse_public_input(s: &str) -> Result<U256, IntmaxError> {
+    let result = match s.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16),
+        None => U256::from_str_radix(s, 10),
+    };
+    result.map_err(|e| IntmaxError::SerializeError(format!("invalid public input {}: {}", s, e)))
+}
+
 pub async fn gnark_start_prove(
     base_url: &str,
     address: Address,
@@ -131,35 +176,178 @@ pub async fn gnark_get_proof(
     }
 }
 
-pub async fn fetch_gnark_proof(
-    base_url: &str,
-    job_id: &str,
-    start_query_time: u64,
-) -> Result<GnarkProof, IntmaxError> {
-    info!("fetch_gnark_proof for job_id: {}", job_id);
-    let cooldown = Settings::load()
+/// A started Gnark job. Times are unix timestamps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GnarkJob {
+    pub job_id: String,
+    pub started_at: u64,
+    pub start_query_time: u64, // started_at + the estimated time returned by the prover
+}
+
+impl GnarkJob {
+    pub async fn start(
+        backend: &dyn GnarkBackend,
+        address: Address,
+        plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<Self, IntmaxError> {
+        let output = backend.start_prove(address, plonky2_proof).await?;
+        let now = now_unix();
+        Ok(Self {
+            job_id: output.job_id,
+            started_at: now,
+            start_query_time: output.estimated_time.unwrap_or(0) / 1000 + now,
+        })
+    }
+}
+
+fn now_unix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
         .unwrap()
-        .api
-        .gnark_get_proof_cooldown_in_sec;
-    sleep_until(start_query_time);
+        .as_secs()
+}
 
-    let max_tries = 4;
-    let mut tries = 0;
+/// Whether the prover no longer knows the job, e.g. because it expired. Other server errors are
+/// not fixed by resubmitting.
+fn is_job_gone(error: &IntmaxErrorResponse) -> bool {
+    let code = error.code.to_ascii_lowercase().replace('-', "_");
+    matches!(
+        code.as_str(),
+        "not_found" | "job_not_found" | "expired" | "job_expired"
+    )
+}
+
+/// Polls the job with exponential backoff until the proof is done. If the job errors, is no longer
+/// known by the prover, or exceeds `gnark.max_wait_in_sec`, the Plonky2 proof is resubmitted up to
+/// `gnark.max_resubmissions` times, and `on_new_job` is called so that the caller can persist the
+/// new job.
+pub async fn fetch_gnark_proof<H>(
+    backend: &dyn GnarkBackend,
+    address: Address,
+    plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+    job: GnarkJob,
+    mut on_new_job: H,
+) -> Result<GnarkProof, IntmaxError>
+where
+    H: FnMut(&GnarkJob) -> anyhow::Result<()>,
+{
+    info!("fetch_gnark_proof for job_id: {}", job.job_id);
+    let settings = Settings::load()
+        .map_err(|e| IntmaxError::InternalError(format!("failed to load settings: {}", e)))?;
+    let initial_cooldown = settings.api.gnark_get_proof_cooldown_in_sec;
+    let gnark = settings.gnark;
+
+    let mut job = job;
+    let mut resubmissions = 0;
+    let mut cooldown = initial_cooldown;
+    let mut last_status: Option<String> = None;
+    sleep_until(job.start_query_time).await?;
     loop {
-        let output = gnark_get_proof(base_url, job_id).await?;
-        if output.status == "done" {
-            return Ok(output.result.unwrap());
-        } else if output.status == "error" {
-            return Err(IntmaxError::InternalError(
-                "gnark server returned error".to_string(),
-            ));
-        }
-        tries += 1;
-        if tries > max_tries {
-            return Err(IntmaxError::InternalError(
-                "gnark server did not return proof in time".to_string(),
+        let elapsed = now_unix().saturating_sub(job.started_at);
+        let estimated = job.start_query_time.saturating_sub(job.started_at);
+        let failure = match backend.get_proof(&job.job_id).await {
+            Ok(output) => {
+                if last_status.as_deref() != Some(output.status.as_str()) {
+                    print_status(format!(
+                        "Gnark job {}: {} ({}s elapsed, estimated {}s)",
+                        job.job_id, output.status, elapsed, estimated
+                    ));
+                    last_status = Some(output.status.clone());
+                }
+                match output.status.as_str() {
+                    "done" => {
+                        return output.result.ok_or_else(|| {
+                            IntmaxError::InternalError(
+                                "gnark server returned no proof for a done job".to_string(),
+                            )
+                        });
+                    }
+                    "error" => Some("returned error".to_string()),
+                    _ if elapsed > gnark.max_wait_in_sec => {
+                        Some(format!("did not finish in {}s", gnark.max_wait_in_sec))
+                    }
+                    _ => None,
+                }
+            }
+            Err(IntmaxError::ServerError(error)) if is_job_gone(&error) => {
+                Some(format!("is not available: {}", error.message))
+            }
+            Err(e) => return Err(e),
+        };
+
+        if let Some(reason) = failure {
+            if resubmissions >= gnark.max_resubmissions {
+                return Err(IntmaxError::InternalError(format!(
+                    "gnark job {} {} after {} resubmissions",
+                    job.job_id, reason, resubmissions
+                )));
+            }
+            resubmissions += 1;
+            print_warning(format!(
+                "Gnark job {} {}. Resubmitting the Plonky2 proof ({}/{})",
+                job.job_id, reason, resubmissions, gnark.max_resubmissions
             ));
+            job = GnarkJob::start(backend, address, plonky2_proof).await?;
+            on_new_job(&job).map_err(|e| {
+                IntmaxError::InternalError(format!("failed to save the gnark job: {}", e))
+            })?;
+            cooldown = initial_cooldown;
+            last_status = None;
+            sleep_until(job.start_query_time).await?;
+            continue;
         }
-        sleep_for(cooldown);
+        sleep_for(cooldown).await?;
+        cooldown = (cooldown * 2).min(gnark.max_poll_interval_in_sec);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Field as _};
+
+    use super::{is_job_gone, GnarkProof, IntmaxErrorResponse};
+
+    #[test]
+    fn test_verify_public_inputs() {
+        let expected = vec![
+            GoldilocksField::from_canonical_u64(1),
+            GoldilocksField::from_canonical_u64(255),
+        ];
+        let proof = GnarkProof {
+            public_inputs: Some(vec!["1".to_string(), "0xff".to_string()]),
+            proof: "0x".to_string(),
+        };
+        assert!(proof.verify_public_inputs(&expected).is_ok());
+
+        let wrong = GnarkProof {
+            public_inputs: Some(vec!["1".to_string(), "254".to_string()]),
+            proof: "0x".to_string(),
+        };
+        assert!(wrong.verify_public_inputs(&expected).is_err());
+
+        let short = GnarkProof {
+            public_inputs: Some(vec!["1".to_string()]),
+            proof: "0x".to_string(),
+        };
+        assert!(short.verify_public_inputs(&expected).is_err());
+
+        let missing = GnarkProof {
+            public_inputs: None,
+            proof: "0x".to_string(),
+        };
+        assert!(missing.verify_public_inputs(&expected).is_err());
+    }
+
+    #[test]
+    fn test_is_job_gone() {
+        let error = |code: &str| IntmaxErrorResponse {
+            code: code.to_string(),
+            message: String::new(),
+            errors: None,
+        };
+        assert!(is_job_gone(&error("NOT_FOUND")));
+        assert!(is_job_gone(&error("job-expired")));
+        assert!(!is_job_gone(&error("internal_error")));
+        assert!(!is_job_gone(&error("rate_limited")));
     }
 }
@@ -0,0 +1,359 @@
+use alloy::primitives::Address;
+use async_trait::async_trait;
+use intmax2_zkp::wrapper_config::plonky2_config::PoseidonBN128GoldilocksConfig;
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::PrimeField64 as _},
+    plonk::proof::ProofWithPublicInputs,
+};
+use serde::de::DeserializeOwned;
+use tokio::io::AsyncWriteExt as _;
+
+use crate::utils::{
+    config::{GnarkBackendKind, Settings},
+    network::{get_network, Network},
+};
+
+use super::{
+    error::IntmaxError,
+    gnark::{
+        gnark_get_proof, gnark_start_prove, GnarkGetProofSuccessResponse, GnarkProof,
+        GnarkStartProofInput, GnarkStartProofSuccessResponse,
+    },
+};
+
+type F = GoldilocksField;
+type C = PoseidonBN128GoldilocksConfig;
+const D: usize = 2;
+
+// separates the backend index from the backend's own job id in job ids of the failover backend
+const JOB_ID_SEPARATOR: char = '|';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GnarkCircuit {
+    Withdrawal,
+    Claim,
+}
+
+impl std::fmt::Display for GnarkCircuit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GnarkCircuit::Withdrawal => write!(f, "withdrawal"),
+            GnarkCircuit::Claim => write!(f, "claim"),
+        }
+    }
+}
+
+/// A prover that wraps a Plonky2 proof into a Gnark proof as an asynchronous job.
+#[async_trait]
+pub trait GnarkBackend: Send + Sync {
+    /// Identifies the backend in logs and in job ids
+    fn name(&self) -> String;
+
+    async fn start_prove(
+        &self,
+        address: Address,
+        plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<GnarkStartProofSuccessResponse, IntmaxError>;
+
+    async fn get_proof(&self, job_id: &str) -> Result<GnarkGetProofSuccessResponse, IntmaxError>;
+}
+
+/// The gateway, or a self-hosted prover speaking the same JSON protocol over HTTP.
+pub struct HttpGnarkBackend {
+    pub base_url: String,
+}
+
+#[async_trait]
+impl GnarkBackend for HttpGnarkBackend {
+    fn name(&self) -> String {
+        self.base_url.clone()
+    }
+
+    async fn start_prove(
+        &self,
+        address: Address,
+        plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<GnarkStartProofSuccessResponse, IntmaxError> {
+        gnark_start_prove(&self.base_url, address, plonky2_proof.clone()).await
+    }
+
+    async fn get_proof(&self, job_id: &str) -> Result<GnarkGetProofSuccessResponse, IntmaxError> {
+        gnark_get_proof(&self.base_url, job_id).await
+    }
+}
+
+/// A locally installed wrapper command speaking the JSON protocol over stdin/stdout:
+/// `<command> start-proof <circuit>` reads the start-proof request from stdin, and
+/// `<command> get-proof <circuit> <job id>` prints the job status.
+pub struct CommandGnarkBackend {
+    pub command: String,
+    pub circuit: GnarkCircuit,
+}
+
+impl CommandGnarkBackend {
+    async fn run<T: DeserializeOwned>(
+        &self,
+        args: &[&str],
+        stdin: Option<Vec<u8>>,
+    ) -> Result<T, IntmaxError> {
+        let mut parts = self.command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| IntmaxError::InternalError("gnark command is empty".to_string()))?;
+        let mut child = tokio::process::Command::new(program)
+            .args(parts)
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                IntmaxError::InternalError(format!("failed to run {}: {}", self.command, e))
+            })?;
+        let mut child_stdin = child.stdin.take().unwrap();
+        if let Some(input) = stdin {
+            child_stdin.write_all(&input).await.map_err(|e| {
+                IntmaxError::InternalError(format!("failed to write to {}: {}", self.command, e))
+            })?;
+        }
+        drop(child_stdin);
+        let output = child.wait_with_output().await.map_err(|e| {
+            IntmaxError::InternalError(format!("failed to run {}: {}", self.command, e))
+        })?;
+        if !output.status.success() {
+            return Err(IntmaxError::InternalError(format!(
+                "{} exited with {}: {}",
+                self.command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        serde_json::from_slice(&output.stdout).map_err(|e| {
+            IntmaxError::SerializeError(format!(
+                "failed to parse output of {}: {}",
+                self.command, e
+            ))
+        })
+    }
+}
+
+#[async_trait]
+impl GnarkBackend for CommandGnarkBackend {
+    fn name(&self) -> String {
+        self.command.clone()
+    }
+
+    async fn start_prove(
+        &self,
+        address: Address,
+        plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<GnarkStartProofSuccessResponse, IntmaxError> {
+        let input = GnarkStartProofInput::new(address, plonky2_proof.clone());
+        let input = serde_json::to_vec(&input).map_err(|e| {
+            IntmaxError::SerializeError(format!("failed to serialize input: {}", e))
+        })?;
+        let circuit = self.circuit.to_string();
+        self.run(&["start-proof", &circuit], Some(input)).await
+    }
+
+    async fn get_proof(&self, job_id: &str) -> Result<GnarkGetProofSuccessResponse, IntmaxError> {
+        let circuit = self.circuit.to_string();
+        self.run(&["get-proof", &circuit, job_id], None).await
+    }
+}
+
+/// Completes every job immediately with a dummy proof, to run the pipeline offline.
+/// The proof is not accepted by the contracts. The job id carries the public inputs of the
+/// Plonky2 proof, which the dummy proof returns.
+pub struct StubGnarkBackend;
+
+#[async_trait]
+impl GnarkBackend for StubGnarkBackend {
+    fn name(&self) -> String {
+        "stub".to_string()
+    }
+
+    async fn start_prove(
+        &self,
+        _address: Address,
+        plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<GnarkStartProofSuccessResponse, IntmaxError> {
+        let public_inputs = plonky2_proof
+            .public_inputs
+            .iter()
+            .map(|x| x.to_canonical_u64().to_string())
+            .collect::<Vec<_>>();
+        Ok(GnarkStartProofSuccessResponse {
+            job_id: format!("stub:{}", public_inputs.join(",")),
+            status: "done".to_string(),
+            estimated_time: Some(0),
+        })
+    }
+
+    async fn get_proof(&self, job_id: &str) -> Result<GnarkGetProofSuccessResponse, IntmaxError> {
+        Ok(GnarkGetProofSuccessResponse {
+            job_id: job_id.to_string(),
+            status: "done".to_string(),
+            result: Some(GnarkProof {
+                public_inputs: job_id
+                    .split_once(':')
+                    .map(|(_, pis)| pis.split(',').map(str::to_string).collect()),
+                proof: "0x".to_string(),
+            }),
+        })
+    }
+}
+
+/// Starts jobs on the first backend that accepts them, in order, and routes status queries
+/// to the backend that started the job.
+pub struct FailoverGnarkBackend {
+    pub backends: Vec<Box<dyn GnarkBackend>>,
+}
+
+#[async_trait]
+impl GnarkBackend for FailoverGnarkBackend {
+    fn name(&self) -> String {
+        self.backends
+            .iter()
+            .map(|backend| backend.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    async fn start_prove(
+        &self,
+        address: Address,
+        plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<GnarkStartProofSuccessResponse, IntmaxError> {
+        let mut last_error = None;
+        for (i, backend) in self.backends.iter().enumerate() {
+            match backend.start_prove(address, plonky2_proof).await {
+                Ok(mut response) => {
+                    response.job_id = format!("{}{}{}", i, JOB_ID_SEPARATOR, response.job_id);
+                    return Ok(response);
+                }
+                Err(e) => {
+                    log::warn!("gnark backend {} failed to start: {}", backend.name(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            IntmaxError::InternalError("no gnark backend is configured".to_string())
+        }))
+    }
+
+    async fn get_proof(&self, job_id: &str) -> Result<GnarkGetProofSuccessResponse, IntmaxError> {
+        // job ids without a backend index were saved before failover and belong to the first backend
+        let (index, backend_job_id) = match job_id.split_once(JOB_ID_SEPARATOR) {
+            Some((index, backend_job_id)) => {
+                let index = index.parse::<usize>().map_err(|e| {
+                    IntmaxError::SerializeError(format!(
+                        "invalid backend index in gnark job id {}: {}",
+                        job_id, e
+                    ))
+                })?;
+                (index, backend_job_id)
+            }
+            None => (0, job_id),
+        };
+        let backend = self.backends.get(index).ok_or_else(|| {
+            IntmaxError::InternalError(format!(
+                "gnark backend {} of job {} is not configured, only {} are",
+                index,
+                job_id,
+                self.backends.len()
+            ))
+        })?;
+        let mut response = backend.get_proof(backend_job_id).await?;
+        response.job_id = job_id.to_string();
+        Ok(response)
+    }
+}
+
+/// Builds the backend for the circuit from the `[gnark]` settings.
+pub fn gnark_backend(circuit: GnarkCircuit) -> anyhow::Result<Box<dyn GnarkBackend>> {
+    let settings = Settings::load()?;
+    let backend: Box<dyn GnarkBackend> = match settings.gnark.backend {
+        GnarkBackendKind::Http => {
+            let (primary_url, extra_urls) = match circuit {
+                GnarkCircuit::Withdrawal => (
+                    settings.api.withdrawal_gnark_prover_url,
+                    settings.gnark.extra_withdrawal_prover_urls,
+                ),
+                GnarkCircuit::Claim => (
+                    settings.api.claim_gnark_prover_url,
+                    settings.gnark.extra_claim_prover_urls,
+                ),
+            };
+            let backends = std::iter::once(primary_url)
+                .chain(extra_urls)
+                .map(|base_url| Box::new(HttpGnarkBackend { base_url }) as Box<dyn GnarkBackend>)
+                .collect();
+            Box::new(FailoverGnarkBackend { backends })
+        }
+        GnarkBackendKind::Command => {
+            if settings.gnark.command.trim().is_empty() {
+                anyhow::bail!("gnark.command must be set to use the command backend");
+            }
+            Box::new(CommandGnarkBackend {
+                command: settings.gnark.command,
+                circuit,
+            })
+        }
+        GnarkBackendKind::Stub => {
+            // dummy proofs would only get transactions reverted on a real network
+            if get_network() != Network::Localnet {
+                anyhow::bail!("gnark.backend = \"stub\" is only allowed on localnet");
+            }
+            Box::new(StubGnarkBackend)
+        }
+    };
+    Ok(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingBackend;
+
+    #[async_trait]
+    impl GnarkBackend for FailingBackend {
+        fn name(&self) -> String {
+            "failing".to_string()
+        }
+
+        async fn start_prove(
+            &self,
+            _address: Address,
+            _plonky2_proof: &ProofWithPublicInputs<F, C, D>,
+        ) -> Result<GnarkStartProofSuccessResponse, IntmaxError> {
+            Err(IntmaxError::NetworkError("unreachable".to_string()))
+        }
+
+        async fn get_proof(
+            &self,
+            _job_id: &str,
+        ) -> Result<GnarkGetProofSuccessResponse, IntmaxError> {
+            Err(IntmaxError::NetworkError("unreachable".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failover_routes_job_to_starting_backend() {
+        let backend = FailoverGnarkBackend {
+            backends: vec![Box::new(FailingBackend), Box::new(StubGnarkBackend)],
+        };
+        let output = backend.get_proof("1|stub").await.unwrap();
+        assert_eq!(output.job_id, "1|stub");
+        assert_eq!(output.status, "done");
+
+        // job ids without a backend index go to the first backend
+        assert!(backend.get_proof("stub").await.is_err());
+        assert!(backend.get_proof("2|stub").await.is_err());
+        // a malformed index is an error, not the first backend
+        let e = backend.get_proof("x|stub").await.unwrap_err();
+        assert!(matches!(e, IntmaxError::SerializeError(_)));
+    }
+}
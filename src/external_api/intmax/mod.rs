@@ -3,5 +3,7 @@ pub mod circulation;
 pub mod error;
 pub mod event;
 pub mod gnark;
+pub mod gnark_backend;
 pub mod header;
+pub mod plonky2_prover;
 pub mod withdrawal;
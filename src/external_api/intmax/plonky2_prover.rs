@@ -0,0 +1,79 @@
+use intmax2_zkp::wrapper_config::plonky2_config::PoseidonBN128GoldilocksConfig;
+use log::info;
+use mining_circuit_v1::{
+    claim::claim_inner_circuit::ClaimInnerValue,
+    withdrawal::simple_withraw_circuit::SimpleWithdrawalValue,
+};
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::proof::ProofWithPublicInputs};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::external_api::intmax::header::VersionHeader as _;
+
+use super::error::{IntmaxError, IntmaxErrorResponse};
+
+type F = GoldilocksField;
+type C = PoseidonBN128GoldilocksConfig;
+const D: usize = 2;
+
+/// Shared secret between the CLI and a `prover-worker`, sent as a bearer token.
+pub const PROVER_TOKEN_ENV: &str = "PLONKY2_PROVER_TOKEN";
+
+/// Token for the worker at `plonky2_prover_url`. Without one every request would be refused,
+/// and each job would silently fall back to local proving.
+pub fn prover_token() -> Result<String, IntmaxError> {
+    match std::env::var(PROVER_TOKEN_ENV) {
+        Ok(token) if !token.trim().is_empty() => Ok(token),
+        _ => Err(IntmaxError::InternalError(format!(
+            "{} must be set to use the plonky2 prover at plonky2_prover_url",
+            PROVER_TOKEN_ENV
+        ))),
+    }
+}
+
+pub const WITHDRAWAL_PROVE_PATH: &str = "/v1/prove/withdrawal";
+pub const CLAIM_PROVE_PATH: &str = "/v1/prove/claim";
+
+async fn post_prove<T: Serialize, R: DeserializeOwned>(
+    base_url: &str,
+    path: &str,
+    input: &T,
+) -> Result<R, IntmaxError> {
+    let token = prover_token()?;
+    // proving takes minutes, so requests are not retried
+    let response = reqwest::Client::new()
+        .post(format!("{}{}", base_url.trim_end_matches('/'), path))
+        .bearer_auth(token)
+        .json(input)
+        .with_version_header()
+        .send()
+        .await
+        .map_err(|e| {
+            IntmaxError::NetworkError(format!("failed to request plonky2 prover: {}", e))
+        })?;
+    if !response.status().is_success() {
+        let error: IntmaxErrorResponse = response.json().await.map_err(|e| {
+            IntmaxError::SerializeError(format!("failed to parse error response: {}", e))
+        })?;
+        return Err(IntmaxError::ServerError(error));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| IntmaxError::SerializeError(format!("failed to parse response: {}", e)))
+}
+
+pub async fn remote_prove_withdrawal(
+    base_url: &str,
+    witness: &SimpleWithdrawalValue,
+) -> Result<ProofWithPublicInputs<F, C, D>, IntmaxError> {
+    info!("remote_prove_withdrawal with {}", base_url);
+    post_prove(base_url, WITHDRAWAL_PROVE_PATH, witness).await
+}
+
+pub async fn remote_prove_claim(
+    base_url: &str,
+    witness: &[ClaimInnerValue],
+) -> Result<ProofWithPublicInputs<F, C, D>, IntmaxError> {
+    info!("remote_prove_claim with {}", base_url);
+    post_prove(base_url, CLAIM_PROVE_PATH, &witness).await
+}
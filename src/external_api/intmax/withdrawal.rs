@@ -145,11 +145,11 @@ pub async fn submit_withdrawal(
             match status.status.as_str() {
                 "pending" => {
                     info!("withdrawal is pending");
-                    sleep_for(cooldown);
+                    sleep_for(cooldown).await?;
                 }
                 "processing" => {
                     info!("withdrawal is processing");
-                    sleep_for(cooldown);
+                    sleep_for(cooldown).await?;
                 }
                 "completed" => return Ok(status.transaction_hash.unwrap()),
                 "failed" => {
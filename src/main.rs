@@ -1,7 +1,12 @@
 use clap::{arg, command, Parser};
 use cli::{
-    availability::check_availability, configure::select_network, console::print_error,
+    availability::check_availability,
+    bundle::BundleOptions,
+    configure::select_network,
+    console::print_error,
+    data_dir::{migrate_data_dir_mode, DataDirOptions},
     press_enter_to_continue, run,
+    settings::{config_mode, ConfigOptions},
 };
 use dotenv::dotenv;
 use simplelog::{Config, LevelFilter, WriteLogger};
@@ -10,6 +15,8 @@ use std::{env, fs::File, path::PathBuf};
 use utils::{
     config::{create_config_files, Settings},
     file::{create_file_with_content, get_data_path},
+    instance_lock::{lock_data_dir, InstanceLock},
+    shutdown::{is_shutdown_requested, print_shutdown_summary},
 };
 
 pub mod cli;
@@ -26,6 +33,15 @@ struct Args {
     /// The mode to run the program in
     #[arg(value_enum)]
     command: Option<RunMode>,
+
+    #[command(flatten)]
+    bundle: BundleOptions,
+
+    #[command(flatten)]
+    data_dir: DataDirOptions,
+
+    #[command(flatten)]
+    config: ConfigOptions,
 }
 
 fn get_log_file_path() -> anyhow::Result<PathBuf> {
@@ -37,21 +53,49 @@ fn get_log_file_path() -> anyhow::Result<PathBuf> {
 
 #[tokio::main]
 async fn main() {
-    let mode = Args::parse().command;
+    let args = Args::parse();
+    let mode = args.command;
     let is_interactive = mode.is_none();
 
-    match set_up(is_interactive).await {
-        Ok(_) => {}
+    // before the data directory is first used
+    if let Err(e) = args.data_dir.apply() {
+        print_error(format!("Invalid data directory: {}", e));
+        return;
+    }
+    if mode == Some(RunMode::MigrateDataDir) {
+        if let Err(e) = migrate_data_dir_mode(&args.data_dir) {
+            print_error(format!("Failed to migrate the data directory: {:#}", e));
+        }
+        return;
+    }
+    // only reads, so it works while another instance holds the data directory
+    if mode == Some(RunMode::Config) {
+        dotenv().ok();
+        if let Err(e) = config_mode(&args.config) {
+            print_error(format!("{:#}", e));
+        }
+        return;
+    }
+
+    // held until the process exits
+    let _data_dir_lock = match set_up(is_interactive).await {
+        Ok(lock) => lock,
         Err(e) => {
             print_error(format!("Error during setup: {}", e));
             press_enter_to_continue();
             return;
         }
-    }
+    };
 
     // run the CLI
-    match run(mode).await {
+    match run(mode, &args.bundle).await {
+        Ok(_) if is_shutdown_requested() => print_shutdown_summary(),
         Ok(_) => {}
+        // waits return an error when cancelled by Ctrl+C, which is not a failure
+        Err(e) if is_shutdown_requested() => {
+            log::info!("Stopped by shutdown request: {:?}", e);
+            print_shutdown_summary();
+        }
         Err(e) => {
             print_error(format!("{:?}", e));
             if is_interactive {
@@ -62,9 +106,11 @@ async fn main() {
     }
 }
 
-async fn set_up(is_interactive: bool) -> anyhow::Result<()> {
+async fn set_up(is_interactive: bool) -> anyhow::Result<InstanceLock> {
     let version = env!("CARGO_PKG_VERSION");
     println!("Mining CLI {}", version);
+    // before anything is written to the data directory
+    let data_dir_lock = lock_data_dir()?;
     if is_interactive {
         // select network if in interactive mode
         let network = select_network()?;
@@ -86,7 +132,11 @@ async fn set_up(is_interactive: bool) -> anyhow::Result<()> {
         "Settings loaded: {}",
         serde_json::to_string_pretty(&settings)?
     );
+    if !settings.api.plonky2_prover_url.is_empty() {
+        // without the token every remote proof would be refused and proved locally instead
+        external_api::intmax::plonky2_prover::prover_token()?;
+    }
 
     check_availability().await?;
-    Ok(())
+    Ok(data_dir_lock)
 }
@@ -102,18 +102,12 @@ pub async fn fetch_assets_status(
             .get_index(event.deposit().hash())
             .unwrap();
         if let Some(leaf_index) = state.short_term_eligible_tree.get_leaf_index(deposit_index) {
-            let leaf = state
-                .short_term_eligible_tree
-                .tree
-                .get_leaf(leaf_index as usize);
+            let leaf = state.short_term_eligible_tree.get_leaf(leaf_index)?;
             short_term_eligible_amounts.push(leaf.amount);
             short_term_eligible_indices.push(index);
         }
         if let Some(leaf_index) = state.long_term_eligible_tree.get_leaf_index(deposit_index) {
-            let leaf = state
-                .long_term_eligible_tree
-                .tree
-                .get_leaf(leaf_index as usize);
+            let leaf = state.long_term_eligible_tree.get_leaf(leaf_index)?;
             long_term_eligible_amounts.push(leaf.amount);
             long_term_eligible_indices.push(index);
         }
@@ -233,7 +227,7 @@ mod tests {
             .try_init();
 
         let env_config = EnvConfig::import_from_env().unwrap();
-        let mut state = crate::test::get_dummy_state(&env_config.rpc_url).await;
+        let mut state = crate::test::get_dummy_state(&env_config.rpc_urls[0]).await;
         state.sync_trees().await.unwrap();
 
         let dummy_key = crate::test::get_dummy_keys();
@@ -1,45 +1,136 @@
 use alloy::{
-    primitives::{Address, B256, U256},
+    consensus::TxEip1559,
+    primitives::{Address, TxHash, TxKind, B256, U256},
     providers::Provider as _,
     rpc::types::TransactionRequest,
 };
 
 use crate::{
-    cli::console::print_warning,
     external_api::contracts::{
         error::BlockchainError,
-        handlers::send_transaction_with_gas_bump,
-        utils::{get_address_from_private_key, get_provider_with_signer, NormalProvider},
+        gas_price_oracle::{add_l1_fee_margin, GasPriceOracleContract},
+        handlers::send_remainder_with_gas_bump,
+        utils::{
+            get_address_from_private_key, get_next_base_fee, get_provider_with_signer,
+            NormalProvider,
+        },
     },
+    utils::network::get_network,
 };
 
-pub async fn balance_transfer(
+/// Fees and amount for transferring the whole balance of an address
+#[derive(Debug, Clone)]
+pub struct SweepPlan {
+    pub from: Address,
+    pub to: Address,
+    pub balance: U256,
+    pub gas_limit: u64,
+    pub max_fee_per_gas: u128, // next base fee plus the priority fee, the fee expected to be paid
+    pub max_priority_fee_per_gas: u128,
+    pub l1_fee: U256,
+    pub amount: U256, // zero if the balance does not cover the fees
+}
+
+impl SweepPlan {
+    /// Maximum fee paid for the transaction, including the L1 data fee
+    pub fn max_fee(&self) -> U256 {
+        U256::from(self.gas_limit) * U256::from(self.max_fee_per_gas) + self.l1_fee
+    }
+
+    /// Amount left after the fees at `max_fee_per_gas`, zero if the balance does not cover them.
+    pub fn amount_at(&self, max_fee_per_gas: u128) -> U256 {
+        self.balance
+            .saturating_sub(U256::from(self.gas_limit) * U256::from(max_fee_per_gas))
+            .saturating_sub(self.l1_fee)
+    }
+
+    pub fn is_sendable(&self) -> bool {
+        !self.amount.is_zero()
+    }
+}
+
+/// Estimates the gas, EIP-1559 fees and (on OP-stack chains) the L1 data fee of transferring
+/// all ETH of the private key's address, and computes the amount that can be sent. The max fee
+/// per gas is the next base fee plus the priority fee, so that little is left behind when the
+/// transaction is mined in the next block.
+pub async fn plan_sweep(
     provider: &NormalProvider,
-    deposit_private_key: B256,
+    private_key: B256,
     to_address: Address,
-) -> Result<(), BlockchainError> {
-    let signer = get_provider_with_signer(provider, deposit_private_key);
-    let deposit_address = get_address_from_private_key(deposit_private_key);
-    let balance = provider.get_balance(deposit_address).await?;
-    // todo: use estimate gas
-    let gas_limit = U256::from(10000);
-    let gas_price = U256::from(provider.get_gas_price().await?);
+) -> Result<SweepPlan, BlockchainError> {
+    let from = get_address_from_private_key(private_key);
+    let balance = provider.get_balance(from).await?;
+    let gas_limit = provider
+        .estimate_gas(
+            TransactionRequest::default()
+                .from(from)
+                .to(to_address)
+                .value(balance),
+        )
+        .await?;
+    let fees = provider.estimate_eip1559_fees().await?;
+    let max_fee_per_gas = get_next_base_fee(provider).await? + fees.max_priority_fee_per_gas;
+    let l1_fee = if get_network().is_op_stack() {
+        let tx = TxEip1559 {
+            chain_id: provider.get_chain_id().await?,
+            nonce: provider.get_transaction_count(from).await?,
+            gas_limit,
+            max_fee_per_gas,
+            max_priority_fee_per_gas: fees.max_priority_fee_per_gas,
+            to: TxKind::Call(to_address),
+            value: balance,
+            access_list: Default::default(),
+            input: Default::default(),
+        };
+        let l1_fee = GasPriceOracleContract::new(provider.clone())
+            .get_l1_fee_of_tx(&tx)
+            .await?;
+        add_l1_fee_margin(l1_fee)
+    } else {
+        U256::ZERO
+    };
+    let mut plan = SweepPlan {
+        from,
+        to: to_address,
+        balance,
+        gas_limit,
+        max_fee_per_gas,
+        max_priority_fee_per_gas: fees.max_priority_fee_per_gas,
+        l1_fee,
+        amount: U256::ZERO,
+    };
+    plan.amount = plan.amount_at(max_fee_per_gas);
+    log::info!("Sweep plan: {:?}", plan);
+    Ok(plan)
+}
 
-    if balance < gas_price * gas_limit {
-        print_warning("Insufficient balance to transfer");
-        return Ok(());
+/// Sends the transfer computed by `plan_sweep`. Replacements with higher fees send less, so that
+/// the transfer never costs more than the balance.
+pub async fn sweep(
+    provider: &NormalProvider,
+    private_key: B256,
+    plan: &SweepPlan,
+) -> Result<TxHash, BlockchainError> {
+    if !plan.is_sendable() {
+        return Err(BlockchainError::InsufficientFunds(format!(
+            "balance of {:?} does not cover the transfer fee",
+            plan.from
+        )));
     }
-    let transfer_amount = balance - gas_price * gas_limit;
+    let signer = get_provider_with_signer(provider, private_key);
     let tx_request = TransactionRequest::default()
-        .to(to_address)
-        .value(transfer_amount);
-    send_transaction_with_gas_bump(
+        .to(plan.to)
+        .value(plan.amount)
+        .gas_limit(plan.gas_limit)
+        .max_fee_per_gas(plan.max_fee_per_gas)
+        .max_priority_fee_per_gas(plan.max_priority_fee_per_gas);
+    send_remainder_with_gas_bump(
         provider,
         signer,
         tx_request,
         "send balance",
         "deposit address",
+        &|max_fee_per_gas| plan.amount_at(max_fee_per_gas),
     )
-    .await?;
-    Ok(())
+    .await
 }
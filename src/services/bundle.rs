@@ -0,0 +1,319 @@
+use std::path::Path;
+
+use alloy::primitives::Address;
+use anyhow::{ensure, Context as _};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest as _, Keccak256};
+
+use crate::{
+    external_api::{
+        contracts::events::Deposited,
+        intmax::{
+            gnark::{fetch_gnark_proof, GnarkJob},
+            gnark_backend::{gnark_backend, GnarkCircuit},
+        },
+    },
+    services::{
+        claim::{
+            claim::prove_claim,
+            temp::{ClaimStatus, ClaimStep},
+            witness_generation::generate_claim_witness,
+        },
+        mining::withdrawal::{
+            prove_withdrawal,
+            temp::{WithdrawalStatus, WithdrawalStep},
+            witness_generation::generate_withdrawal_witness,
+        },
+    },
+    state::{
+        key::Key,
+        prover::{check_public_inputs, Prover},
+        state::State,
+    },
+    utils::{
+        file::create_file_with_content,
+        network::{get_network, Network},
+    },
+};
+
+const BUNDLE_VERSION: u32 = 1;
+
+/// A withdrawal or claim job with the same state as its entry in the job journal.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum BundleJob {
+    Withdrawal {
+        name: String,
+        status: WithdrawalStatus,
+    },
+    Claim {
+        name: String,
+        status: ClaimStatus,
+    },
+}
+
+impl BundleJob {
+    pub fn name(&self) -> &str {
+        match self {
+            BundleJob::Withdrawal { name, .. } | BundleJob::Claim { name, .. } => name,
+        }
+    }
+
+    pub fn is_proved(&self) -> bool {
+        match self {
+            BundleJob::Withdrawal { status, .. } => status.plonlky2_proof.is_some(),
+            BundleJob::Claim { status, .. } => status.plonlky2_proof.is_some(),
+        }
+    }
+}
+
+/// Jobs moved between the machine holding the keys and a proving machine. Only witnesses,
+/// proofs and the withdrawal address are included, never private keys.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobBundle {
+    pub version: u32,
+    pub network: Network,
+    pub withdrawal_address: Address,
+    pub jobs: Vec<BundleJob>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundleFile {
+    checksum: String,
+    bundle: serde_json::Value,
+}
+
+fn checksum(bundle: &serde_json::Value) -> anyhow::Result<String> {
+    let content = serde_json::to_vec(bundle)?;
+    Ok(hex::encode(Keccak256::digest(&content)))
+}
+
+impl JobBundle {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content =
+            std::fs::read(path).with_context(|| format!("Failed to read bundle {:?}", path))?;
+        let file: BundleFile = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse bundle {:?}", path))?;
+        ensure!(
+            checksum(&file.bundle)? == file.checksum,
+            "Bundle {:?} is corrupted: checksum does not match",
+            path
+        );
+        let bundle: Self = serde_json::from_value(file.bundle)?;
+        ensure!(
+            bundle.version == BUNDLE_VERSION,
+            "Unsupported bundle version {}",
+            bundle.version
+        );
+        ensure!(
+            bundle.network == get_network(),
+            "Bundle is for {} but the current network is {}",
+            bundle.network,
+            get_network()
+        );
+        Ok(bundle)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let bundle = serde_json::to_value(self)?;
+        let file = BundleFile {
+            checksum: checksum(&bundle)?,
+            bundle,
+        };
+        create_file_with_content(path, &serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+}
+
+/// Generates the witnesses of the pending withdrawals and claims of the key.
+pub async fn generate_bundle(
+    state: &State,
+    key: &Key,
+    withdrawals: Vec<Deposited>,
+    claims: Vec<(bool, Vec<Deposited>)>,
+) -> anyhow::Result<JobBundle> {
+    let mut jobs = Vec::new();
+    for event in withdrawals {
+        let name = format!("withdrawal_{}", event.deposit_id);
+        let witness = generate_withdrawal_witness(state, key, event)?;
+        jobs.push(BundleJob::Withdrawal {
+            name,
+            status: WithdrawalStatus {
+                next_step: WithdrawalStep::Plonky2Prove,
+                witness,
+                plonlky2_proof: None,
+                job_id: None,
+                job_started_at: None,
+                start_query_time: None,
+                gnark_proof: None,
+            },
+        });
+    }
+    for (is_short_term, events) in claims {
+        let name = format!(
+            "claim_{}_{}",
+            if is_short_term { "short" } else { "long" },
+            events[0].deposit_id
+        );
+        let witness = generate_claim_witness(state, key, is_short_term, &events).await?;
+        jobs.push(BundleJob::Claim {
+            name,
+            status: ClaimStatus {
+                is_short_term,
+                next_step: ClaimStep::Plonky2Prove,
+                witness,
+                plonlky2_proof: None,
+                job_id: None,
+                job_started_at: None,
+                start_query_time: None,
+                gnark_proof: None,
+            },
+        });
+    }
+    Ok(JobBundle {
+        version: BUNDLE_VERSION,
+        network: get_network(),
+        withdrawal_address: key.withdrawal_address,
+        jobs,
+    })
+}
+
+/// Proves the job with Plonky2 if it is not proved yet.
+pub fn prove_job(prover: &Prover, job: &mut BundleJob) -> anyhow::Result<()> {
+    if job.is_proved() {
+        return Ok(());
+    }
+    match job {
+        BundleJob::Withdrawal { status, .. } => prove_withdrawal(prover, status),
+        BundleJob::Claim { status, .. } => prove_claim(prover, status),
+    }
+}
+
+/// Wraps the Plonky2 proof of the job with Gnark if it is not wrapped yet.
+pub async fn wrap_job(address: Address, job: &mut BundleJob) -> anyhow::Result<()> {
+    let (circuit, gnark_proof, plonky2_proof, expected_public_inputs) = match job {
+        BundleJob::Withdrawal { status, .. } => (
+            GnarkCircuit::Withdrawal,
+            &status.gnark_proof,
+            &status.plonlky2_proof,
+            status.proof_public_inputs(),
+        ),
+        BundleJob::Claim { status, .. } => (
+            GnarkCircuit::Claim,
+            &status.gnark_proof,
+            &status.plonlky2_proof,
+            status.proof_public_inputs(),
+        ),
+    };
+    if gnark_proof.is_some() {
+        return Ok(());
+    }
+    let plonky2_proof = plonky2_proof
+        .clone()
+        .context("Job is not proved with Plonky2")?;
+    // the job may have been proved on another machine
+    check_public_inputs(&plonky2_proof, &expected_public_inputs)?;
+    let backend = gnark_backend(circuit)?;
+    let gnark_job = GnarkJob::start(backend.as_ref(), address, &plonky2_proof).await?;
+    let output = fetch_gnark_proof(backend.as_ref(), address, &plonky2_proof, gnark_job, |_| {
+        Ok(())
+    })
+    .await?;
+    output
+        .verify_public_inputs(&expected_public_inputs)
+        .map_err(|e| anyhow::anyhow!("Gnark {} proof does not match: {}", circuit, e))?;
+    match job {
+        BundleJob::Withdrawal { status, .. } => {
+            status.gnark_proof = Some(output.proof);
+            status.next_step = WithdrawalStep::ContractCall;
+        }
+        BundleJob::Claim { status, .. } => {
+            status.gnark_proof = Some(output.proof);
+            status.next_step = ClaimStep::ContractCall;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the roots the job was proved against are still accepted by the contracts.
+pub async fn check_job_roots(state: &State, job: &BundleJob) -> anyhow::Result<()> {
+    match job {
+        BundleJob::Withdrawal { name, status } => {
+            ensure!(
+                state
+                    .int1
+                    .get_deposit_root_exits(status.witness.deposit_root)
+                    .await?,
+                "{}: deposit root is not known to the contract",
+                name
+            );
+            ensure!(
+                !state
+                    .int1
+                    .get_withdrawal_nullifier_exists(status.witness.nullifier)
+                    .await?,
+                "{}: deposit is already withdrawn",
+                name
+            );
+        }
+        BundleJob::Claim { name, status } => {
+            let witness = status.witness.first().context("Claim has no witness")?;
+            ensure!(
+                state
+                    .int1
+                    .get_deposit_root_exits(witness.deposit_tree_root)
+                    .await?,
+                "{}: deposit root is not known to the contract",
+                name
+            );
+            let eligible_root = if status.is_short_term {
+                state.minter.get_short_term_eligible_root().await?
+            } else {
+                state.minter.get_long_term_eligible_root().await?
+            };
+            ensure!(
+                witness.eligible_tree_root == eligible_root,
+                "{}: eligible tree root has changed since the witness was generated",
+                name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Moves the job into the job journal, from where the pipeline completes it.
+pub fn journal_job(job: &BundleJob) -> anyhow::Result<()> {
+    match job {
+        BundleJob::Withdrawal { name, status } => status.save(name),
+        BundleJob::Claim { name, status } => status.save(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_checksum() {
+        let path = &std::env::temp_dir().join(format!("test_bundle-{}.json", std::process::id()));
+        let bundle = JobBundle {
+            version: BUNDLE_VERSION,
+            network: get_network(),
+            withdrawal_address: Address::ZERO,
+            jobs: Vec::new(),
+        };
+        bundle.save(path).unwrap();
+        assert!(JobBundle::load(path).is_ok());
+
+        let content = std::fs::read_to_string(path).unwrap();
+        let tampered = content.replace(
+            &format!("{:?}", Address::ZERO),
+            &format!("{:?}", Address::repeat_byte(1)),
+        );
+        assert_ne!(content, tampered);
+        std::fs::write(path, tampered).unwrap();
+        assert!(JobBundle::load(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,42 +1,104 @@
-use std::{str::FromStr, time::UNIX_EPOCH};
+use std::str::FromStr;
 
 use alloy::primitives::Bytes;
 use anyhow::ensure;
-use mining_circuit_v1::claim::claim_circuit::ClaimPublicInputs;
 
 use crate::{
     cli::console::print_status,
     external_api::{
         contracts::events::Deposited,
-        intmax::gnark::{fetch_gnark_proof, gnark_start_prove},
+        intmax::{
+            gnark::{fetch_gnark_proof, GnarkJob},
+            gnark_backend::{gnark_backend, GnarkCircuit},
+            plonky2_prover::remote_prove_claim,
+        },
+    },
+    services::{
+        pipeline::{record_job_outcome, run_jobs, Pipeline},
+        utils::{await_until_low_gas_price, prove_remote_or_local},
+    },
+    state::{
+        key::Key,
+        prover::{check_public_inputs, Prover},
+        state::State,
+    },
+    utils::{
+        config::Settings,
+        shutdown::{checkpoint, finish_before_shutdown},
     },
-    services::utils::await_until_low_gas_price,
-    state::{key::Key, state::State},
-    utils::config::Settings,
 };
 
 use super::*;
 
-pub async fn single_claim_task(
-    state: &mut State,
+/// Claims the chunks of events as parallel jobs, see [`Pipeline`].
+pub async fn claim_tasks(
+    state: &State,
     key: &Key,
     is_short_term: bool,
-    events: &[Deposited],
+    chunks: Vec<Vec<Deposited>>,
 ) -> anyhow::Result<()> {
-    from_step1(state, key, is_short_term, events).await?;
-    Ok(())
+    let pipeline = Pipeline::new();
+    let max_parallel_jobs = Settings::load()?.service.max_parallel_jobs;
+    run_jobs(chunks, max_parallel_jobs, |events| {
+        let pipeline = &pipeline;
+        async move {
+            let job = format!(
+                "claim_{}_{}",
+                if is_short_term { "short" } else { "long" },
+                events[0].deposit_id
+            );
+            let result = from_step1(state, key, pipeline, &job, is_short_term, &events).await;
+            record_job_outcome(&job, &result, next_step(&job));
+            result
+        }
+    })
+    .await
 }
 
-pub async fn resume_claim_task(state: &State, key: &Key) -> anyhow::Result<()> {
-    let status = match temp::ClaimStatus::new() {
-        Ok(status) => status,
-        Err(_) => return Ok(()),
-    };
+/// Resumes the jobs left in the journal by a previous run.
+pub async fn resume_claim_tasks(state: &State, key: &Key) -> anyhow::Result<()> {
+    let jobs = temp::ClaimStatus::jobs()?;
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    print_status(format!("Claim: resuming {} claims", jobs.len()));
+    run_claim_jobs(state, key, jobs).await
+}
+
+/// Completes the given jobs of the journal from the steps they stopped at.
+pub async fn run_claim_jobs(state: &State, key: &Key, jobs: Vec<String>) -> anyhow::Result<()> {
+    let pipeline = Pipeline::new();
+    let max_parallel_jobs = Settings::load()?.service.max_parallel_jobs;
+    run_jobs(jobs, max_parallel_jobs, |job| {
+        let pipeline = &pipeline;
+        async move {
+            let result = resume_claim_job(state, key, pipeline, &job).await;
+            record_job_outcome(&job, &result, next_step(&job));
+            result
+        }
+    })
+    .await
+}
+
+/// Step the job resumes from, if it has an entry in the journal.
+fn next_step(job: &str) -> Option<String> {
+    temp::ClaimStatus::new(job)
+        .ok()
+        .map(|status| format!("{:?}", status.next_step))
+}
+
+async fn resume_claim_job(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    let status = temp::ClaimStatus::new(job)?;
     match status.next_step {
-        temp::ClaimStep::Plonky2Prove => from_step2(state, key).await?,
-        temp::ClaimStep::GnarkStart => from_step3(state, key).await?,
-        temp::ClaimStep::GnarkGetProof => from_step4(state, key).await?,
-        temp::ClaimStep::ContractCall => from_step5(state, key).await?,
+        temp::ClaimStep::Plonky2Prove => from_step2(state, key, pipeline, job).await?,
+        temp::ClaimStep::GnarkStart => from_step3(state, key, pipeline, job).await?,
+        temp::ClaimStep::GnarkGetProof => from_step4(state, key, pipeline, job).await?,
+        temp::ClaimStep::ContractCall => from_step5(state, key, pipeline, job).await?,
     }
     Ok(())
 }
@@ -45,10 +107,14 @@ pub async fn resume_claim_task(state: &State, key: &Key) -> anyhow::Result<()> {
 async fn from_step1(
     state: &State,
     key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
     is_short_term: bool,
     events: &[Deposited],
 ) -> anyhow::Result<()> {
-    print_status("Claim: generating claim witness");
+    // jobs not started yet are left for the next run
+    checkpoint()?;
+    print_status(format!("[{}] generating claim witness", job));
     let witness =
         witness_generation::generate_claim_witness(state, key, is_short_term, events).await?;
     let status = temp::ClaimStatus {
@@ -57,106 +123,164 @@ async fn from_step1(
         witness: witness.clone(),
         plonlky2_proof: None,
         job_id: None,
+        job_started_at: None,
         start_query_time: None,
         gnark_proof: None,
     };
-    status.save()?;
-    from_step2(state, key).await?;
+    status.save(job)?;
+    checkpoint()?;
+    from_step2(state, key, pipeline, job).await?;
     Ok(())
 }
 
 // Prove with Plonky2
-async fn from_step2(state: &State, key: &Key) -> anyhow::Result<()> {
-    print_status("Claim: proving with plonky2");
-    let mut status = temp::ClaimStatus::new()?;
+async fn from_step2(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    let mut status = temp::ClaimStatus::new(job)?;
     ensure!(status.next_step == temp::ClaimStep::Plonky2Prove);
-    let mut cyclic_proof = None;
-    for w in &status.witness {
-        let proof = state.prover.claim_processor().prove(w, &cyclic_proof)?;
-        cyclic_proof = Some(proof);
-    }
-    let plonky2_proof = state
-        .prover
-        .claim_wrapper_processor()
-        .prove(&cyclic_proof.unwrap())?;
-    status.plonlky2_proof = Some(plonky2_proof.clone());
+    let prover = state.prover.clone();
+    let witness = status.witness.clone();
+    let plonky2_proof = prove_remote_or_local(
+        job,
+        pipeline,
+        &Settings::load()?.api.plonky2_prover_url,
+        &status.witness,
+        &status.proof_public_inputs(),
+        |url, witness| remote_prove_claim(url, witness),
+        move || prover.prove_claim(&witness),
+    )
+    .await?;
+    status.plonlky2_proof = Some(plonky2_proof);
+    status.next_step = temp::ClaimStep::GnarkStart;
+    status.save(job)?;
+    checkpoint()?;
+    from_step3(state, key, pipeline, job).await?;
+    Ok(())
+}
+
+/// Proves the claim with Plonky2, checks the proof and advances the status to Gnark.
+pub fn prove_claim(prover: &Prover, status: &mut temp::ClaimStatus) -> anyhow::Result<()> {
+    ensure!(status.next_step == temp::ClaimStep::Plonky2Prove);
+    let plonky2_proof = prover.prove_claim(&status.witness)?;
+    check_public_inputs(&plonky2_proof, &status.proof_public_inputs())?;
+    status.plonlky2_proof = Some(plonky2_proof);
     status.next_step = temp::ClaimStep::GnarkStart;
-    status.save()?;
-    from_step3(state, key).await?;
     Ok(())
 }
 
 // Start Gnark
-async fn from_step3(state: &State, key: &Key) -> anyhow::Result<()> {
-    print_status("Claim: starting gnark prover");
-    let mut status = temp::ClaimStatus::new()?;
+async fn from_step3(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    print_status(format!("[{}] starting gnark prover", job));
+    let mut status = temp::ClaimStatus::new(job)?;
     ensure!(status.next_step == temp::ClaimStep::GnarkStart);
-    let settings = Settings::load()?;
     let withdrawal_address = key.withdrawal_address;
 
-    let prover_url = settings.api.claim_gnark_prover_url.clone();
+    let backend = gnark_backend(GnarkCircuit::Claim)?;
     let plonky2_proof = status.plonlky2_proof.clone().unwrap();
-    let output = gnark_start_prove(&prover_url, withdrawal_address, plonky2_proof).await?;
-    status.job_id = Some(output.job_id.clone());
-    let now: u64 = std::time::SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    status.start_query_time = Some(output.estimated_time.unwrap_or(0) / 1000 + now);
+    let gnark_job = GnarkJob::start(backend.as_ref(), withdrawal_address, &plonky2_proof).await?;
+    status.job_id = Some(gnark_job.job_id);
+    status.job_started_at = Some(gnark_job.started_at);
+    status.start_query_time = Some(gnark_job.start_query_time);
     status.next_step = temp::ClaimStep::GnarkGetProof;
-    status.save()?;
-    from_step4(state, key).await?;
+    status.save(job)?;
+    checkpoint()?;
+    from_step4(state, key, pipeline, job).await?;
     Ok(())
 }
 
 // Get Gnark proof
-async fn from_step4(state: &State, key: &Key) -> anyhow::Result<()> {
-    print_status("Claim: getting gnark proof");
-    let mut status = temp::ClaimStatus::new()?;
+async fn from_step4(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    print_status(format!("[{}] getting gnark proof", job));
+    let mut status = temp::ClaimStatus::new(job)?;
     ensure!(status.next_step == temp::ClaimStep::GnarkGetProof);
-    let settings = Settings::load()?;
-    let prover_url = settings.api.claim_gnark_prover_url.clone();
+    let backend = gnark_backend(GnarkCircuit::Claim)?;
+    let plonky2_proof = status.plonlky2_proof.clone().unwrap();
+    let start_query_time = status.start_query_time.unwrap();
+    let gnark_job = GnarkJob {
+        job_id: status.job_id.clone().unwrap(),
+        // temp files saved before the start time was recorded
+        started_at: status.job_started_at.unwrap_or(start_query_time),
+        start_query_time,
+    };
     let output = fetch_gnark_proof(
-        &prover_url,
-        status.job_id.as_ref().unwrap(),
-        status.start_query_time.unwrap(),
+        backend.as_ref(),
+        key.withdrawal_address,
+        &plonky2_proof,
+        gnark_job,
+        |gnark_job| {
+            status.job_id = Some(gnark_job.job_id.clone());
+            status.job_started_at = Some(gnark_job.started_at);
+            status.start_query_time = Some(gnark_job.start_query_time);
+            status.save(job)
+        },
     )
     .await?;
+    if let Err(e) = output.verify_public_inputs(&status.proof_public_inputs()) {
+        // the proof is of no use, so start a new job on the next attempt
+        status.next_step = temp::ClaimStep::GnarkStart;
+        status.save(job)?;
+        anyhow::bail!("Gnark claim proof does not match the claim: {}", e);
+    }
     status.gnark_proof = Some(output.proof.clone());
     status.next_step = temp::ClaimStep::ContractCall;
-    status.save()?;
-    from_step5(state, key).await?;
+    status.save(job)?;
+    checkpoint()?;
+    from_step5(state, key, pipeline, job).await?;
     Ok(())
 }
 
 // Call contract
-async fn from_step5(state: &State, key: &Key) -> anyhow::Result<()> {
-    print_status("Claim: calling contract");
-    let status = temp::ClaimStatus::new()?;
+async fn from_step5(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    print_status(format!("[{}] calling contract", job));
+    let status = temp::ClaimStatus::new(job)?;
     ensure!(status.next_step == temp::ClaimStep::ContractCall);
     let mut claims = Vec::new();
     for w in &status.witness {
         claims.push(w.claim.clone());
     }
-    let last_claim_hash = status.witness.last().unwrap().new_claim_hash;
-    let pis = ClaimPublicInputs {
-        deposit_tree_root: status.witness[0].deposit_tree_root,
-        eligible_tree_root: status.witness[0].eligible_tree_root,
-        last_claim_hash,
-    };
-    temp::ClaimStatus::delete()?;
-    let proof = Bytes::from_str(&status.gnark_proof.unwrap())?;
-    await_until_low_gas_price(&state.provider).await?;
-    state
-        .minter
-        .claim_tokens(
-            key.withdrawal_private_key,
-            status.is_short_term,
-            &claims,
-            &pis,
-            proof,
-        )
-        .await?;
+    let pis = status.public_inputs();
+    // transactions of the jobs are sent one at a time to keep nonces in order
+    let _submission = pipeline.lock_submission().await;
+    if !await_until_low_gas_price(&state.provider).await? {
+        // keep the temp file to resume from the contract call next time
+        return Ok(());
+    }
+    // the journal entry is deleted before sending, so finish this even on shutdown
+    finish_before_shutdown(async {
+        temp::ClaimStatus::delete(job)?;
+        let proof = Bytes::from_str(&status.gnark_proof.unwrap())?;
+        state
+            .minter
+            .claim_tokens(
+                key.withdrawal_private_key,
+                status.is_short_term,
+                &claims,
+                &pis,
+                proof,
+            )
+            .await?;
+        anyhow::Ok(())
+    })
+    .await?;
     Ok(())
 }
 
@@ -171,7 +295,7 @@ mod tests {
     async fn test_claim_task() {
         dotenv::dotenv().ok();
         let env_config = EnvConfig::import_from_env().unwrap();
-        let mut state = crate::test::get_dummy_state(&env_config.rpc_url).await;
+        let mut state = crate::test::get_dummy_state(&env_config.rpc_urls[0]).await;
         let dummy_key = get_dummy_keys();
         let assets_status = state.sync_and_fetch_assets(&dummy_key).await.unwrap();
 
@@ -179,7 +303,8 @@ mod tests {
         let not_claimed_events = assets_status.get_not_claimed_events(is_short_term);
         assert!(!not_claimed_events.is_empty());
 
-        single_claim_task(&mut state, &dummy_key, is_short_term, &not_claimed_events)
+        let chunk = not_claimed_events.into_iter().take(MAX_CLAIMS).collect();
+        claim_tasks(&state, &dummy_key, is_short_term, vec![chunk])
             .await
             .unwrap();
     }
@@ -1,6 +1,10 @@
-use claim::single_claim_task;
+use alloy::primitives::{Address, U256};
+use claim::claim_tasks;
 
 use crate::{
+    cli::console::print_status,
+    external_api::contracts::token::TokenMetadata,
+    services::utils::{await_until_low_gas_price, pretty_format_u256, pretty_format_units},
     state::{key::Key, state::State},
     utils::errors::CLIError,
 };
@@ -20,13 +24,63 @@ pub async fn claim_task(
     is_short_term: bool,
     assets_status: &AssetsStatus,
 ) -> anyhow::Result<()> {
-    for events in assets_status
+    let chunks = assets_status
         .get_not_claimed_events(is_short_term)
         .chunks(MAX_CLAIMS)
+        .map(|events| events.to_vec())
+        .collect::<Vec<_>>();
+    claim_tasks(state, key, is_short_term, chunks)
+        .await
+        .map_err(|e| CLIError::InternalError(format!("Failed to claim: {:#}", e)))?;
+    Ok(())
+}
+
+/// Transfers all ITX held by the withdrawal address to `forward_to`
+pub async fn forward_claimed_tokens(
+    state: &State,
+    key: &Key,
+    forward_to: Address,
+) -> anyhow::Result<()> {
+    let balance = state
+        .token
+        .get_token_balance(key.withdrawal_address)
+        .await?;
+    if balance.is_zero() {
+        return Ok(());
+    }
+    if !await_until_low_gas_price(&state.provider).await? {
+        return Ok(());
+    }
+    let metadata = state.token.get_token_metadata().await?;
+    print_status(format!(
+        "Forwarding {} to {:?}",
+        format_token_amount(balance, metadata.as_ref()),
+        forward_to
+    ));
+    if let Some((amount, tx_hash)) = state
+        .token
+        .transfer_all(key.withdrawal_private_key, forward_to)
+        .await
+        .map_err(|e| CLIError::InternalError(format!("Failed to forward ITX: {:#}", e)))?
     {
-        single_claim_task(state, key, is_short_term, events)
-            .await
-            .map_err(|e| CLIError::InternalError(format!("Failed to claim: {:#}", e)))?;
+        log::info!(
+            "Forwarded {} to {:?} in tx {:?}",
+            format_token_amount(amount, metadata.as_ref()),
+            forward_to,
+            tx_hash
+        );
     }
     Ok(())
 }
+
+// falls back to ITX with 18 decimals for tokens without the optional metadata
+fn format_token_amount(amount: U256, metadata: Option<&TokenMetadata>) -> String {
+    match metadata {
+        Some(metadata) => format!(
+            "{} {}",
+            pretty_format_units(amount, metadata.decimals),
+            metadata.symbol
+        ),
+        None => format!("{} ITX", pretty_format_u256(amount)),
+    }
+}
@@ -1,17 +1,25 @@
 use std::path::{Path, PathBuf};
 
 use intmax2_zkp::wrapper_config::plonky2_config::PoseidonBN128GoldilocksConfig;
-use mining_circuit_v1::claim::claim_inner_circuit::ClaimInnerValue;
+use mining_circuit_v1::claim::{
+    claim_circuit::ClaimPublicInputs, claim_inner_circuit::ClaimInnerValue,
+};
 use plonky2::{field::goldilocks_field::GoldilocksField, plonk::proof::ProofWithPublicInputs};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::file::{create_file_with_content, get_data_path};
+use crate::{
+    services::utils::list_jobs,
+    state::prover::wrapped_public_inputs,
+    utils::file::{create_file_with_content, get_data_path},
+};
 
-fn claim_temp_path() -> PathBuf {
+// each job has its own entry in the job journal, `temp/claim_<job>.json`; the entry of a job
+// saved before jobs ran in parallel is `temp/claim_temp.json`
+fn claim_temp_path(job: &str) -> PathBuf {
     get_data_path()
         .unwrap()
         .join("temp")
-        .join("claim_temp.json")
+        .join(format!("{}.json", job))
 }
 
 type F = GoldilocksField;
@@ -35,25 +43,46 @@ pub struct ClaimStatus {
     pub witness: Vec<ClaimInnerValue>,
     pub plonlky2_proof: Option<ProofWithPublicInputs<F, C, D>>,
     pub job_id: Option<String>,
+    #[serde(default)]
+    pub job_started_at: Option<u64>, // unix timestamp
     pub start_query_time: Option<u64>, // unix timestamp
     pub gnark_proof: Option<String>,
 }
 
 impl ClaimStatus {
-    pub fn new() -> anyhow::Result<Self> {
-        let file = std::fs::read(claim_temp_path())?;
+    pub fn new(job: &str) -> anyhow::Result<Self> {
+        let file = std::fs::read(claim_temp_path(job))?;
         let status: Self = serde_json::from_slice(&file)?;
         Ok(status)
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
+    pub fn save(&self, job: &str) -> anyhow::Result<()> {
         let input = serde_json::to_vec_pretty(&self)?;
-        create_file_with_content(Path::new(&claim_temp_path()), &input)?;
+        create_file_with_content(Path::new(&claim_temp_path(job)), &input)?;
         Ok(())
     }
 
-    pub fn delete() -> anyhow::Result<()> {
-        std::fs::remove_file(claim_temp_path())?;
+    pub fn delete(job: &str) -> anyhow::Result<()> {
+        std::fs::remove_file(claim_temp_path(job))?;
         Ok(())
     }
+
+    /// Jobs with an entry in the journal, oldest first
+    pub fn jobs() -> anyhow::Result<Vec<String>> {
+        list_jobs("claim_")
+    }
+
+    /// Public inputs of the chain of claims, as submitted to the contract
+    pub fn public_inputs(&self) -> ClaimPublicInputs {
+        ClaimPublicInputs {
+            deposit_tree_root: self.witness[0].deposit_tree_root,
+            eligible_tree_root: self.witness[0].eligible_tree_root,
+            last_claim_hash: self.witness.last().unwrap().new_claim_hash,
+        }
+    }
+
+    /// Public inputs the Plonky2 and Gnark proofs of the witness must have
+    pub fn proof_public_inputs(&self) -> Vec<F> {
+        wrapped_public_inputs(self.public_inputs().hash())
+    }
 }
@@ -41,13 +41,20 @@ pub async fn generate_claim_witness(
         let deposit_index = state
             .deposit_hash_tree
             .get_index(event.deposit().hash())
-            .unwrap();
-        let deposit_merkle_proof = state.deposit_hash_tree.prove(deposit_index);
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Deposit {} is not in the deposit tree",
+                    event.deposit().hash()
+                )
+            })?;
+        let deposit_merkle_proof = state.deposit_hash_tree.prove(deposit_index)?;
         let deposit = event.deposit();
 
-        let eligible_index = eligible_tree.get_leaf_index(deposit_index).unwrap();
-        let eligible_merkle_proof = eligible_tree.tree.prove(eligible_index as usize);
-        let eligible_leaf = eligible_tree.tree.get_leaf(eligible_index as usize);
+        let eligible_index = eligible_tree
+            .get_leaf_index(deposit_index)
+            .ok_or_else(|| anyhow::anyhow!("Deposit {} is not eligible", deposit_index))?;
+        let eligible_merkle_proof = eligible_tree.prove(eligible_index)?;
+        let eligible_leaf = eligible_tree.get_leaf(eligible_index)?;
         let salt = derive_salt_from_private_key_nonce(key.deposit_private_key, event.tx_nonce);
         let value = ClaimInnerValue::new(
             deposit_tree_root,
@@ -0,0 +1,235 @@
+use std::time::{Duration, Instant};
+
+use alloy::{
+    eips::BlockNumberOrTag,
+    primitives::{utils::format_units, U256},
+    providers::Provider as _,
+};
+use chrono::NaiveTime;
+use dialoguer::Confirm;
+
+use crate::{
+    cli::console::{print_log, print_warning},
+    external_api::contracts::utils::{wait_for_new_block, NormalProvider},
+    utils::config::{GasPriceSource, OnGasDeadline, Settings},
+};
+
+/// Local time-of-day window in which transactions may be sent. Wraps around midnight if
+/// `start > end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Parses a window in the form `HH:MM-HH:MM`.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("Invalid time window {}: expected HH:MM-HH:MM", s))?;
+        let parse_time = |t: &str| {
+            NaiveTime::parse_from_str(t.trim(), "%H:%M")
+                .map_err(|e| anyhow::anyhow!("Invalid time {} in window {}: {}", t, s, e))
+        };
+        Ok(Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+pub struct GasPolicy {
+    pub max_gas_price: U256,
+    pub source: GasPriceSource,
+    pub fee_history_block_count: u64,
+    pub fee_history_reward_percentile: f64,
+    pub deadline: Option<Duration>,
+    pub on_deadline: OnGasDeadline,
+    pub allowed_time_windows: Vec<TimeWindow>,
+    pub retry_interval_in_sec: u64,
+}
+
+impl GasPolicy {
+    pub fn load(max_gas_price: U256) -> anyhow::Result<Self> {
+        let settings = Settings::load()?;
+        let gas_policy = settings.gas_policy;
+        let allowed_time_windows = gas_policy
+            .allowed_time_windows
+            .iter()
+            .map(|window| TimeWindow::parse(window))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            max_gas_price,
+            source: gas_policy.price_source,
+            fee_history_block_count: gas_policy.fee_history_block_count,
+            fee_history_reward_percentile: gas_policy.fee_history_reward_percentile,
+            deadline: (gas_policy.max_wait_in_sec > 0)
+                .then(|| Duration::from_secs(gas_policy.max_wait_in_sec)),
+            on_deadline: gas_policy.on_deadline,
+            allowed_time_windows,
+            retry_interval_in_sec: settings.service.high_gas_retry_interval_in_sec,
+        })
+    }
+
+    fn is_in_allowed_window(&self, time: NaiveTime) -> bool {
+        self.allowed_time_windows.is_empty()
+            || self
+                .allowed_time_windows
+                .iter()
+                .any(|window| window.contains(time))
+    }
+
+    /// Gas price compared against `max_gas_price`. With fee history, this is the median over the
+    /// recent blocks and the next one of the base fee plus the priority fee at the configured
+    /// percentile, which is less sensitive to single-block spikes than `eth_gasPrice`.
+    pub async fn current_gas_price(&self, provider: &NormalProvider) -> anyhow::Result<U256> {
+        match self.source {
+            GasPriceSource::Instant => Ok(U256::from(provider.get_gas_price().await?)),
+            GasPriceSource::FeeHistory => {
+                let fee_history = provider
+                    .get_fee_history(
+                        self.fee_history_block_count,
+                        BlockNumberOrTag::Latest,
+                        &[self.fee_history_reward_percentile],
+                    )
+                    .await?;
+                let rewards = fee_history.reward.unwrap_or_default();
+                // the last base fee is that of the next block, which has no reward yet, so it is
+                // paired with the reward of the latest block
+                let mut prices = fee_history
+                    .base_fee_per_gas
+                    .iter()
+                    .enumerate()
+                    .map(|(i, base_fee)| {
+                        let reward = rewards.get(i).or(rewards.last());
+                        base_fee + reward.and_then(|r| r.first().copied()).unwrap_or(0)
+                    })
+                    .collect::<Vec<u128>>();
+                if prices.is_empty() {
+                    return Ok(U256::from(provider.get_gas_price().await?));
+                }
+                prices.sort();
+                Ok(U256::from(prices[prices.len() / 2]))
+            }
+        }
+    }
+
+    /// Waits until the gas price is at most `max_gas_price` within an allowed time window.
+    /// Returns false if the deadline passed and the transaction should be skipped.
+    pub async fn wait(&self, provider: &NormalProvider) -> anyhow::Result<bool> {
+        let start = Instant::now();
+        let mut min_observed: Option<U256> = None;
+        loop {
+            let in_window = self.is_in_allowed_window(chrono::Local::now().time());
+            let gas_price = self.current_gas_price(provider).await?;
+            min_observed = Some(min_observed.map_or(gas_price, |min| min.min(gas_price)));
+            let waited = start.elapsed();
+
+            if in_window && gas_price <= self.max_gas_price {
+                log::info!(
+                    "Current gas price: {} GWei is lower than max gas price: {} GWei",
+                    format_gwei(gas_price),
+                    format_gwei(self.max_gas_price),
+                );
+                if waited.as_secs() > 0 {
+                    self.report_wait(waited, min_observed.unwrap());
+                }
+                return Ok(true);
+            }
+
+            let reason = if in_window {
+                format!(
+                    "Current gas price: {} Gwei > max gas price: {} Gwei",
+                    format_gwei(gas_price),
+                    format_gwei(self.max_gas_price),
+                )
+            } else {
+                "Outside of the allowed time windows".to_string()
+            };
+            if self.deadline.is_some_and(|deadline| waited >= deadline) {
+                self.report_wait(waited, min_observed.unwrap());
+                return self.on_deadline_passed(&reason);
+            }
+            print_warning(format!(
+                "{}. Waiting for gas price to drop or the time window to open...",
+                reason
+            ));
+            wait_for_new_block(provider, self.retry_interval_in_sec).await?;
+        }
+    }
+
+    fn on_deadline_passed(&self, reason: &str) -> anyhow::Result<bool> {
+        if self.on_deadline == OnGasDeadline::Prompt && console::user_attended() {
+            let send_anyway = Confirm::new()
+                .with_prompt(format!(
+                    "{} after the maximum wait time. Send the transaction anyway?",
+                    reason
+                ))
+                .default(false)
+                .interact()?;
+            if send_anyway {
+                return Ok(true);
+            }
+        }
+        print_warning(format!(
+            "{} after the maximum wait time. Skipping the transaction.",
+            reason
+        ));
+        Ok(false)
+    }
+
+    fn report_wait(&self, waited: Duration, min_observed: U256) {
+        print_log(format!(
+            "Waited {} for gas price; minimum observed {} Gwei (max {} Gwei)",
+            format_duration(waited),
+            format_gwei(min_observed),
+            format_gwei(self.max_gas_price),
+        ));
+    }
+}
+
+fn format_gwei(value: U256) -> String {
+    format_units(value, "gwei").unwrap()
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}h {}m {}s", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::TimeWindow;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_time_window() {
+        let day = TimeWindow::parse("09:00-17:30").unwrap();
+        assert!(day.contains(time(9, 0)));
+        assert!(day.contains(time(17, 29)));
+        assert!(!day.contains(time(17, 30)));
+        assert!(!day.contains(time(3, 0)));
+
+        let night = TimeWindow::parse("22:00-06:00").unwrap();
+        assert!(night.contains(time(23, 0)));
+        assert!(night.contains(time(5, 59)));
+        assert!(!night.contains(time(12, 0)));
+
+        assert!(TimeWindow::parse("22:00").is_err());
+        assert!(TimeWindow::parse("25:00-06:00").is_err());
+    }
+}
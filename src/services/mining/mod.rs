@@ -1,7 +1,7 @@
 use alloy::primitives::U256;
 use anyhow::Context;
 
-use withdrawal::withdrawal_task;
+use withdrawal::withdrawal_tasks;
 
 use crate::{
     cli::console::print_warning,
@@ -26,7 +26,9 @@ pub async fn mining_task(
     if cancel_pending_deposits {
         for &index in assets_status.pending_indices.iter() {
             let event = assets_status.senders_deposits[index].clone();
-            await_until_low_gas_price(&state.provider).await?;
+            if !await_until_low_gas_price(&state.provider).await? {
+                continue;
+            }
             state
                 .int1
                 .cancel_deposit(
@@ -48,7 +50,9 @@ pub async fn mining_task(
             key.deposit_address
         ));
         let event = assets_status.senders_deposits[index].clone();
-        await_until_low_gas_price(&state.provider).await?;
+        if !await_until_low_gas_price(&state.provider).await? {
+            continue;
+        }
         state
             .int1
             .cancel_deposit(
@@ -68,12 +72,9 @@ pub async fn mining_task(
 
     // withdrawal
     if !assets_status.not_withdrawn_indices.is_empty() {
-        for &index in assets_status.not_withdrawn_indices.iter() {
-            let event = assets_status.senders_deposits[index].clone();
-            withdrawal_task(state, key, event)
-                .await
-                .map_err(|e| CLIError::InternalError(format!("Failed to withdrawal: {:#}", e)))?;
-        }
+        withdrawal_tasks(state, key, assets_status.get_not_withdrawn_events())
+            .await
+            .map_err(|e| CLIError::InternalError(format!("Failed to withdrawal: {:#}", e)))?;
         // return true to cooldown after withdrawal
         return Ok(());
     }
@@ -1,140 +1,276 @@
-use std::time::UNIX_EPOCH;
-
 use alloy::providers::Provider as _;
 use anyhow::ensure;
-use mining_circuit_v1::withdrawal::simple_withraw_circuit::SimpleWithdrawalPublicInputs;
 
 use crate::{
     cli::console::print_status,
     external_api::{
         contracts::events::Deposited,
         intmax::{
-            gnark::{fetch_gnark_proof, gnark_start_prove},
+            gnark::{fetch_gnark_proof, GnarkJob},
+            gnark_backend::{gnark_backend, GnarkCircuit},
+            plonky2_prover::remote_prove_withdrawal,
             withdrawal::submit_withdrawal,
         },
     },
-    state::{key::Key, state::State},
-    utils::config::Settings,
+    services::{
+        pipeline::{record_job_outcome, run_jobs, Pipeline},
+        utils::prove_remote_or_local,
+    },
+    state::{
+        key::Key,
+        prover::{check_public_inputs, Prover},
+        state::State,
+    },
+    utils::{
+        config::Settings,
+        shutdown::{checkpoint, finish_before_shutdown},
+    },
 };
 
 pub mod temp;
 pub mod witness_generation;
 
-pub async fn withdrawal_task(state: &mut State, key: &Key, event: Deposited) -> anyhow::Result<()> {
-    from_step1(state, key, event).await?;
-    Ok(())
+/// Withdraws the deposits as parallel jobs, see [`Pipeline`].
+pub async fn withdrawal_tasks(
+    state: &State,
+    key: &Key,
+    events: Vec<Deposited>,
+) -> anyhow::Result<()> {
+    let pipeline = Pipeline::new();
+    let max_parallel_jobs = Settings::load()?.service.max_parallel_jobs;
+    run_jobs(events, max_parallel_jobs, |event| {
+        let pipeline = &pipeline;
+        async move {
+            let job = format!("withdrawal_{}", event.deposit_id);
+            let result = from_step1(state, key, pipeline, &job, event).await;
+            record_job_outcome(&job, &result, next_step(&job));
+            result
+        }
+    })
+    .await
 }
 
-pub async fn resume_withdrawal_task(state: &State, key: &Key) -> anyhow::Result<()> {
-    let status = match temp::WithdrawalStatus::new() {
-        Ok(status) => status,
-        Err(_) => return Ok(()),
-    };
-    print_status("Withdrawal: resuming withdrawal");
+/// Resumes the jobs left in the journal by a previous run.
+pub async fn resume_withdrawal_tasks(state: &State, key: &Key) -> anyhow::Result<()> {
+    let jobs = temp::WithdrawalStatus::jobs()?;
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    print_status(format!("Withdrawal: resuming {} withdrawals", jobs.len()));
+    run_withdrawal_jobs(state, key, jobs).await
+}
+
+/// Completes the given jobs of the journal from the steps they stopped at.
+pub async fn run_withdrawal_jobs(
+    state: &State,
+    key: &Key,
+    jobs: Vec<String>,
+) -> anyhow::Result<()> {
+    let pipeline = Pipeline::new();
+    let max_parallel_jobs = Settings::load()?.service.max_parallel_jobs;
+    run_jobs(jobs, max_parallel_jobs, |job| {
+        let pipeline = &pipeline;
+        async move {
+            let result = resume_withdrawal_job(state, key, pipeline, &job).await;
+            record_job_outcome(&job, &result, next_step(&job));
+            result
+        }
+    })
+    .await
+}
+
+/// Step the job resumes from, if it has an entry in the journal.
+fn next_step(job: &str) -> Option<String> {
+    temp::WithdrawalStatus::new(job)
+        .ok()
+        .map(|status| format!("{:?}", status.next_step))
+}
+
+async fn resume_withdrawal_job(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    let status = temp::WithdrawalStatus::new(job)?;
     match status.next_step {
-        temp::WithdrawalStep::Plonky2Prove => from_step2(state, key).await?,
-        temp::WithdrawalStep::GnarkStart => from_step3(state, key).await?,
-        temp::WithdrawalStep::GnarkGetProof => from_step4(state, key).await?,
-        temp::WithdrawalStep::ContractCall => from_step5(state, key).await?,
+        temp::WithdrawalStep::Plonky2Prove => from_step2(state, key, pipeline, job).await?,
+        temp::WithdrawalStep::GnarkStart => from_step3(state, key, pipeline, job).await?,
+        temp::WithdrawalStep::GnarkGetProof => from_step4(state, key, pipeline, job).await?,
+        temp::WithdrawalStep::ContractCall => from_step5(state, key, pipeline, job).await?,
     }
     Ok(())
 }
 
 // Generate witness
-async fn from_step1(state: &State, key: &Key, event: Deposited) -> anyhow::Result<()> {
-    print_status("Withdrawal: generating withdrawal witness");
+async fn from_step1(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+    event: Deposited,
+) -> anyhow::Result<()> {
+    // jobs not started yet are left for the next run
+    checkpoint()?;
+    print_status(format!("[{}] generating withdrawal witness", job));
     let witness = witness_generation::generate_withdrawal_witness(state, key, event)?;
     let status = temp::WithdrawalStatus {
         next_step: temp::WithdrawalStep::Plonky2Prove,
         witness: witness.clone(),
         plonlky2_proof: None,
         job_id: None,
+        job_started_at: None,
         start_query_time: None,
         gnark_proof: None,
     };
-    status.save()?;
-    from_step2(state, key).await?;
+    status.save(job)?;
+    checkpoint()?;
+    from_step2(state, key, pipeline, job).await?;
     Ok(())
 }
 
 // Prove with Plonky2
-async fn from_step2(state: &State, key: &Key) -> anyhow::Result<()> {
-    print_status("Withdrawal: proving with plonky2");
-    let mut status = temp::WithdrawalStatus::new()?;
+async fn from_step2(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    let mut status = temp::WithdrawalStatus::new(job)?;
     ensure!(status.next_step == temp::WithdrawalStep::Plonky2Prove);
-    let plonky2_proof = state
-        .prover
-        .withdrawal_wrapper_processor()
-        .prove(&status.witness)?;
-    status.plonlky2_proof = Some(plonky2_proof.clone());
+    let prover = state.prover.clone();
+    let witness = status.witness.clone();
+    let plonky2_proof = prove_remote_or_local(
+        job,
+        pipeline,
+        &Settings::load()?.api.plonky2_prover_url,
+        &status.witness,
+        &status.proof_public_inputs(),
+        |url, witness| remote_prove_withdrawal(url, witness),
+        move || prover.prove_withdrawal(&witness),
+    )
+    .await?;
+    status.plonlky2_proof = Some(plonky2_proof);
+    status.next_step = temp::WithdrawalStep::GnarkStart;
+    status.save(job)?;
+    checkpoint()?;
+    from_step3(state, key, pipeline, job).await?;
+    Ok(())
+}
+
+/// Proves the withdrawal with Plonky2, checks the proof and advances the status to Gnark.
+pub fn prove_withdrawal(
+    prover: &Prover,
+    status: &mut temp::WithdrawalStatus,
+) -> anyhow::Result<()> {
+    ensure!(status.next_step == temp::WithdrawalStep::Plonky2Prove);
+    let plonky2_proof = prover.prove_withdrawal(&status.witness)?;
+    check_public_inputs(&plonky2_proof, &status.proof_public_inputs())?;
+    status.plonlky2_proof = Some(plonky2_proof);
     status.next_step = temp::WithdrawalStep::GnarkStart;
-    status.save()?;
-    from_step3(state, key).await?;
     Ok(())
 }
 
 // Start Gnark
-async fn from_step3(state: &State, key: &Key) -> anyhow::Result<()> {
-    print_status("Withdrawal: starting gnark");
-    let mut status = temp::WithdrawalStatus::new()?;
+async fn from_step3(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    print_status(format!("[{}] starting gnark", job));
+    let mut status = temp::WithdrawalStatus::new(job)?;
     ensure!(status.next_step == temp::WithdrawalStep::GnarkStart);
-    let settings = Settings::load()?;
     let withdrawal_address = key.withdrawal_address;
-    let prover_url = settings.api.withdrawal_gnark_prover_url.clone();
+    let backend = gnark_backend(GnarkCircuit::Withdrawal)?;
     let plonky2_proof = status.plonlky2_proof.clone().unwrap();
-    let output = gnark_start_prove(&prover_url, withdrawal_address, plonky2_proof).await?;
-    status.job_id = Some(output.job_id.clone());
-    let now: u64 = std::time::SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    status.start_query_time = Some(output.estimated_time.unwrap_or(0) / 1000 + now);
+    let gnark_job = GnarkJob::start(backend.as_ref(), withdrawal_address, &plonky2_proof).await?;
+    status.job_id = Some(gnark_job.job_id);
+    status.job_started_at = Some(gnark_job.started_at);
+    status.start_query_time = Some(gnark_job.start_query_time);
     status.next_step = temp::WithdrawalStep::GnarkGetProof;
-    status.save()?;
-    from_step4(state, key).await?;
+    status.save(job)?;
+    checkpoint()?;
+    from_step4(state, key, pipeline, job).await?;
     Ok(())
 }
 
 // Get Gnark proof
-async fn from_step4(state: &State, key: &Key) -> anyhow::Result<()> {
-    print_status("Withdrawal: getting gnark proof");
-    let mut status = temp::WithdrawalStatus::new()?;
+async fn from_step4(
+    state: &State,
+    key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    print_status(format!("[{}] getting gnark proof", job));
+    let mut status = temp::WithdrawalStatus::new(job)?;
     ensure!(status.next_step == temp::WithdrawalStep::GnarkGetProof);
-    let settings = Settings::load()?;
-    let prover_url = settings.api.withdrawal_gnark_prover_url.clone();
+    let backend = gnark_backend(GnarkCircuit::Withdrawal)?;
+    let plonky2_proof = status.plonlky2_proof.clone().unwrap();
+    let start_query_time = status.start_query_time.unwrap();
+    let gnark_job = GnarkJob {
+        job_id: status.job_id.clone().unwrap(),
+        // temp files saved before the start time was recorded
+        started_at: status.job_started_at.unwrap_or(start_query_time),
+        start_query_time,
+    };
     let output = fetch_gnark_proof(
-        &prover_url,
-        status.job_id.as_ref().unwrap(),
-        status.start_query_time.unwrap(),
+        backend.as_ref(),
+        key.withdrawal_address,
+        &plonky2_proof,
+        gnark_job,
+        |gnark_job| {
+            status.job_id = Some(gnark_job.job_id.clone());
+            status.job_started_at = Some(gnark_job.started_at);
+            status.start_query_time = Some(gnark_job.start_query_time);
+            status.save(job)
+        },
     )
     .await?;
+    if let Err(e) = output.verify_public_inputs(&status.proof_public_inputs()) {
+        // the proof is of no use, so start a new job on the next attempt
+        status.next_step = temp::WithdrawalStep::GnarkStart;
+        status.save(job)?;
+        anyhow::bail!(
+            "Gnark withdrawal proof does not match the withdrawal: {}",
+            e
+        );
+    }
     status.gnark_proof = Some(output.proof.clone());
     status.next_step = temp::WithdrawalStep::ContractCall;
-    status.save()?;
-    from_step5(state, key).await?;
+    status.save(job)?;
+    checkpoint()?;
+    from_step5(state, key, pipeline, job).await?;
     Ok(())
 }
 
 // Call contract
-async fn from_step5(state: &State, _key: &Key) -> anyhow::Result<()> {
-    print_status("Withdrawal: calling contract");
-    let status = temp::WithdrawalStatus::new()?;
+async fn from_step5(
+    state: &State,
+    _key: &Key,
+    pipeline: &Pipeline,
+    job: &str,
+) -> anyhow::Result<()> {
+    print_status(format!("[{}] calling contract", job));
+    let status = temp::WithdrawalStatus::new(job)?;
     ensure!(status.next_step == temp::WithdrawalStep::ContractCall);
-    let pis = SimpleWithdrawalPublicInputs {
-        deposit_root: status.witness.deposit_root,
-        nullifier: status.witness.nullifier,
-        recipient: status.witness.recipient,
-        token_index: status.witness.deposit_leaf.token_index,
-        amount: status.witness.deposit_leaf.amount,
-    };
-    let tx_hash = submit_withdrawal(&state.int1, pis, status.gnark_proof.as_ref().unwrap()).await?;
-    // delete here because get_tx_receipt may fail, and we don't want to retry this step
-    temp::WithdrawalStatus::delete()?;
+    let pis = status.public_inputs();
+    // submissions are sent one at a time, since on localnet they are transactions of one key
+    let _submission = pipeline.lock_submission().await;
+    // once submitted, the journal entry must be deleted, so finish this even on shutdown
+    let tx_hash = finish_before_shutdown(async {
+        let tx_hash =
+            submit_withdrawal(&state.int1, pis, status.gnark_proof.as_ref().unwrap()).await?;
+        // delete here because get_tx_receipt may fail, and we don't want to retry this step
+        temp::WithdrawalStatus::delete(job)?;
+        anyhow::Ok(tx_hash)
+    })
+    .await?;
     let receipt = state.provider.get_transaction_receipt(tx_hash).await?;
     ensure!(receipt.is_some(), "Transaction receipt not found");
     let receipt = receipt.unwrap();
     ensure!(receipt.status(), "Transaction failed");
-    print_status("Successfully withdrawn".to_string());
+    print_status(format!("[{}] successfully withdrawn", job));
     Ok(())
 }
 
@@ -147,14 +283,14 @@ mod tests {
     async fn test_withdrawal() {
         dotenv::dotenv().ok();
         let env_config = EnvConfig::import_from_env().unwrap();
-        let mut state = crate::test::get_dummy_state(&env_config.rpc_url).await;
+        let mut state = crate::test::get_dummy_state(&env_config.rpc_urls[0]).await;
 
         let dummy_key = get_dummy_keys();
         let assets_status = state.sync_and_fetch_assets(&dummy_key).await.unwrap();
         let events = assets_status.get_not_withdrawn_events();
         assert!(!events.is_empty());
 
-        super::withdrawal_task(&mut state, &dummy_key, events[0].clone())
+        super::withdrawal_tasks(&state, &dummy_key, vec![events[0].clone()])
             .await
             .unwrap();
     }
@@ -164,10 +300,10 @@ mod tests {
     async fn test_resume_withdrawal() {
         dotenv::dotenv().ok();
         let env_config = EnvConfig::import_from_env().unwrap();
-        let mut state = crate::test::get_dummy_state(&env_config.rpc_url).await;
+        let mut state = crate::test::get_dummy_state(&env_config.rpc_urls[0]).await;
         state.sync_trees().await.unwrap();
         let dummy_key = get_dummy_keys();
-        super::resume_withdrawal_task(&state, &dummy_key)
+        super::resume_withdrawal_tasks(&state, &dummy_key)
             .await
             .unwrap();
     }
@@ -1,17 +1,25 @@
 use std::path::{Path, PathBuf};
 
 use intmax2_zkp::wrapper_config::plonky2_config::PoseidonBN128GoldilocksConfig;
-use mining_circuit_v1::withdrawal::simple_withraw_circuit::SimpleWithdrawalValue;
+use mining_circuit_v1::withdrawal::simple_withraw_circuit::{
+    SimpleWithdrawalPublicInputs, SimpleWithdrawalValue,
+};
 use plonky2::{field::goldilocks_field::GoldilocksField, plonk::proof::ProofWithPublicInputs};
 use serde::{Deserialize, Serialize};
 
-use crate::utils::file::{create_file_with_content, get_data_path};
+use crate::{
+    services::utils::list_jobs,
+    state::prover::wrapped_public_inputs,
+    utils::file::{create_file_with_content, get_data_path},
+};
 
-fn withdrawal_temp_path() -> PathBuf {
+// each job has its own entry in the job journal, `temp/withdrawal_<job>.json`; the entry of a job
+// saved before jobs ran in parallel is `temp/withdrawal_temp.json`
+fn withdrawal_temp_path(job: &str) -> PathBuf {
     get_data_path()
         .unwrap()
         .join("temp")
-        .join("withdrawal_temp.json")
+        .join(format!("{}.json", job))
 }
 
 type F = GoldilocksField;
@@ -34,25 +42,48 @@ pub struct WithdrawalStatus {
     pub witness: SimpleWithdrawalValue,
     pub plonlky2_proof: Option<ProofWithPublicInputs<F, C, D>>,
     pub job_id: Option<String>,
+    #[serde(default)]
+    pub job_started_at: Option<u64>, // unix timestamp
     pub start_query_time: Option<u64>, // unix timestamp
     pub gnark_proof: Option<String>,
 }
 
 impl WithdrawalStatus {
-    pub fn new() -> anyhow::Result<Self> {
-        let file = std::fs::read(withdrawal_temp_path())?;
+    pub fn new(job: &str) -> anyhow::Result<Self> {
+        let file = std::fs::read(withdrawal_temp_path(job))?;
         let status: Self = serde_json::from_slice(&file)?;
         Ok(status)
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
+    pub fn save(&self, job: &str) -> anyhow::Result<()> {
         let input = serde_json::to_vec_pretty(&self)?;
-        create_file_with_content(Path::new(&withdrawal_temp_path()), &input)?;
+        create_file_with_content(Path::new(&withdrawal_temp_path(job)), &input)?;
         Ok(())
     }
 
-    pub fn delete() -> anyhow::Result<()> {
-        std::fs::remove_file(withdrawal_temp_path())?;
+    pub fn delete(job: &str) -> anyhow::Result<()> {
+        std::fs::remove_file(withdrawal_temp_path(job))?;
         Ok(())
     }
+
+    /// Jobs with an entry in the journal, oldest first
+    pub fn jobs() -> anyhow::Result<Vec<String>> {
+        list_jobs("withdrawal_")
+    }
+
+    /// Public inputs of the withdrawal, as submitted to the contract
+    pub fn public_inputs(&self) -> SimpleWithdrawalPublicInputs {
+        SimpleWithdrawalPublicInputs {
+            deposit_root: self.witness.deposit_root,
+            nullifier: self.witness.nullifier,
+            recipient: self.witness.recipient,
+            token_index: self.witness.deposit_leaf.token_index,
+            amount: self.witness.deposit_leaf.amount,
+        }
+    }
+
+    /// Public inputs the Plonky2 and Gnark proofs of the witness must have
+    pub fn proof_public_inputs(&self) -> Vec<F> {
+        wrapped_public_inputs(self.public_inputs().hash())
+    }
 }
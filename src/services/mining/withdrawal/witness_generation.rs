@@ -18,8 +18,13 @@ pub fn generate_withdrawal_witness(
     let deposit_index = state
         .deposit_hash_tree
         .get_index(event.deposit().hash())
-        .unwrap();
-    let deposit_merkle_proof = state.deposit_hash_tree.prove(deposit_index);
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Deposit {} is not in the deposit tree",
+                event.deposit().hash()
+            )
+        })?;
+    let deposit_merkle_proof = state.deposit_hash_tree.prove(deposit_index)?;
     let recipient = convert_address_to_intmax(key.withdrawal_address);
     let pubkey = derive_pubkey_from_private_key(key.deposit_private_key);
     let salt = derive_salt_from_private_key_nonce(key.deposit_private_key, event.tx_nonce);
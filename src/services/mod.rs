@@ -9,17 +9,21 @@ use crate::{
     state::{key::Key, state::State},
     utils::{config::Settings, time::sleep_for},
 };
-use alloy::primitives::{B256, U256};
+use alloy::primitives::{Address, B256, U256};
 use chrono::TimeZone as _;
-use claim::claim_task;
+use claim::{claim_task, forward_claimed_tokens};
 use utils::is_address_used;
 
 const DEPOSIT_CLOSE_TIMESTAMP: u64 = 1751068800; // 2025-06-28 00:00:00 UTC
 
 pub mod assets_status;
 pub mod balance_transfer;
+pub mod bundle;
 pub mod claim;
+pub mod gas_policy;
 pub mod mining;
+pub mod pipeline;
+pub mod prover_worker;
 pub mod sync;
 pub mod utils;
 
@@ -86,7 +90,7 @@ pub async fn mining_loop(
         // print assets status after mining
         let assets_status = state.sync_and_fetch_assets(&key).await?;
         print_assets_status(&assets_status);
-        common_loop_cool_down();
+        common_loop_cool_down().await?;
     }
     print_log(format!(
         "Mining loop for deposit address {:?} ended",
@@ -108,7 +112,7 @@ pub async fn exit_loop(state: &mut State, withdrawal_private_key: B256) -> anyho
             break;
         }
         mining_task(state, &key, &assets_status, false, true, U256::default()).await?;
-        common_loop_cool_down();
+        common_loop_cool_down().await?;
     }
     print_status(format!(
         "Exit ended for deposit address {:?}",
@@ -148,14 +152,18 @@ pub async fn legacy_exit_loop(
             }
             mining_task(state, &key, &assets_status, false, true, U256::default()).await?;
 
-            common_loop_cool_down();
+            common_loop_cool_down().await?;
         }
     }
 }
 
-pub async fn claim_loop(state: &mut State, withdrawal_private_key: B256) -> anyhow::Result<()> {
+pub async fn claim_loop(
+    state: &mut State,
+    withdrawal_private_key: B256,
+    forward_to: Option<Address>,
+) -> anyhow::Result<()> {
     let key = Key::new(withdrawal_private_key, 0);
-    for is_short_term in [true, false] {
+    for (pass, is_short_term) in CLAIM_TERMS.into_iter().enumerate() {
         check_availability().await?;
         if !is_address_used(&state.provider, key.deposit_address).await? {
             print_status("claim loop finished".to_string());
@@ -170,10 +178,14 @@ pub async fn claim_loop(state: &mut State, withdrawal_private_key: B256) -> anyh
             &state.provider,
             &assets_status,
             key.withdrawal_address,
+            remaining_forwards(forward_to, pass),
         )
         .await?;
         claim_task(state, &key, is_short_term, &assets_status).await?;
-        common_loop_cool_down();
+        if let Some(forward_to) = forward_to {
+            forward_claimed_tokens(state, &key, forward_to).await?;
+        }
+        common_loop_cool_down().await?;
     }
     print_status(format!(
         "Claim ended for deposit address {:?}",
@@ -185,10 +197,11 @@ pub async fn claim_loop(state: &mut State, withdrawal_private_key: B256) -> anyh
 pub async fn legacy_claim_loop(
     state: &mut State,
     withdrawal_private_key: B256,
+    forward_to: Option<Address>,
 ) -> anyhow::Result<()> {
     let mut key_number = 0;
     loop {
-        for is_short_term in [true, false] {
+        for (pass, is_short_term) in CLAIM_TERMS.into_iter().enumerate() {
             check_availability().await?;
             let key = Key::new(withdrawal_private_key, key_number);
             if !is_address_used(&state.provider, key.deposit_address).await? {
@@ -206,17 +219,34 @@ pub async fn legacy_claim_loop(
                 &state.provider,
                 &assets_status,
                 key.withdrawal_address,
+                remaining_forwards(forward_to, pass),
             )
             .await?;
             let assets_status = state.sync_and_fetch_assets(&key).await?;
             claim_task(state, &key, is_short_term, &assets_status).await?;
-            common_loop_cool_down();
+            if let Some(forward_to) = forward_to {
+                forward_claimed_tokens(state, &key, forward_to).await?;
+            }
+            common_loop_cool_down().await?;
         }
         key_number += 1;
     }
 }
 
-fn common_loop_cool_down() {
+// terms claimed by the claim loops for each deposit address, short-term first
+const CLAIM_TERMS: [bool; 2] = [true, false];
+
+// the claim loops forward after every pass, so the gas left must cover one forward for the
+// current pass and each one after it
+fn remaining_forwards(forward_to: Option<Address>, pass: usize) -> u64 {
+    match forward_to {
+        Some(_) => (CLAIM_TERMS.len() - pass) as u64,
+        None => 0,
+    }
+}
+
+async fn common_loop_cool_down() -> anyhow::Result<()> {
     let settings = Settings::load().expect("Failed to load settings");
-    sleep_for(settings.service.loop_cooldown_in_sec);
+    sleep_for(settings.service.loop_cooldown_in_sec).await?;
+    Ok(())
 }
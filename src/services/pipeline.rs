@@ -0,0 +1,98 @@
+use std::future::Future;
+
+use futures::{stream, StreamExt as _};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{
+    cli::console::print_warning,
+    utils::shutdown::{is_shutdown_requested, record_completed_job, record_resumable_job},
+};
+
+/// Coordinates withdrawal or claim jobs running concurrently: Plonky2 proving uses all cores, so
+/// only one job proves at a time, and transactions from the same key are sent one at a time to
+/// keep nonces in order. Gnark proving and waiting for the withdrawal server overlap freely.
+#[derive(Default)]
+pub struct Pipeline {
+    prove_lock: Mutex<()>,
+    submit_lock: Mutex<()>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn lock_prover(&self) -> MutexGuard<'_, ()> {
+        self.prove_lock.lock().await
+    }
+
+    pub async fn lock_submission(&self) -> MutexGuard<'_, ()> {
+        self.submit_lock.lock().await
+    }
+}
+
+/// Runs a job for each item with at most `max_parallel_jobs` jobs in flight. Jobs continue after
+/// another one fails; the first error is returned once all jobs have finished.
+pub async fn run_jobs<T, F, Fut>(
+    items: Vec<T>,
+    max_parallel_jobs: usize,
+    job: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let total = items.len();
+    let results = stream::iter(items)
+        .map(job)
+        .buffer_unordered(max_parallel_jobs.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    let mut errors = results.into_iter().filter_map(|result| result.err());
+    let Some(first_error) = errors.next() else {
+        return Ok(());
+    };
+    let mut failed = 1;
+    for e in errors {
+        // jobs stopped by a shutdown are listed in the shutdown summary instead
+        if !is_shutdown_requested() {
+            print_warning(format!("Job failed: {:#}", e));
+        }
+        failed += 1;
+    }
+    Err(first_error.context(format!("{} of {} jobs failed", failed, total)))
+}
+
+/// Records the outcome of a job for the summary printed on shutdown. `next_step` is the step of
+/// the job's entry left in the journal, if any, which is where the job resumes next time.
+pub fn record_job_outcome(job: &str, result: &anyhow::Result<()>, next_step: Option<String>) {
+    match next_step {
+        Some(next_step) => record_resumable_job(job, next_step),
+        None if result.is_ok() => record_completed_job(job),
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_jobs_continues_after_failure() {
+        let completed = AtomicUsize::new(0);
+        let result = run_jobs(vec![1, 2, 3, 4], 2, |i| {
+            let completed = &completed;
+            async move {
+                tokio::task::yield_now().await;
+                anyhow::ensure!(i != 2, "job {} failed", i);
+                completed.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(completed.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,304 @@
+use std::{sync::Arc, time::Duration};
+
+use mining_circuit_v1::{
+    claim::claim_inner_circuit::ClaimInnerValue,
+    withdrawal::simple_withraw_circuit::SimpleWithdrawalValue,
+};
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    time::timeout,
+};
+
+use crate::{
+    cli::console::print_status,
+    external_api::intmax::plonky2_prover::{CLAIM_PROVE_PATH, WITHDRAWAL_PROVE_PATH},
+    state::prover::{Prover, ProverCircuit},
+};
+
+// witnesses are a few hundred kilobytes; anything much larger is not a prove request
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+// the request line and headers are read before the client is authenticated, so they are bounded
+// in size and time
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+const BODY_TIMEOUT: Duration = Duration::from_secs(120);
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn error(status: u16, code: &str, message: impl ToString) -> Self {
+        // same shape as the errors of the gateway, so the client parses them alike
+        let body = json!({ "code": code, "message": message.to_string(), "errors": null });
+        Self {
+            status,
+            body: serde_json::to_vec(&body).unwrap(),
+        }
+    }
+}
+
+/// Serves Plonky2 proving over HTTP to CLIs configured with `plonky2_prover_url`. Requests must
+/// carry `token` as a bearer token. Proofs are made one at a time with the local prover.
+pub async fn run_prover_worker(listen_address: &str, token: String) -> anyhow::Result<()> {
+    anyhow::ensure!(!token.is_empty(), "The prover worker requires a token");
+    let prover = Arc::new(Prover::new());
+    prover.warm_up(ProverCircuit::Withdrawal);
+    prover.warm_up(ProverCircuit::Claim);
+    let prove_lock = Arc::new(Mutex::new(()));
+    let token = Arc::new(token);
+
+    let listener = TcpListener::bind(listen_address).await?;
+    print_status(format!("Prover worker listening on {}", listen_address));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let prover = prover.clone();
+        let prove_lock = prove_lock.clone();
+        let token = token.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &prover, &prove_lock, &token).await {
+                log::warn!("Prover worker connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    prover: &Arc<Prover>,
+    prove_lock: &Mutex<()>,
+    token: &str,
+) -> anyhow::Result<()> {
+    let request = read_request(&mut BufReader::new(&mut stream), token).await;
+    let response = match request {
+        Ok(request) => handle_request(request, prover, prove_lock).await,
+        Err(response) => response,
+    };
+    write_response(&mut stream, response).await
+}
+
+async fn handle_request(
+    request: Request,
+    prover: &Arc<Prover>,
+    prove_lock: &Mutex<()>,
+) -> Response {
+    if request.method != "POST" {
+        return Response::error(405, "METHOD_NOT_ALLOWED", "only POST is supported");
+    }
+    let prover = prover.clone();
+    let result = match request.path.as_str() {
+        WITHDRAWAL_PROVE_PATH => {
+            let witness: SimpleWithdrawalValue = match serde_json::from_slice(&request.body) {
+                Ok(witness) => witness,
+                Err(e) => return Response::error(400, "BAD_REQUEST", e),
+            };
+            log::info!("Proving withdrawal for {:?}", witness.recipient);
+            let _lock = prove_lock.lock().await;
+            tokio::task::spawn_blocking(move || prover.prove_withdrawal(&witness)).await
+        }
+        CLAIM_PROVE_PATH => {
+            let witness: Vec<ClaimInnerValue> = match serde_json::from_slice(&request.body) {
+                Ok(witness) => witness,
+                Err(e) => return Response::error(400, "BAD_REQUEST", e),
+            };
+            log::info!("Proving {} claims", witness.len());
+            let _lock = prove_lock.lock().await;
+            tokio::task::spawn_blocking(move || prover.prove_claim(&witness)).await
+        }
+        _ => return Response::error(404, "NOT_FOUND", "unknown path"),
+    };
+    match result {
+        Ok(Ok(proof)) => Response {
+            status: 200,
+            body: serde_json::to_vec(&proof).unwrap(),
+        },
+        Ok(Err(e)) => Response::error(500, "PROVING_FAILED", format!("{:#}", e)),
+        Err(e) => Response::error(500, "PROVING_FAILED", e),
+    }
+}
+
+// Reads the request, checking the token before the body is read
+async fn read_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    token: &str,
+) -> Result<Request, Response> {
+    let head = match timeout(HEAD_TIMEOUT, read_head(reader)).await {
+        Ok(Ok(head)) => head,
+        Ok(Err(e)) => return Err(Response::error(400, "BAD_REQUEST", e)),
+        Err(_) => return Err(Response::error(408, "REQUEST_TIMEOUT", "headers timed out")),
+    };
+    let expected_authorization = format!("Bearer {}", token);
+    if !constant_time_eq(
+        head.authorization.as_deref().unwrap_or_default(),
+        &expected_authorization,
+    ) {
+        return Err(Response::error(401, "UNAUTHORIZED", "invalid token"));
+    }
+    if head.content_length > MAX_BODY_SIZE {
+        return Err(Response::error(
+            413,
+            "PAYLOAD_TOO_LARGE",
+            "request body is too large",
+        ));
+    }
+    let mut body = vec![0; head.content_length];
+    match timeout(BODY_TIMEOUT, reader.read_exact(&mut body)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => return Err(Response::error(400, "BAD_REQUEST", e)),
+        Err(_) => return Err(Response::error(408, "REQUEST_TIMEOUT", "body timed out")),
+    }
+    Ok(Request {
+        method: head.method,
+        path: head.path,
+        body,
+    })
+}
+
+struct RequestHead {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    content_length: usize,
+}
+
+async fn read_head<R: AsyncBufRead + Unpin>(reader: &mut R) -> anyhow::Result<RequestHead> {
+    let request_line = read_line(reader).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut authorization = None;
+    let mut content_length = 0;
+    for _ in 0..=MAX_HEADERS {
+        let line = read_line(reader).await?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            return Ok(RequestHead {
+                method,
+                path,
+                authorization,
+                content_length,
+            });
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "authorization" => authorization = Some(value.trim().to_string()),
+                "content-length" => content_length = value.trim().parse()?,
+                _ => {}
+            }
+        }
+    }
+    anyhow::bail!("too many headers")
+}
+
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> anyhow::Result<String> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_LINE_LENGTH)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if !line.ends_with(b"\n") {
+        anyhow::ensure!(
+            (line.len() as u64) < MAX_LINE_LENGTH,
+            "header line is too long"
+        );
+        anyhow::bail!("connection closed before the end of the headers");
+    }
+    Ok(String::from_utf8(line)?)
+}
+
+async fn write_response(stream: &mut TcpStream, response: Response) -> anyhow::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    let header = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+// compares tokens without leaking the length of the matching prefix through timing
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("Bearer secret", "Bearer secret"));
+        assert!(!constant_time_eq("Bearer secreT", "Bearer secret"));
+        assert!(!constant_time_eq("Bearer", "Bearer secret"));
+        assert!(!constant_time_eq("", "Bearer secret"));
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let request =
+            b"POST /prove HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: 2\r\n\r\n{}";
+        let request = read_request(&mut &request[..], "secret")
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(request.path, "/prove");
+        assert_eq!(request.body, b"{}");
+
+        // the body of an unauthenticated request is not read
+        let request = b"POST /prove HTTP/1.1\r\nContent-Length: 16777216\r\n\r\n";
+        let response = read_request(&mut &request[..], "secret")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status, 401);
+
+        let long_line = format!(
+            "POST /{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_LINE_LENGTH as usize)
+        );
+        let response = read_request(&mut long_line.as_bytes(), "secret")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status, 400);
+
+        let many_headers = format!(
+            "POST / HTTP/1.1\r\n{}\r\n",
+            "X: y\r\n".repeat(MAX_HEADERS + 1)
+        );
+        let response = read_request(&mut many_headers.as_bytes(), "secret")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(response.status, 400);
+    }
+}
@@ -4,15 +4,21 @@ use crate::{
         github::{fetch_latest_tree_from_github, BinTrees},
     },
     utils::{
-        bin_parser::{BinDepositTree, BinEligibleTree, DepositTreeInfo, EligibleTreeInfo},
+        bin_parser::{
+            read_bin_file, BinDepositTree, BinEligibleTree, DepositTreeInfo, EligibleTreeInfo,
+        },
         config::Settings,
         deposit_hash_tree::DepositHashTree,
         eligible_tree_with_map::EligibleTreeWithMap,
+        shutdown::Cancelled,
         time::sleep_for,
     },
 };
+use std::{collections::HashSet, path::Path};
+
 use anyhow::ensure;
-use chrono::{NaiveDateTime, Utc};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use intmax2_zkp::ethereum_types::bytes32::Bytes32;
 use log::warn;
 
 #[derive(Debug, thiserror::Error)]
@@ -27,6 +33,8 @@ pub enum Error {
     SyncDepositTreeFromEventsError(String),
     #[error("Max Sync Trials Exceeded")]
     MaxSyncTrialsExceeded,
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
 }
 
 const MAX_TRY_FETCH_TREE: usize = 10;
@@ -35,6 +43,7 @@ pub async fn sync_trees(
     int1: &Int1Contract,
     minter: &MinterContract,
     last_update: &mut NaiveDateTime,
+    light_client_deposits: Option<&HashSet<Bytes32>>,
     deposit_hash_tree: &mut DepositHashTree,
     short_term_eligible_tree: &mut EligibleTreeWithMap,
     long_term_eligible_tree: &mut EligibleTreeWithMap,
@@ -54,42 +63,71 @@ pub async fn sync_trees(
                 return Err(Error::MaxSyncTrialsExceeded);
             }
             // fetch trees from github
-            let BinTrees {
-                bin_deposit_tree,
-                bin_short_term_eligible_tree,
-                bin_long_term_eligible_tree,
-                latest_update: _,
-            } = fetch_latest_tree_from_github(last_update.date())
+            let mut bin_trees = fetch_latest_tree_from_github(last_update.date())
                 .await
                 .map_err(|e| {
                     Error::NetworkError(format!("Failed to fetch latest tree from github: {}", e))
                 })?;
+            // a light tree keeps only the leaves of the deposits known when it was built, so the
+            // eligible trees are rebuilt from the latest files whenever the deposit tree changes
+            if light_client_deposits.is_some()
+                && bin_trees.deposit_tree_file.is_some()
+                && (bin_trees.short_term_eligible_tree_file.is_none()
+                    || bin_trees.long_term_eligible_tree_file.is_none())
+            {
+                bin_trees = fetch_latest_tree_from_github(NaiveDate::default())
+                    .await
+                    .map_err(|e| {
+                        Error::NetworkError(format!(
+                            "Failed to fetch latest tree from github: {}",
+                            e
+                        ))
+                    })?;
+            }
+            let BinTrees {
+                deposit_tree_file,
+                short_term_eligible_tree_file,
+                long_term_eligible_tree_file,
+                latest_update: _,
+            } = bin_trees;
             log::info!("fetched bin trees from github");
 
             // retry if TreeRootSyncError occurs
             let update = || async {
-                if let Some(bin_deposit_tree) = bin_deposit_tree {
-                    let new_deposit_hash_tree =
-                        parse_and_validate_bin_deposit_tree(int1, bin_deposit_tree).await?;
+                if let Some(deposit_tree_file) = deposit_tree_file {
+                    let new_deposit_hash_tree = parse_and_validate_bin_deposit_tree(
+                        int1,
+                        light_client_deposits,
+                        &deposit_tree_file,
+                    )
+                    .await?;
                     log::info!(
                         "Fetched deposit tree with {} leaves",
-                        new_deposit_hash_tree.tree.len(),
+                        new_deposit_hash_tree.len(),
                     );
                     *deposit_hash_tree = new_deposit_hash_tree;
                 }
-                if let Some(bin_short_term_eligible_tree) = bin_short_term_eligible_tree {
+                let tracked_deposit_indices = light_client_deposits.map(|hashes| {
+                    hashes
+                        .iter()
+                        .filter_map(|hash| deposit_hash_tree.get_index(*hash))
+                        .collect::<HashSet<_>>()
+                });
+                if let Some(short_term_eligible_tree_file) = short_term_eligible_tree_file {
                     *short_term_eligible_tree = parse_and_validate_bin_eligible_tree(
                         minter,
                         true,
-                        bin_short_term_eligible_tree,
+                        tracked_deposit_indices.as_ref(),
+                        &short_term_eligible_tree_file,
                     )
                     .await?;
                 }
-                if let Some(bin_long_term_eligible_tree) = bin_long_term_eligible_tree {
+                if let Some(long_term_eligible_tree_file) = long_term_eligible_tree_file {
                     *long_term_eligible_tree = parse_and_validate_bin_eligible_tree(
                         minter,
                         false,
-                        bin_long_term_eligible_tree,
+                        tracked_deposit_indices.as_ref(),
+                        &long_term_eligible_tree_file,
                     )
                     .await?;
                 }
@@ -100,7 +138,7 @@ pub async fn sync_trees(
                 Err(e) => {
                     warn!("Fetched tree is invalid in try {}: {}", try_number, e);
                     try_number += 1;
-                    sleep_for(30);
+                    sleep_for(30).await?;
                 }
             }
         }
@@ -117,11 +155,15 @@ pub async fn sync_trees(
 
 async fn parse_and_validate_bin_deposit_tree(
     int1: &Int1Contract,
-    bin_deposit_tree: BinDepositTree,
+    light_client_deposits: Option<&HashSet<Bytes32>>,
+    deposit_tree_file: &Path,
 ) -> Result<DepositHashTree, Error> {
-    let deposit_tree_info: DepositTreeInfo = bin_deposit_tree
-        .try_into()
-        .map_err(|e: anyhow::Error| Error::TreeDeserializationError(e.to_string()))?;
+    // a light parse streams the leaves instead of reading the whole tree
+    let deposit_tree_info = match light_client_deposits {
+        Some(hashes) => DepositTreeInfo::from_bin_file_light(deposit_tree_file, hashes),
+        None => read_bin_file::<BinDepositTree>(deposit_tree_file).and_then(TryInto::try_into),
+    }
+    .map_err(|e: anyhow::Error| Error::TreeDeserializationError(e.to_string()))?;
     let deposit_root_exists = int1
         .get_deposit_root_exits(deposit_tree_info.root)
         .await
@@ -138,11 +180,14 @@ async fn parse_and_validate_bin_deposit_tree(
 async fn parse_and_validate_bin_eligible_tree(
     minter: &MinterContract,
     is_short_term: bool,
-    bin_eligible_tree: BinEligibleTree,
+    tracked_deposit_indices: Option<&HashSet<u32>>,
+    eligible_tree_file: &Path,
 ) -> Result<EligibleTreeWithMap, Error> {
-    let eligible_tree_info: EligibleTreeInfo = bin_eligible_tree
-        .try_into()
-        .map_err(|e: anyhow::Error| Error::TreeDeserializationError(e.to_string()))?;
+    let eligible_tree_info = match tracked_deposit_indices {
+        Some(indices) => EligibleTreeInfo::from_bin_file_light(eligible_tree_file, indices),
+        None => read_bin_file::<BinEligibleTree>(eligible_tree_file).and_then(TryInto::try_into),
+    }
+    .map_err(|e: anyhow::Error| Error::TreeDeserializationError(e.to_string()))?;
     let onchain_eligible_root = if is_short_term {
         minter.get_short_term_eligible_root().await.map_err(|e| {
             Error::NetworkError(format!("Failed to get short term eligible root: {}", e))
@@ -169,7 +214,7 @@ async fn sync_to_latest_deposit_tree(
     log::info!(
         "Local deposit root: {}, total leaves: {}",
         local_root,
-        deposit_hash_tree.tree.len()
+        deposit_hash_tree.len()
     );
     let is_exists = int1.get_deposit_root_exits(local_root).await?;
     ensure!(
@@ -203,13 +248,14 @@ mod tests {
         let env_config = EnvConfig::import_from_env().unwrap();
         dbg!(&env_config);
 
-        let mut state = crate::test::get_dummy_state(&env_config.rpc_url).await;
+        let mut state = crate::test::get_dummy_state(&env_config.rpc_urls[0]).await;
 
         let mut last_update = chrono::NaiveDateTime::default();
         super::sync_trees(
             &state.int1,
             &state.minter,
             &mut last_update,
+            None,
             &mut state.deposit_hash_tree,
             &mut state.short_term_eligible_tree,
             &mut state.long_term_eligible_tree,
@@ -217,6 +263,6 @@ mod tests {
         .await
         .unwrap();
 
-        dbg!(state.deposit_hash_tree.tree.len());
+        dbg!(state.deposit_hash_tree.len());
     }
 }
@@ -1,14 +1,28 @@
+use std::future::Future;
+
 use alloy::{
     primitives::{utils::format_units, Address, U256},
     providers::Provider as _,
 };
+use intmax2_zkp::wrapper_config::plonky2_config::PoseidonBN128GoldilocksConfig;
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::proof::ProofWithPublicInputs};
 
 use crate::{
     cli::console::{print_status, print_warning},
-    external_api::contracts::utils::NormalProvider,
-    utils::{config::Settings, env_config::EnvConfig, time::sleep_for},
+    external_api::{
+        contracts::utils::{wait_for_new_block, NormalProvider},
+        intmax::error::IntmaxError,
+    },
+    state::prover::check_public_inputs,
+    utils::{env_config::EnvConfig, file::get_data_path, shutdown::checkpoint, time::sleep_for},
 };
 
+use super::{gas_policy::GasPolicy, pipeline::Pipeline};
+
+type F = GoldilocksField;
+type C = PoseidonBN128GoldilocksConfig;
+const D: usize = 2;
+
 pub async fn insufficient_balance_instruction(
     provider: &NormalProvider,
     address: Address,
@@ -30,7 +44,7 @@ pub async fn insufficient_balance_instruction(
         let new_balance = provider.get_balance(address).await?;
         if new_balance > required_balance {
             print_status("Balance updated");
-            sleep_for(10);
+            sleep_for(10).await?;
             break;
         } else if new_balance != balance {
             print_warning(format!(
@@ -40,34 +54,16 @@ pub async fn insufficient_balance_instruction(
             ));
             balance = new_balance;
         }
-        sleep_for(10);
+        wait_for_new_block(provider, 10).await?;
     }
     Ok(())
 }
 
-pub async fn await_until_low_gas_price(provider: &NormalProvider) -> anyhow::Result<()> {
+/// Waits according to the gas policy in the settings. Returns false if the transaction
+/// should be skipped because the maximum wait time passed.
+pub async fn await_until_low_gas_price(provider: &NormalProvider) -> anyhow::Result<bool> {
     let max_gas_price = EnvConfig::import_from_env()?.max_gas_price;
-    let settings = Settings::load()?;
-    let high_gas_retry_interval_in_sec = settings.service.high_gas_retry_interval_in_sec;
-    let _url = settings.service.repository_url;
-    loop {
-        let current_gas_price = U256::from(provider.get_gas_price().await?);
-        if current_gas_price <= max_gas_price {
-            log::info!(
-                "Current gas price: {} GWei is lower than max gas price: {} GWei",
-                format_units(current_gas_price, "gwei").unwrap(),
-                format_units(max_gas_price, "gwei").unwrap(),
-            );
-            break;
-        }
-        print_warning(format!(
-            "Current gas price: {} Gwei > max gas price: {} Gwei. Waiting for gas price to drop...",
-            format_units(current_gas_price, "gwei").unwrap(),
-            format_units(max_gas_price, "gwei").unwrap(),
-        ));
-        sleep_for(high_gas_retry_interval_in_sec);
-    }
-    Ok(())
+    GasPolicy::load(max_gas_price)?.wait(provider).await
 }
 
 pub async fn is_address_used(
@@ -79,12 +75,86 @@ pub async fn is_address_used(
     Ok(nonce > 0 || balance > U256::default())
 }
 
+/// Proves the witness of a job with the plonky2 worker at `url`, or locally with `local_fn` if no
+/// worker is configured or it fails. The proof must have `expected_pis` as public inputs, which
+/// shows that it proves this witness.
+pub async fn prove_remote_or_local<'a, W, Fut>(
+    job: &str,
+    pipeline: &Pipeline,
+    url: &'a str,
+    witness: &'a W,
+    expected_pis: &[F],
+    remote_fn: impl FnOnce(&'a str, &'a W) -> Fut,
+    local_fn: impl FnOnce() -> anyhow::Result<ProofWithPublicInputs<F, C, D>> + Send + 'static,
+) -> anyhow::Result<ProofWithPublicInputs<F, C, D>>
+where
+    W: ?Sized,
+    Fut: Future<Output = Result<ProofWithPublicInputs<F, C, D>, IntmaxError>>,
+{
+    if !url.is_empty() {
+        print_status(format!("[{}] proving with plonky2 worker", job));
+        // the worker checks the proof against the circuit before returning it
+        let proof = match remote_fn(url, witness).await {
+            Ok(proof) => check_public_inputs(&proof, expected_pis).map(|_| proof),
+            Err(e) => Err(e.into()),
+        };
+        match proof {
+            Ok(proof) => return Ok(proof),
+            Err(e) => print_warning(format!(
+                "[{}] plonky2 worker failed, proving locally: {}",
+                job, e
+            )),
+        }
+    }
+    let _prover = pipeline.lock_prover().await;
+    // jobs queued for the prover stay at this step
+    checkpoint()?;
+    print_status(format!("[{}] proving with plonky2", job));
+    // prove on a blocking thread so that the other jobs keep polling meanwhile
+    let proof = tokio::task::spawn_blocking(local_fn).await??;
+    check_public_inputs(&proof, expected_pis)?;
+    Ok(proof)
+}
+
 pub fn pretty_format_u256(value: U256) -> String {
-    let s = format_units(value, "ether").unwrap();
+    pretty_format_units(value, 18)
+}
+
+/// Formats an amount of a token with `decimals` decimals without trailing zeros.
+pub fn pretty_format_units(value: U256, decimals: u8) -> String {
+    let s = format_units(value, decimals).unwrap();
+    if !s.contains('.') {
+        return s;
+    }
     let s = s.trim_end_matches('0').trim_end_matches('.');
     s.to_string()
 }
 
+/// Names of the job journal entries in the temp directory starting with `prefix`, oldest first
+pub fn list_jobs(prefix: &str) -> anyhow::Result<Vec<String>> {
+    let temp_dir = get_data_path()?.join("temp");
+    let Ok(entries) = std::fs::read_dir(&temp_dir) else {
+        return Ok(Vec::new());
+    };
+    let mut jobs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(job) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+        else {
+            continue;
+        };
+        if job.starts_with(prefix) {
+            let modified = entry.metadata()?.modified()?;
+            jobs.push((modified, job.to_string()));
+        }
+    }
+    jobs.sort();
+    Ok(jobs.into_iter().map(|(_, job)| job).collect())
+}
+
 #[cfg(test)]
 mod tests {
     use alloy::primitives::utils::parse_ether;
@@ -98,5 +168,9 @@ mod tests {
         let value = parse_ether("1.00000000000000000").unwrap();
         let pretty = super::pretty_format_u256(value);
         assert_eq!(pretty, "1");
+
+        let value = alloy::primitives::U256::from(1_500_000u64);
+        assert_eq!(super::pretty_format_units(value, 6), "1.5");
+        assert_eq!(super::pretty_format_units(value, 0), "1500000");
     }
 }
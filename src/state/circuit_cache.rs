@@ -0,0 +1,136 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+use crate::utils::file::{get_data_path, write_file_atomically};
+
+// bump when the layout of the cache files changes
+const CIRCUIT_CACHE_VERSION: u32 = 2;
+
+// digest of the locked revisions of the circuit crates, set by build.rs. It changes whenever any
+// of them is updated, even without a new version of the CLI
+const CIRCUIT_SOURCES_DIGEST: &str = env!("CIRCUIT_SOURCES_DIGEST");
+
+/// Processor that can be saved to the circuit cache. Circuit data has no serde support, so the
+/// processors are written with plonky2's gate and generator serializers.
+pub trait CachedProcessor: Sized {
+    fn to_bytes(&self) -> anyhow::Result<Vec<u8>>;
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self>;
+}
+
+fn circuit_cache_root() -> anyhow::Result<PathBuf> {
+    Ok(get_data_path()?.join("circuit_cache"))
+}
+
+// None if the binary was built without a Cargo.lock, whose circuits can't be told apart
+fn circuit_cache_dir() -> anyhow::Result<Option<PathBuf>> {
+    if CIRCUIT_SOURCES_DIGEST.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        circuit_cache_root()?
+            .join(format!("v{}", CIRCUIT_CACHE_VERSION))
+            .join(CIRCUIT_SOURCES_DIGEST),
+    ))
+}
+
+/// Loads the processor saved under `name` if it was built from the current circuit sources.
+pub fn load_circuit<T: CachedProcessor>(name: &str) -> Option<T> {
+    load_from(&circuit_cache_dir().ok()??.join(format!("{}.bin", name)))
+}
+
+/// Saves the processor under `name`, and removes caches of other circuit sources.
+pub fn save_circuit<T: CachedProcessor>(name: &str, processor: &T) -> anyhow::Result<()> {
+    let Some(dir) = circuit_cache_dir()? else {
+        log::info!("Not caching {}: the circuit sources are unknown", name);
+        return Ok(());
+    };
+    remove_stale_caches(&circuit_cache_root()?, &dir)?;
+    save_to(&dir.join(format!("{}.bin", name)), processor)
+}
+
+fn load_from<T: CachedProcessor>(path: &Path) -> Option<T> {
+    let content = fs::read(path).ok()?;
+    match T::from_bytes(&content) {
+        Ok(processor) => Some(processor),
+        Err(e) => {
+            log::warn!("Ignoring unreadable circuit cache {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn save_to<T: CachedProcessor>(path: &Path, processor: &T) -> anyhow::Result<()> {
+    // an interrupted write never leaves a partial cache
+    write_file_atomically(path, &processor.to_bytes()?)
+}
+
+fn remove_stale_caches(root: &Path, current: &Path) -> anyhow::Result<()> {
+    let Ok(version_dirs) = fs::read_dir(root) else {
+        return Ok(());
+    };
+    for version_dir in version_dirs.flatten() {
+        let version_path = version_dir.path();
+        if !current.starts_with(&version_path) {
+            fs::remove_dir_all(&version_path)
+                .with_context(|| format!("Failed to remove {:?}", version_path))?;
+            continue;
+        }
+        for digest_dir in fs::read_dir(&version_path)?.flatten() {
+            let digest_path = digest_dir.path();
+            if digest_path != current {
+                fs::remove_dir_all(&digest_path)
+                    .with_context(|| format!("Failed to remove {:?}", digest_path))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct DummyProcessor(u64);
+
+    impl CachedProcessor for DummyProcessor {
+        fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+            Ok(self.0.to_le_bytes().to_vec())
+        }
+
+        fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+            Ok(Self(u64::from_le_bytes(bytes.try_into()?)))
+        }
+    }
+
+    #[test]
+    fn test_circuit_cache() {
+        let root = std::env::temp_dir().join(format!("mining-cli-circuit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let current = root.join("v2").join("aaaa");
+        let stale = root.join("v2").join("bbbb");
+        let path = current.join("processor.bin");
+        assert_eq!(load_from::<DummyProcessor>(&path), None);
+
+        save_to(&stale.join("processor.bin"), &DummyProcessor(1)).unwrap();
+        save_to(&root.join("v1").join("processor.bin"), &DummyProcessor(1)).unwrap();
+        save_to(&path, &DummyProcessor(2)).unwrap();
+        assert_eq!(load_from(&path), Some(DummyProcessor(2)));
+
+        // caches of other sources and layouts are removed
+        remove_stale_caches(&root, &current).unwrap();
+        assert!(!stale.exists());
+        assert!(!root.join("v1").exists());
+        assert!(path.exists());
+
+        // an unreadable cache is ignored
+        fs::write(&path, b"broken").unwrap();
+        assert_eq!(load_from::<DummyProcessor>(&path), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+pub mod circuit_cache;
 pub mod key;
 pub mod mode;
 pub mod prover;
@@ -4,12 +4,20 @@ use clap::{Subcommand, ValueEnum};
 
 #[derive(Subcommand, Debug, Copy, Clone, PartialEq, ValueEnum)]
 pub enum RunMode {
-    Mining,      // only mining
-    Claim,       // only claim
-    Exit,        // only withdraw or cancel pending deposits
-    Export,      // export the deposit addresses
-    CheckUpdate, // check for updates
-    ClearCache,  // clear cached responses
+    Mining,         // only mining
+    Claim,          // only claim
+    Exit,           // only withdraw or cancel pending deposits
+    Export,         // export the deposit addresses
+    ChangePassword, // change the password of the encrypted private key
+    History,        // show the transactions sent by this CLI
+    CheckUpdate,    // check for updates
+    ClearCache,     // clear cached responses
+    GenerateBundle, // write the witnesses of pending jobs to a bundle file
+    ProveBundle,    // prove the jobs of a bundle file, without keys
+    SubmitBundle,   // submit the proved jobs of a bundle file
+    ProverWorker,   // serve Plonky2 proving to other CLIs over HTTP
+    MigrateDataDir, // move an existing data directory to the one chosen by the flags
+    Config,         // show the user config file or the effective settings
 }
 
 impl Display for RunMode {
@@ -19,8 +27,16 @@ impl Display for RunMode {
             RunMode::Claim => write!(f, "Claim"),
             RunMode::Exit => write!(f, "Exit"),
             RunMode::Export => write!(f, "Export"),
+            RunMode::ChangePassword => write!(f, "ChangePassword"),
+            RunMode::History => write!(f, "History"),
             RunMode::CheckUpdate => write!(f, "CheckUpdate"),
             RunMode::ClearCache => write!(f, "ClearCache"),
+            RunMode::GenerateBundle => write!(f, "GenerateBundle"),
+            RunMode::ProveBundle => write!(f, "ProveBundle"),
+            RunMode::SubmitBundle => write!(f, "SubmitBundle"),
+            RunMode::ProverWorker => write!(f, "ProverWorker"),
+            RunMode::MigrateDataDir => write!(f, "MigrateDataDir"),
+            RunMode::Config => write!(f, "Config"),
         }
     }
 }
@@ -1,21 +1,67 @@
-use std::sync::OnceLock;
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Instant,
+};
 
+use intmax2_zkp::{
+    ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _},
+    wrapper_config::plonky2_config::PoseidonBN128GoldilocksConfig,
+};
 use mining_circuit_v1::{
-    claim::{claim_processor::ClaimProcessor, claim_wrapper_processor::ClaimWrapperProcessor},
-    withdrawal::simple_withdrawal_wrapper_processor::SimpleWithdrawalWrapperProcessor,
+    claim::{
+        claim_inner_circuit::ClaimInnerValue, claim_processor::ClaimProcessor,
+        claim_wrapper_processor::ClaimWrapperProcessor,
+    },
+    withdrawal::{
+        simple_withdrawal_wrapper_processor::SimpleWithdrawalWrapperProcessor,
+        simple_withraw_circuit::SimpleWithdrawalValue,
+    },
+};
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::Field as _},
+    plonk::{config::PoseidonGoldilocksConfig, proof::ProofWithPublicInputs},
+    util::serialization::{DefaultGateSerializer, DefaultGeneratorSerializer},
 };
-use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
 
-use crate::cli::console::print_status;
+use crate::{
+    cli::console::{print_log, print_status},
+    utils::{config::Settings, memory::log_memory_usage},
+};
+
+use super::circuit_cache::{load_circuit, save_circuit, CachedProcessor};
 
 type F = GoldilocksField;
 const D: usize = 2;
 type C = PoseidonGoldilocksConfig;
+type OuterC = PoseidonBN128GoldilocksConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProverCircuit {
+    Withdrawal,
+    Claim,
+}
+
+impl std::fmt::Display for ProverCircuit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProverCircuit::Withdrawal => write!(f, "withdrawal"),
+            ProverCircuit::Claim => write!(f, "claim"),
+        }
+    }
+}
 
 pub struct Prover {
-    withdrawal_wrapper_processor: OnceLock<SimpleWithdrawalWrapperProcessor>,
-    claim_processor: OnceLock<ClaimProcessor<F, C, D>>,
-    claim_wrapper_processor: OnceLock<ClaimWrapperProcessor>,
+    withdrawal_wrapper_processor: ProcessorSlot<SimpleWithdrawalWrapperProcessor>,
+    claim_processor: ProcessorSlot<ClaimProcessor<F, C, D>>,
+    claim_wrapper_processor: ProcessorSlot<ClaimWrapperProcessor>,
+    withdrawal_warm_up_started: AtomicBool,
+    claim_warm_up_started: AtomicBool,
+    // builds one circuit at a time and releases the processors after each proof
+    low_memory: bool,
 }
 
 impl Default for Prover {
@@ -26,31 +72,380 @@ impl Default for Prover {
 
 impl Prover {
     pub fn new() -> Self {
+        let low_memory = Settings::load()
+            .map(|settings| settings.prover.low_memory)
+            .unwrap_or(false);
         Self {
-            withdrawal_wrapper_processor: OnceLock::new(),
-            claim_processor: OnceLock::new(),
-            claim_wrapper_processor: OnceLock::new(),
+            withdrawal_wrapper_processor: ProcessorSlot::new(),
+            claim_processor: ProcessorSlot::new(),
+            claim_wrapper_processor: ProcessorSlot::new(),
+            withdrawal_warm_up_started: AtomicBool::new(false),
+            claim_warm_up_started: AtomicBool::new(false),
+            low_memory,
         }
     }
 
-    pub fn withdrawal_wrapper_processor(&self) -> &SimpleWithdrawalWrapperProcessor {
-        self.withdrawal_wrapper_processor.get_or_init(|| {
-            print_status("Waiting for withdrawal prover to be ready");
-            SimpleWithdrawalWrapperProcessor::new()
+    /// Builds the circuits on a background thread, so that they are ready or partly built by
+    /// the time the first proof is needed. Provers that are called meanwhile wait for the build.
+    /// Does nothing in low-memory mode, where circuits are built when they are needed.
+    pub fn warm_up(self: &Arc<Self>, circuit: ProverCircuit) {
+        if self.low_memory {
+            log::info!(
+                "Low-memory mode: the {} prover is built when needed",
+                circuit
+            );
+            return;
+        }
+        let started = match circuit {
+            ProverCircuit::Withdrawal => &self.withdrawal_warm_up_started,
+            ProverCircuit::Claim => &self.claim_warm_up_started,
+        };
+        if started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let prover = Arc::clone(self);
+        print_log(format!(
+            "Preparing the {} prover in the background",
+            circuit
+        ));
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            match circuit {
+                ProverCircuit::Withdrawal => {
+                    prover.withdrawal_wrapper_processor();
+                }
+                ProverCircuit::Claim => {
+                    prover.claim_wrapper_processor();
+                }
+            }
+            log_memory_usage(&format!("after building the {} prover", circuit));
+            print_log(format!(
+                "The {} prover is ready ({}s)",
+                circuit,
+                start.elapsed().as_secs()
+            ));
+        });
+    }
+
+    /// Drops the processors of the circuit. They are freed once running proofs finish, and
+    /// rebuilt or loaded from the circuit cache when needed again.
+    pub fn release(&self, circuit: ProverCircuit) {
+        match circuit {
+            ProverCircuit::Withdrawal => {
+                self.withdrawal_wrapper_processor.release();
+                self.withdrawal_warm_up_started
+                    .store(false, Ordering::SeqCst);
+            }
+            ProverCircuit::Claim => {
+                self.claim_wrapper_processor.release();
+                self.claim_processor.release();
+                self.claim_warm_up_started.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Drops the processors of all circuits
+    pub fn release_all(&self) {
+        self.release(ProverCircuit::Withdrawal);
+        self.release(ProverCircuit::Claim);
+        log_memory_usage("after releasing the provers");
+    }
+
+    pub fn withdrawal_wrapper_processor(&self) -> Arc<SimpleWithdrawalWrapperProcessor> {
+        self.withdrawal_wrapper_processor.get_or_build(|| {
+            load_or_build("withdrawal_wrapper_processor", || {
+                print_status("Waiting for withdrawal prover to be ready");
+                SimpleWithdrawalWrapperProcessor::new()
+            })
         })
     }
 
-    pub fn claim_processor(&self) -> &ClaimProcessor<F, C, D> {
-        self.claim_processor.get_or_init(|| {
-            print_status("Waiting for claim prover to be ready");
-            ClaimProcessor::new()
+    pub fn claim_processor(&self) -> Arc<ClaimProcessor<F, C, D>> {
+        self.claim_processor.get_or_build(|| {
+            load_or_build("claim_processor", || {
+                print_status("Waiting for claim prover to be ready");
+                ClaimProcessor::new()
+            })
         })
     }
 
-    pub fn claim_wrapper_processor(&self) -> &ClaimWrapperProcessor {
-        self.claim_wrapper_processor.get_or_init(|| {
-            print_status("Waiting for claim wrapper prover to be ready");
-            ClaimWrapperProcessor::new(&self.claim_processor().claim_circuit)
+    pub fn claim_wrapper_processor(&self) -> Arc<ClaimWrapperProcessor> {
+        self.claim_wrapper_processor.get_or_build(|| {
+            load_or_build("claim_wrapper_processor", || {
+                print_status("Waiting for claim wrapper prover to be ready");
+                ClaimWrapperProcessor::new(&self.claim_processor().claim_circuit)
+            })
         })
     }
+
+    /// Proves the withdrawal and checks the wrapped proof against the circuit
+    pub fn prove_withdrawal(
+        &self,
+        witness: &SimpleWithdrawalValue,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, OuterC, D>> {
+        if self.low_memory {
+            self.release(ProverCircuit::Claim);
+        }
+        log_memory_usage("before proving the withdrawal");
+        let proof = self.withdrawal_wrapper_processor().prove(witness)?;
+        self.verify_withdrawal_wrapper_proof(&proof)?;
+        log_memory_usage("after proving the withdrawal");
+        if self.low_memory {
+            self.release(ProverCircuit::Withdrawal);
+        }
+        Ok(proof)
+    }
+
+    /// Proves the chain of claims and checks the wrapped proof against the circuit
+    pub fn prove_claim(
+        &self,
+        witness: &[ClaimInnerValue],
+    ) -> anyhow::Result<ProofWithPublicInputs<F, OuterC, D>> {
+        anyhow::ensure!(!witness.is_empty(), "No claim to prove");
+        if self.low_memory {
+            self.release(ProverCircuit::Withdrawal);
+        }
+        log_memory_usage("before proving the claim");
+        let mut cyclic_proof = None;
+        {
+            let claim_processor = self.claim_processor();
+            for w in witness {
+                let proof = claim_processor.prove(w, &cyclic_proof)?;
+                cyclic_proof = Some(proof);
+            }
+        }
+        log_memory_usage("after proving the claim chain");
+        let claim_wrapper_processor = self.claim_wrapper_processor();
+        if self.low_memory {
+            // the wrapper only needs the claim circuit to be built
+            self.claim_processor.release();
+        }
+        let proof = claim_wrapper_processor.prove(&cyclic_proof.unwrap())?;
+        drop(claim_wrapper_processor);
+        self.verify_claim_wrapper_proof(&proof)?;
+        log_memory_usage("after wrapping the claim proof");
+        if self.low_memory {
+            self.release(ProverCircuit::Claim);
+        }
+        Ok(proof)
+    }
+
+    /// Verifies the wrapped withdrawal proof against the data of the outermost wrapper circuit
+    pub fn verify_withdrawal_wrapper_proof(
+        &self,
+        proof: &ProofWithPublicInputs<F, OuterC, D>,
+    ) -> anyhow::Result<()> {
+        self.withdrawal_wrapper_processor()
+            .wrapper_circuit1
+            .data
+            .verify(proof.clone())
+            .map_err(|e| anyhow::anyhow!("Plonky2 withdrawal proof is invalid: {}", e))
+    }
+
+    /// Verifies the wrapped claim proof against the data of the outermost wrapper circuit
+    pub fn verify_claim_wrapper_proof(
+        &self,
+        proof: &ProofWithPublicInputs<F, OuterC, D>,
+    ) -> anyhow::Result<()> {
+        self.claim_wrapper_processor()
+            .wrapper_circuit1
+            .data
+            .verify(proof.clone())
+            .map_err(|e| anyhow::anyhow!("Plonky2 claim proof is invalid: {}", e))
+    }
+}
+
+/// Public inputs of a wrapped proof, which are the limbs of the hash of the withdrawal or claim
+/// public inputs.
+pub fn wrapped_public_inputs(pis_hash: Bytes32) -> Vec<F> {
+    pis_hash
+        .to_u32_vec()
+        .into_iter()
+        .map(F::from_canonical_u32)
+        .collect()
+}
+
+/// Checks that the wrapped proof commits to the `expected` public inputs
+pub fn check_public_inputs(
+    proof: &ProofWithPublicInputs<F, OuterC, D>,
+    expected: &[F],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        proof.public_inputs == expected,
+        "Plonky2 proof public inputs {:?} do not match the witness, expected {:?}",
+        proof.public_inputs,
+        expected
+    );
+    Ok(())
+}
+
+// Slot of a processor. The processor is built outside the lock, so that releasing the slot
+// never waits for a build of several minutes. A build that finishes after the slot was released
+// is handed to its caller but not kept.
+struct ProcessorSlot<T> {
+    state: Mutex<SlotState<T>>,
+    built: Condvar,
+}
+
+struct SlotState<T> {
+    processor: Option<Arc<T>>,
+    building: bool,
+    generation: u64, // incremented on each release
+}
+
+impl<T> ProcessorSlot<T> {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(SlotState {
+                processor: None,
+                building: false,
+                generation: 0,
+            }),
+            built: Condvar::new(),
+        }
+    }
+
+    // Returns the processor in the slot, building it first if the slot is empty. Callers wait
+    // while another thread builds it.
+    fn get_or_build(&self, build: impl FnOnce() -> T) -> Arc<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(processor) = &state.processor {
+                return processor.clone();
+            }
+            if !state.building {
+                break;
+            }
+            state = self.built.wait(state).unwrap();
+        }
+        state.building = true;
+        let generation = state.generation;
+        drop(state);
+
+        // clears the flag even if the build panics, so that waiters build it themselves
+        let guard = BuildGuard(self);
+        let processor = Arc::new(build());
+        guard.finish(generation, &processor);
+        processor
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.processor = None;
+        state.generation += 1;
+    }
+}
+
+struct BuildGuard<'a, T>(&'a ProcessorSlot<T>);
+
+impl<T> BuildGuard<'_, T> {
+    // stores the processor unless the slot was released meanwhile, then wakes the waiters
+    fn finish(self, generation: u64, processor: &Arc<T>) {
+        let mut state = self.0.state.lock().unwrap();
+        if state.generation == generation {
+            state.processor = Some(processor.clone());
+        }
+    }
+}
+
+impl<T> Drop for BuildGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.building = false;
+        self.0.built.notify_all();
+    }
+}
+
+// Loads the processor from the circuit cache, or builds it and saves it for later runs.
+fn load_or_build<T: CachedProcessor>(name: &str, build: impl FnOnce() -> T) -> T {
+    if let Some(processor) = load_circuit(name) {
+        log::info!("Loaded {} from the circuit cache", name);
+        return processor;
+    }
+    let processor = build();
+    if let Err(e) = save_circuit(name, &processor) {
+        log::warn!("Failed to save {} to the circuit cache: {}", name, e);
+    }
+    processor
+}
+
+// the processors' own `to_bytes` and `from_bytes` write their circuit data and targets with
+// these serializers
+fn generator_serializer() -> DefaultGeneratorSerializer<C, D> {
+    DefaultGeneratorSerializer {
+        _phantom: PhantomData,
+    }
+}
+
+fn serialization_error(e: impl std::fmt::Debug) -> anyhow::Error {
+    anyhow::anyhow!("Failed to serialize the circuit: {:?}", e)
+}
+
+impl CachedProcessor for SimpleWithdrawalWrapperProcessor {
+    fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        self.to_bytes(&DefaultGateSerializer, &generator_serializer())
+            .map_err(serialization_error)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_bytes(bytes, &DefaultGateSerializer, &generator_serializer())
+            .map_err(serialization_error)
+    }
+}
+
+impl CachedProcessor for ClaimProcessor<F, C, D> {
+    fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        self.to_bytes(&DefaultGateSerializer, &generator_serializer())
+            .map_err(serialization_error)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_bytes(bytes, &DefaultGateSerializer, &generator_serializer())
+            .map_err(serialization_error)
+    }
+}
+
+impl CachedProcessor for ClaimWrapperProcessor {
+    fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        self.to_bytes(&DefaultGateSerializer, &generator_serializer())
+            .map_err(serialization_error)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_bytes(bytes, &DefaultGateSerializer, &generator_serializer())
+            .map_err(serialization_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_release_does_not_wait_for_build() {
+        let slot = Arc::new(ProcessorSlot::new());
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let builder = {
+            let slot = Arc::clone(&slot);
+            std::thread::spawn(move || {
+                slot.get_or_build(|| {
+                    started_tx.send(()).unwrap();
+                    finish_rx.recv().unwrap();
+                    1
+                })
+            })
+        };
+        started_rx.recv().unwrap();
+        // returns while the build is running
+        slot.release();
+        finish_tx.send(()).unwrap();
+        assert_eq!(*builder.join().unwrap(), 1);
+
+        // the build finished after the release is not kept
+        assert_eq!(*slot.get_or_build(|| 2), 2);
+        assert_eq!(*slot.get_or_build(|| 3), 2);
+    }
 }
@@ -1,12 +1,22 @@
+use std::{collections::HashSet, sync::Arc};
+
 use chrono::NaiveDateTime;
+use intmax2_zkp::{ethereum_types::bytes32::Bytes32, utils::leafable::Leafable as _};
 
-use super::{key::Key, prover::Prover};
+use super::{
+    key::Key,
+    prover::{Prover, ProverCircuit},
+};
 use crate::{
-    external_api::contracts::{
-        int1::Int1Contract,
-        minter::MinterContract,
-        token::TokenContract,
-        utils::{get_provider, NormalProvider},
+    external_api::{
+        contracts::{
+            int1::Int1Contract,
+            minter::MinterContract,
+            rpc_health::select_healthy_rpc_urls,
+            token::TokenContract,
+            utils::{get_provider_with_fallback, NormalProvider},
+        },
+        intmax::event::get_deposit_events,
     },
     services::{
         assets_status::{fetch_assets_status, AssetsStatus},
@@ -23,50 +33,88 @@ pub struct State {
     pub short_term_eligible_tree: EligibleTreeWithMap,
     pub long_term_eligible_tree: EligibleTreeWithMap,
     pub last_tree_fetched_at: NaiveDateTime,
-    pub prover: Prover,
+    pub prover: Arc<Prover>,
+
+    // in light client mode, the trees keep only the proofs of these deposits
+    pub light_client: bool,
+    pub light_client_deposits: HashSet<Bytes32>,
 
     // block chain state
     pub int1: Int1Contract,
     pub minter: MinterContract,
     pub token: TokenContract,
     pub provider: NormalProvider,
+
+    // configured RPC URLs and the subset currently used by the provider
+    pub rpc_urls: Vec<String>,
+    pub active_rpc_urls: Vec<String>,
+    pub last_rpc_health_checked_at: NaiveDateTime,
 }
 
 impl State {
-    pub fn new(rpc_url: &str) -> Self {
-        let settings = Settings::load().unwrap();
-        let provider = get_provider(rpc_url).unwrap();
-        let int1 = Int1Contract::new(
-            provider.clone(),
-            settings.blockchain.int1_address.parse().unwrap(),
-        );
-        let minter = MinterContract::new(
-            provider.clone(),
-            settings.blockchain.minter_address.parse().unwrap(),
-        );
-        let token = TokenContract::new(
-            provider.clone(),
-            settings.blockchain.token_address.parse().unwrap(),
-        );
+    pub async fn new(rpc_urls: &[String]) -> anyhow::Result<Self> {
+        let provider = get_provider_with_fallback(rpc_urls).await?;
+        let (int1, minter, token) = connect_contracts(&provider);
+        let light_client = Settings::load()?.service.light_client;
 
-        Self {
+        Ok(Self {
             deposit_hash_tree: DepositHashTree::new(),
             short_term_eligible_tree: EligibleTreeWithMap::new(),
             long_term_eligible_tree: EligibleTreeWithMap::new(),
             last_tree_fetched_at: NaiveDateTime::default(),
-            prover: Prover::new(),
+            prover: Arc::new(Prover::new()),
+            light_client,
+            light_client_deposits: HashSet::new(),
             int1,
             minter,
             token,
             provider,
+            rpc_urls: rpc_urls.to_vec(),
+            active_rpc_urls: rpc_urls.to_vec(),
+            last_rpc_health_checked_at: NaiveDateTime::default(),
+        })
+    }
+
+    /// Re-checks the configured RPC endpoints at most once per `rpc_health_check_interval_in_sec`
+    /// and reconnects if the set of healthy endpoints has changed.
+    pub async fn refresh_rpc_health(&mut self) -> anyhow::Result<()> {
+        let interval = Settings::load()?.service.rpc_health_check_interval_in_sec;
+        let now = chrono::Utc::now().naive_utc();
+        if now - self.last_rpc_health_checked_at < chrono::Duration::seconds(interval as i64) {
+            return Ok(());
+        }
+        self.last_rpc_health_checked_at = now;
+        let healthy_rpc_urls = match select_healthy_rpc_urls(&self.rpc_urls).await {
+            Ok(urls) => urls,
+            Err(e) => {
+                // keep the current endpoints rather than having none
+                log::warn!(
+                    "RPC health check failed, keeping the current endpoints: {}",
+                    e
+                );
+                return Ok(());
+            }
+        };
+        if healthy_rpc_urls != self.active_rpc_urls {
+            log::info!(
+                "Switching to {} of {} RPC endpoints",
+                healthy_rpc_urls.len(),
+                self.rpc_urls.len()
+            );
+            self.provider = get_provider_with_fallback(&healthy_rpc_urls).await?;
+            (self.int1, self.minter, self.token) = connect_contracts(&self.provider);
+            self.active_rpc_urls = healthy_rpc_urls;
         }
+        Ok(())
     }
 
     pub async fn sync_trees(&mut self) -> anyhow::Result<()> {
+        self.refresh_rpc_health().await?;
         sync_trees(
             &self.int1,
             &self.minter,
             &mut self.last_tree_fetched_at,
+            self.light_client.then_some(&self.light_client_deposits),
             &mut self.deposit_hash_tree,
             &mut self.short_term_eligible_tree,
             &mut self.long_term_eligible_tree,
@@ -76,7 +124,50 @@ impl State {
     }
 
     pub async fn sync_and_fetch_assets(&mut self, key: &Key) -> anyhow::Result<AssetsStatus> {
+        if self.light_client {
+            let events = get_deposit_events(&self.provider, key.deposit_address).await?;
+            self.track_deposits(events.iter().map(|event| event.deposit().hash()));
+        }
         self.sync_trees().await?;
-        fetch_assets_status(self, key.deposit_address, key.deposit_private_key).await
+        let assets_status =
+            fetch_assets_status(self, key.deposit_address, key.deposit_private_key).await?;
+        if !assets_status.not_withdrawn_indices.is_empty() {
+            self.prover.warm_up(ProverCircuit::Withdrawal);
+        }
+        if !assets_status.short_term_not_claimed_indices.is_empty()
+            || !assets_status.long_term_not_claimed_indices.is_empty()
+        {
+            self.prover.warm_up(ProverCircuit::Claim);
+        }
+        Ok(assets_status)
+    }
+
+    /// Adds the deposits to those kept by the light trees. The trees are rebuilt from the
+    /// latest tree files on the next sync if any deposit is new.
+    fn track_deposits(&mut self, hashes: impl IntoIterator<Item = Bytes32>) {
+        let mut is_new = false;
+        for hash in hashes {
+            is_new |= self.light_client_deposits.insert(hash);
+        }
+        if is_new {
+            self.last_tree_fetched_at = NaiveDateTime::default();
+        }
     }
 }
+
+fn connect_contracts(provider: &NormalProvider) -> (Int1Contract, MinterContract, TokenContract) {
+    let settings = Settings::load().unwrap();
+    let int1 = Int1Contract::new(
+        provider.clone(),
+        settings.blockchain.int1_address.parse().unwrap(),
+    );
+    let minter = MinterContract::new(
+        provider.clone(),
+        settings.blockchain.minter_address.parse().unwrap(),
+    );
+    let token = TokenContract::new(
+        provider.clone(),
+        settings.blockchain.token_address.parse().unwrap(),
+    );
+    (int1, minter, token)
+}
@@ -1,3 +1,5 @@
+use std::{collections::HashSet, sync::Arc};
+
 use alloy::primitives::B256;
 use chrono::NaiveDateTime;
 use intmax2_zkp::ethereum_types::u256::U256;
@@ -32,10 +34,12 @@ pub fn get_dummy_keys() -> Key {
 pub async fn get_dummy_state(rpc_url: &str) -> State {
     let mut eligible_tree = EligibleTreeWithMap::new();
     for i in 0..100 {
-        eligible_tree.push(EligibleLeaf {
-            deposit_index: i,
-            amount: U256::try_from(BigUint::from(10u32).pow(18)).unwrap(),
-        });
+        eligible_tree
+            .push(EligibleLeaf {
+                deposit_index: i,
+                amount: U256::try_from(BigUint::from(10u32).pow(18)).unwrap(),
+            })
+            .unwrap();
     }
     let settings = crate::utils::config::Settings::load().unwrap();
     let provider = get_provider(rpc_url).unwrap();
@@ -57,10 +61,15 @@ pub async fn get_dummy_state(rpc_url: &str) -> State {
         short_term_eligible_tree: eligible_tree.clone(),
         long_term_eligible_tree: eligible_tree.clone(),
         last_tree_fetched_at: NaiveDateTime::default(),
-        prover: Prover::new(),
+        prover: Arc::new(Prover::new()),
+        light_client: false,
+        light_client_deposits: HashSet::new(),
         int1,
         minter,
         token,
         provider,
+        rpc_urls: vec![rpc_url.to_string()],
+        active_rpc_urls: vec![rpc_url.to_string()],
+        last_rpc_health_checked_at: NaiveDateTime::default(),
     }
 }
@@ -1,15 +1,34 @@
-use std::time::Instant;
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    marker::PhantomData,
+    path::Path,
+    time::Instant,
+};
 
 use anyhow::ensure;
+use bincode::Options as _;
 use intmax2_zkp::{
     constants::DEPOSIT_TREE_HEIGHT,
     ethereum_types::{bytes32::Bytes32, u256::U256, u32limb_trait::U32LimbTrait},
+    utils::leafable::Leafable,
 };
 use mining_circuit_v1::eligible_tree::{EligibleLeaf, ELIGIBLE_TREE_HEIGHT};
 use num_bigint::BigUint;
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor},
+    Deserialize, Deserializer as _, Serialize,
+};
 
-use super::{deposit_hash_tree::DepositHashTree, eligible_tree_with_map::EligibleTreeWithMap};
+use super::{
+    bulk_merkle_tree::BulkMerkleTree,
+    deposit_hash_tree::{DepositHash, DepositHashTree},
+    eligible_tree_with_map::EligibleTreeWithMap,
+    light_merkle_tree::HashOut,
+    progress::progress_bar,
+};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -39,50 +58,94 @@ impl TryFrom<BinEligibleTree> for EligibleTreeInfo {
 
     fn try_from(bin_tree: BinEligibleTree) -> anyhow::Result<Self> {
         let instant = Instant::now();
-        let mut tree = EligibleTreeWithMap::new();
-        for leaf in &bin_tree.leaves {
-            let amount: U256 = BigUint::from_bytes_le(&leaf.amount).try_into()?;
-            tree.push(EligibleLeaf {
-                deposit_index: leaf.deposit_index,
-                amount,
-            });
-        }
+        let leaves = bin_tree
+            .leaves
+            .iter()
+            .map(BinEligibleLeaf::to_eligible_leaf)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let tree = build_tree(ELIGIBLE_TREE_HEIGHT, leaves, "Building eligible tree")?;
         log::info!(
-            "Inserting {} eligible leaves took {:?}",
+            "Building eligible tree of {} leaves took {:?}",
             bin_tree.leaves.len(),
             instant.elapsed()
         );
-        ensure!(tree.tree.height() == ELIGIBLE_TREE_HEIGHT);
-
-        ensure!(
-            bin_tree.tree_height == ELIGIBLE_TREE_HEIGHT as u32,
-            "Tree height mismatch: expected {}, got {}",
+        // check the root before indexing the leaves
+        let root = check_root(
+            &bin_tree.root_hash,
+            bin_tree.tree_height,
             ELIGIBLE_TREE_HEIGHT,
-            bin_tree.tree_height
-        );
+            tree.get_root().into(),
+        )?;
+        Ok(Self {
+            root,
+            block_number: bin_tree.block_number,
+            tree: EligibleTreeWithMap::from_tree(tree),
+        })
+    }
+}
+
+impl EligibleTreeInfo {
+    /// Parses the tree file keeping only the leaves and proofs of `tracked_deposit_indices`.
+    pub fn from_bin_file_light(
+        path: &Path,
+        tracked_deposit_indices: &HashSet<u32>,
+    ) -> anyhow::Result<Self> {
+        Self::from_bin_light(BufReader::new(File::open(path)?), tracked_deposit_indices)
+    }
 
-        let expected_root = Bytes32::from_bytes_be(&bin_tree.root_hash);
-        let actual_root = tree.get_root();
-        ensure!(
-            actual_root == expected_root,
-            "Root hash mismatch: expected {}, got {}",
-            expected_root,
-            tree.get_root()
+    /// Parses a bincode [`BinEligibleTree`] keeping only the leaves and proofs of
+    /// `tracked_deposit_indices`. The leaves are read one at a time and never held together.
+    pub fn from_bin_light(
+        reader: impl Read,
+        tracked_deposit_indices: &HashSet<u32>,
+    ) -> anyhow::Result<Self> {
+        let instant = Instant::now();
+        let mut leaf_count = 0;
+        let (header, tree) = stream_bin_tree::<BinEligibleLeaf, _, _, _>(reader, |leaves| {
+            let leaves = leaves.map(|leaf| {
+                leaf_count += 1;
+                leaf?.to_eligible_leaf()
+            });
+            EligibleTreeWithMap::new_light(leaves, tracked_deposit_indices)
+        })?;
+        log::info!(
+            "Scanning {} eligible leaves for {} deposits took {:?}",
+            leaf_count,
+            tracked_deposit_indices.len(),
+            instant.elapsed()
         );
+        let root = check_root(
+            &header.root_hash,
+            header.tree_height,
+            ELIGIBLE_TREE_HEIGHT,
+            tree.get_root(),
+        )?;
         Ok(Self {
-            root: actual_root,
-            block_number: bin_tree.block_number,
+            root,
+            block_number: header.block_number,
             tree,
         })
     }
 }
 
-impl From<EligibleTreeInfo> for BinEligibleTree {
-    fn from(tree_info: EligibleTreeInfo) -> Self {
+impl BinEligibleLeaf {
+    fn to_eligible_leaf(&self) -> anyhow::Result<EligibleLeaf> {
+        let amount: U256 = BigUint::from_bytes_le(&self.amount).try_into()?;
+        Ok(EligibleLeaf {
+            deposit_index: self.deposit_index,
+            amount,
+        })
+    }
+}
+
+impl TryFrom<EligibleTreeInfo> for BinEligibleTree {
+    type Error = anyhow::Error;
+
+    fn try_from(tree_info: EligibleTreeInfo) -> anyhow::Result<Self> {
         let leaves: Vec<BinEligibleLeaf> = tree_info
-            .tree
             .tree
             .leaves()
+            .ok_or_else(|| anyhow::anyhow!("A light eligible tree has no leaves to serialize"))?
             .iter()
             .map(|leaf| BinEligibleLeaf {
                 deposit_index: leaf.deposit_index,
@@ -96,12 +159,12 @@ impl From<EligibleTreeInfo> for BinEligibleTree {
                     .unwrap(),
             })
             .collect();
-        Self {
+        Ok(Self {
             root_hash: tree_info.root.to_bytes_be().try_into().unwrap(),
             block_number: tree_info.block_number,
             tree_height: ELIGIBLE_TREE_HEIGHT as u32,
             leaves,
-        }
+        })
     }
 }
 
@@ -125,52 +188,274 @@ impl TryFrom<BinDepositTree> for DepositTreeInfo {
 
     fn try_from(bin_tree: BinDepositTree) -> anyhow::Result<Self> {
         let instant = Instant::now();
-        let mut tree = DepositHashTree::new();
-        for leaf_hash in &bin_tree.leaf_hashes {
-            let leaf_hash: Bytes32 = Bytes32::from_bytes_be(leaf_hash);
-            tree.push(leaf_hash);
-        }
+        let leaves = bin_tree
+            .leaf_hashes
+            .iter()
+            .map(|leaf_hash| DepositHash(Bytes32::from_bytes_be(leaf_hash)))
+            .collect();
+        let tree = build_tree(DEPOSIT_TREE_HEIGHT, leaves, "Building deposit tree")?;
         log::info!(
-            "Inserting {} deposit leaves took {:?}",
+            "Building deposit tree of {} leaves took {:?}",
             bin_tree.leaf_hashes.len(),
             instant.elapsed()
         );
-        ensure!(tree.tree.height() == DEPOSIT_TREE_HEIGHT);
-
-        let expected_root = Bytes32::from_bytes_be(&bin_tree.root_hash);
-        let actual_root = tree.get_root();
-        ensure!(
-            actual_root == expected_root,
-            "Root hash mismatch: expected {}, got {}",
-            expected_root,
-            tree.get_root()
-        );
-        ensure!(bin_tree.tree_height == DEPOSIT_TREE_HEIGHT as u32);
+        // check the root before indexing the leaves
+        let root = check_root(
+            &bin_tree.root_hash,
+            bin_tree.tree_height,
+            DEPOSIT_TREE_HEIGHT,
+            tree.get_root(),
+        )?;
         Ok(Self {
-            root: actual_root,
+            root,
             block_number: bin_tree.block_number,
+            tree: DepositHashTree::from_tree(tree),
+        })
+    }
+}
+
+impl DepositTreeInfo {
+    /// Parses the tree file keeping only the indices and proofs of `tracked_hashes`.
+    pub fn from_bin_file_light(
+        path: &Path,
+        tracked_hashes: &HashSet<Bytes32>,
+    ) -> anyhow::Result<Self> {
+        Self::from_bin_light(BufReader::new(File::open(path)?), tracked_hashes)
+    }
+
+    /// Parses a bincode [`BinDepositTree`] keeping only the indices and proofs of
+    /// `tracked_hashes`. The leaf hashes are read one at a time and never held together.
+    pub fn from_bin_light(
+        reader: impl Read,
+        tracked_hashes: &HashSet<Bytes32>,
+    ) -> anyhow::Result<Self> {
+        let instant = Instant::now();
+        let (header, tree) = stream_bin_tree::<[u8; 32], _, _, _>(reader, |leaf_hashes| {
+            let leaf_hashes = leaf_hashes
+                .map(|leaf_hash| leaf_hash.map(|leaf_hash| Bytes32::from_bytes_be(&leaf_hash)));
+            DepositHashTree::new_light(leaf_hashes, tracked_hashes)
+        })?;
+        log::info!(
+            "Scanning {} deposit leaves for {} deposits took {:?}",
+            tree.len(),
+            tracked_hashes.len(),
+            instant.elapsed()
+        );
+        let root = check_root(
+            &header.root_hash,
+            header.tree_height,
+            DEPOSIT_TREE_HEIGHT,
+            tree.get_root(),
+        )?;
+        Ok(Self {
+            root,
+            block_number: header.block_number,
             tree,
         })
     }
 }
 
-impl From<DepositTreeInfo> for BinDepositTree {
-    fn from(tree_info: DepositTreeInfo) -> Self {
+impl TryFrom<DepositTreeInfo> for BinDepositTree {
+    type Error = anyhow::Error;
+
+    fn try_from(tree_info: DepositTreeInfo) -> anyhow::Result<Self> {
         let leaf_hashes: Vec<[u8; 32]> = tree_info
-            .tree
             .tree
             .leaves()
+            .ok_or_else(|| anyhow::anyhow!("A light deposit tree has no leaves to serialize"))?
             .iter()
             .map(|leaf_hash| {
-                let leaf_hash: [u8; 32] = leaf_hash.0.to_bytes_be().try_into().unwrap();
+                let leaf_hash: [u8; 32] = leaf_hash.to_bytes_be().try_into().unwrap();
                 leaf_hash
             })
             .collect();
-        Self {
+        Ok(Self {
             root_hash: tree_info.root.to_bytes_be().try_into().unwrap(),
             block_number: tree_info.block_number,
             tree_height: DEPOSIT_TREE_HEIGHT as u32,
             leaf_hashes,
+        })
+    }
+}
+
+/// Deserializes a whole bincode tree file while reading it, without loading the file first.
+pub fn read_bin_file<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let file = File::open(path)?;
+    Ok(bincode::deserialize_from(BufReader::new(file))?)
+}
+
+// Fields of the tree files before the leaves, which come last
+struct BinTreeHeader {
+    root_hash: [u8; 32],
+    block_number: u64,
+    tree_height: u32,
+}
+
+// Deserializes a bincode tree file while handing its leaves one at a time to `build`
+fn stream_bin_tree<L, R, T, B>(reader: R, build: B) -> anyhow::Result<(BinTreeHeader, T)>
+where
+    L: DeserializeOwned,
+    R: Read,
+    B: FnOnce(&mut dyn Iterator<Item = anyhow::Result<L>>) -> anyhow::Result<T>,
+{
+    // the options of `bincode::deserialize_from`, which wrote the files
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    let mut deserializer = bincode::Deserializer::with_reader(reader, options);
+    let visitor = BinTreeVisitor {
+        build,
+        _leaf: PhantomData,
+    };
+    Ok((&mut deserializer).deserialize_tuple(4, visitor)?)
+}
+
+struct BinTreeVisitor<L, B> {
+    build: B,
+    _leaf: PhantomData<L>,
+}
+
+impl<'de, L, T, B> Visitor<'de> for BinTreeVisitor<L, B>
+where
+    L: DeserializeOwned,
+    B: FnOnce(&mut dyn Iterator<Item = anyhow::Result<L>>) -> anyhow::Result<T>,
+{
+    type Value = (BinTreeHeader, T);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tree with its root, block number, height and leaves")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let missing = |index| de::Error::invalid_length(index, &"4 fields");
+        let root_hash = seq.next_element()?.ok_or_else(|| missing(0))?;
+        let block_number = seq.next_element()?.ok_or_else(|| missing(1))?;
+        let tree_height = seq.next_element()?.ok_or_else(|| missing(2))?;
+        let output = seq.next_element_seed(self)?.ok_or_else(|| missing(3))?;
+        let header = BinTreeHeader {
+            root_hash,
+            block_number,
+            tree_height,
+        };
+        Ok((header, output))
+    }
+}
+
+// the same visitor deserializes the leaves, streaming them into `build`
+impl<'de, L, T, B> DeserializeSeed<'de> for BinTreeVisitor<L, B>
+where
+    L: DeserializeOwned,
+    B: FnOnce(&mut dyn Iterator<Item = anyhow::Result<L>>) -> anyhow::Result<T>,
+{
+    type Value = T;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        deserializer.deserialize_seq(LeavesVisitor(self))
+    }
+}
+
+struct LeavesVisitor<L, B>(BinTreeVisitor<L, B>);
+
+impl<'de, L, T, B> Visitor<'de> for LeavesVisitor<L, B>
+where
+    L: DeserializeOwned,
+    B: FnOnce(&mut dyn Iterator<Item = anyhow::Result<L>>) -> anyhow::Result<T>,
+{
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of leaves")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut leaves = std::iter::from_fn(|| {
+            seq.next_element::<L>()
+                .map_err(|e| anyhow::anyhow!("Failed to read a leaf: {}", e))
+                .transpose()
+        });
+        (self.0.build)(&mut leaves).map_err(|e| de::Error::custom(format!("{:#}", e)))
+    }
+}
+
+fn build_tree<V>(height: usize, leaves: Vec<V>, message: &str) -> anyhow::Result<BulkMerkleTree<V>>
+where
+    V: Leafable + Send + Sync,
+    HashOut<V>: Send + Sync,
+{
+    let progress = progress_bar(
+        BulkMerkleTree::<V>::node_count(height, leaves.len() as u64),
+        message,
+    );
+    let tree = BulkMerkleTree::from_leaves(height, leaves, |nodes| progress.inc(nodes));
+    progress.finish_and_clear();
+    tree
+}
+
+fn check_root(
+    expected_root: &[u8; 32],
+    tree_height: u32,
+    expected_height: usize,
+    actual_root: Bytes32,
+) -> anyhow::Result<Bytes32> {
+    ensure!(
+        tree_height == expected_height as u32,
+        "Tree height mismatch: expected {}, got {}",
+        expected_height,
+        tree_height
+    );
+    let expected_root = Bytes32::from_bytes_be(expected_root);
+    ensure!(
+        actual_root == expected_root,
+        "Root hash mismatch: expected {}, got {}",
+        expected_root,
+        actual_root
+    );
+    Ok(actual_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use intmax2_zkp::{
+        common::deposit::Deposit,
+        constants::DEPOSIT_TREE_HEIGHT,
+        ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _},
+        utils::leafable::Leafable as _,
+    };
+
+    use super::{BinDepositTree, DepositTreeInfo};
+    use crate::utils::deposit_hash_tree::DepositHashTree;
+
+    #[test]
+    fn test_deposit_tree_from_bin_light() {
+        let mut rng = rand::thread_rng();
+        let mut full_tree = DepositHashTree::new();
+        let mut leaf_hashes = Vec::new();
+        for _ in 0..10 {
+            let hash = Deposit::rand(&mut rng).hash();
+            full_tree.push(hash).unwrap();
+            leaf_hashes.push(hash.to_bytes_be().try_into().unwrap());
+        }
+        let tracked = HashSet::from([Bytes32::from_bytes_be(&leaf_hashes[3])]);
+        let bin_tree = BinDepositTree {
+            root_hash: full_tree.get_root().to_bytes_be().try_into().unwrap(),
+            block_number: 1,
+            tree_height: DEPOSIT_TREE_HEIGHT as u32,
+            leaf_hashes,
+        };
+        let bytes = bincode::serialize(&bin_tree).unwrap();
+
+        let info = DepositTreeInfo::from_bin_light(bytes.as_slice(), &tracked).unwrap();
+        assert_eq!(info.root, full_tree.get_root());
+        assert_eq!(info.block_number, 1);
+        assert_eq!(info.tree.len(), 10);
+        for hash in &tracked {
+            assert_eq!(info.tree.get_index(*hash), Some(3));
         }
+
+        // a truncated file fails instead of giving a partial tree
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(DepositTreeInfo::from_bin_light(truncated, &tracked).is_err());
     }
 }
@@ -0,0 +1,199 @@
+use anyhow::ensure;
+use intmax2_zkp::utils::{leafable::Leafable, leafable_hasher::LeafableHasher};
+use rayon::prelude::*;
+
+use super::light_merkle_tree::HashOut;
+
+/// Merkle tree that stores the non-empty nodes of each level, so that it can be built from all
+/// leaves at once, hashing each level in parallel.
+#[derive(Debug, Clone)]
+pub struct BulkMerkleTree<V: Leafable> {
+    height: usize,
+    leaves: Vec<V>,
+    // levels[0] are the leaf hashes and levels[height] the root. Nodes right of the last leaf
+    // cover only empty leaves and are not stored.
+    levels: Vec<Vec<HashOut<V>>>,
+    // hash of an empty subtree at each level
+    empty_hashes: Vec<HashOut<V>>,
+}
+
+impl<V> BulkMerkleTree<V>
+where
+    V: Leafable + Send + Sync,
+    HashOut<V>: Send + Sync,
+{
+    pub fn new(height: usize) -> Self {
+        Self::from_leaves(height, Vec::new(), |_| {}).unwrap()
+    }
+
+    /// Builds the tree from the leaves. `progress` is called with the number of nodes hashed
+    /// after each level, adding up to `node_count`.
+    pub fn from_leaves(
+        height: usize,
+        leaves: Vec<V>,
+        progress: impl Fn(u64),
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            leaves.len() as u64 <= 1 << height,
+            "{} leaves do not fit in a merkle tree of height {}",
+            leaves.len(),
+            height
+        );
+        let mut empty_hashes = vec![V::empty_leaf().hash()];
+        for level in 0..height {
+            let h = empty_hashes[level].clone();
+            empty_hashes.push(V::LeafableHasher::two_to_one(h.clone(), h));
+        }
+        let mut levels = Vec::with_capacity(height + 1);
+        levels.push(
+            leaves
+                .par_iter()
+                .map(|leaf| leaf.hash())
+                .collect::<Vec<_>>(),
+        );
+        progress(leaves.len() as u64);
+        for level in 0..height {
+            let empty = &empty_hashes[level];
+            let parents = levels[level]
+                .par_chunks(2)
+                .map(|pair| {
+                    let right = pair.get(1).cloned().unwrap_or_else(|| empty.clone());
+                    V::LeafableHasher::two_to_one(pair[0].clone(), right)
+                })
+                .collect::<Vec<_>>();
+            progress(parents.len() as u64);
+            levels.push(parents);
+        }
+        Ok(Self {
+            height,
+            leaves,
+            levels,
+            empty_hashes,
+        })
+    }
+
+    /// Number of nodes `from_leaves` hashes for `len` leaves.
+    pub fn node_count(height: usize, len: u64) -> u64 {
+        (0..=height).map(|level| len.div_ceil(1 << level)).sum()
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Appends the leaf, rehashing only its path to the root.
+    pub fn push(&mut self, leaf: V) {
+        let mut index = self.leaves.len();
+        assert!(
+            (index as u64) < 1 << self.height,
+            "merkle tree of height {} is full",
+            self.height
+        );
+        let mut hash = leaf.hash();
+        self.leaves.push(leaf);
+        for level in 0..=self.height {
+            let nodes = &mut self.levels[level];
+            if index < nodes.len() {
+                nodes[index] = hash.clone();
+            } else {
+                nodes.push(hash.clone());
+            }
+            if level == self.height {
+                break;
+            }
+            // the new leaf is the last one, so a right sibling is always empty
+            hash = if index & 1 == 0 {
+                V::LeafableHasher::two_to_one(hash, self.empty_hashes[level].clone())
+            } else {
+                V::LeafableHasher::two_to_one(nodes[index - 1].clone(), hash)
+            };
+            index >>= 1;
+        }
+    }
+
+    pub fn get_root(&self) -> HashOut<V> {
+        self.levels[self.height]
+            .first()
+            .cloned()
+            .unwrap_or_else(|| self.empty_hashes[self.height].clone())
+    }
+
+    pub fn get_leaf(&self, index: usize) -> V {
+        self.leaves
+            .get(index)
+            .cloned()
+            .unwrap_or_else(V::empty_leaf)
+    }
+
+    pub fn leaves(&self) -> &[V] {
+        &self.leaves
+    }
+
+    /// Siblings of the leaf at `index` from the bottom.
+    pub fn prove(&self, index: usize) -> Vec<HashOut<V>> {
+        (0..self.height)
+            .map(|level| {
+                self.levels[level]
+                    .get((index >> level) ^ 1)
+                    .cloned()
+                    .unwrap_or_else(|| self.empty_hashes[level].clone())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _},
+        utils::trees::incremental_merkle_tree::IncrementalMerkleTree,
+    };
+
+    use crate::utils::deposit_hash_tree::DepositHash;
+
+    use super::BulkMerkleTree;
+
+    #[test]
+    fn test_bulk_merkle_tree() {
+        let mut rng = rand::thread_rng();
+        let height = 8;
+        for n in [0, 1, 2, 3, 7, 100, 256] {
+            let leaves = (0..n)
+                .map(|_| DepositHash(Bytes32::rand(&mut rng)))
+                .collect::<Vec<_>>();
+            let mut tree = IncrementalMerkleTree::<DepositHash>::new(height);
+            for leaf in &leaves {
+                tree.push(leaf.clone());
+            }
+            let hashed = std::cell::Cell::new(0);
+            let bulk_tree = BulkMerkleTree::from_leaves(height, leaves.clone(), |nodes| {
+                hashed.set(hashed.get() + nodes)
+            })
+            .unwrap();
+            assert_eq!(
+                hashed.get(),
+                BulkMerkleTree::<DepositHash>::node_count(height, n as u64)
+            );
+            assert_eq!(bulk_tree.get_root(), tree.get_root());
+
+            let mut pushed_tree = BulkMerkleTree::new(height);
+            for leaf in leaves {
+                pushed_tree.push(leaf);
+            }
+            assert_eq!(pushed_tree.get_root(), tree.get_root());
+            for index in (0..n).step_by(7) {
+                let siblings = tree.prove(index).0.siblings;
+                assert_eq!(bulk_tree.prove(index), siblings);
+                assert_eq!(pushed_tree.prove(index), siblings);
+            }
+        }
+    }
+}
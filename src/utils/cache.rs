@@ -4,7 +4,7 @@ use anyhow::Context as _;
 
 use super::file::get_data_path;
 
-/// Removes the cached GitHub responses stored under `~/.mining-cli/github_cache`.
+/// Removes the cached GitHub responses stored under `github_cache` in the data directory.
 /// Returns `Ok(true)` when the cache directory existed and was removed.
 pub fn clear_github_cache() -> anyhow::Result<bool> {
     let cache_dir = get_data_path()?.join("github_cache");
@@ -1,6 +1,10 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
-use config::{Config, File};
+use config::{builder::DefaultState, Config, ConfigBuilder, File, FileFormat, Map, Value};
 use serde::{Deserialize, Serialize};
 
 use crate::utils::network::get_network;
@@ -11,10 +15,52 @@ use super::{
     network::Network,
 };
 
-fn config_path(network: Network) -> PathBuf {
-    get_data_path()
-        .unwrap()
-        .join(format!("config.{}.toml", network))
+/// Prefix of the environment variables overriding settings, followed by the section and the
+/// key, e.g. `MINING_CLI__API__EVENT_SERVER_URL`. Lists are comma separated.
+pub const SETTINGS_ENV_PREFIX: &str = "MINING_CLI__";
+
+// copies of the defaults written by earlier versions on every start
+fn legacy_config_path(data_path: &Path, network: Network) -> PathBuf {
+    data_path.join(format!("config.{}.toml", network))
+}
+
+/// File of the user's overrides of the default settings, which is never overwritten. Localnet
+/// has no defaults of its own, so its file is `config.localnet.toml` as in earlier versions and
+/// must set the contract addresses and URLs.
+pub fn user_config_path(network: Network) -> PathBuf {
+    user_config_path_in(&get_data_path().unwrap(), network)
+}
+
+fn user_config_path_in(data_path: &Path, network: Network) -> PathBuf {
+    let file_name = match network {
+        Network::Localnet => "config.localnet.toml".to_string(),
+        network => format!("config.{}.user.toml", network),
+    };
+    data_path.join(file_name)
+}
+
+// what the settings are read from besides the defaults, taken from the process unless a test
+// passes its own
+struct SettingsSources {
+    data_path: PathBuf,
+    env_vars: Vec<(String, String)>,
+}
+
+impl SettingsSources {
+    fn current() -> anyhow::Result<Self> {
+        Ok(Self {
+            data_path: get_data_path()?,
+            env_vars: std::env::vars().collect(),
+        })
+    }
+
+    fn user_config_path(&self, network: Network) -> PathBuf {
+        user_config_path_in(&self.data_path, network)
+    }
+
+    fn env_overrides(&self) -> Vec<(String, String, String)> {
+        env_overrides_in(self.env_vars.iter().cloned())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -22,6 +68,9 @@ pub struct Settings {
     pub api: Api,
     pub blockchain: Blockchain,
     pub service: Service,
+    pub gas_policy: GasPolicy,
+    pub gnark: Gnark,
+    pub prover: ProverSettings,
     pub env: Env,
 }
 
@@ -38,6 +87,7 @@ pub struct Api {
     pub gnark_get_proof_cooldown_in_sec: u64,
     pub withdrawal_server_url: String,
     pub event_server_url: String,
+    pub plonky2_prover_url: String,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -49,6 +99,12 @@ pub struct Blockchain {
     pub int1_deployed_block: u64,
     pub single_deposit_gas: u64,
     pub single_claim_gas: u64,
+    pub single_token_transfer_gas: u64,
+    // calldata sizes in bytes, used to estimate the L1 data fee on OP-stack chains
+    pub single_deposit_calldata_size: usize,
+    pub single_claim_calldata_size: usize,
+    pub single_token_transfer_calldata_size: usize,
+    pub max_rpc_block_lag: u64, // endpoints further behind the highest block are not used
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -65,13 +121,71 @@ pub struct Service {
     pub mining_max_cooldown_in_sec: u64,
     pub loop_cooldown_in_sec: u64,
     pub high_gas_retry_interval_in_sec: u64,
+    pub rpc_health_check_interval_in_sec: u64,
+    pub max_parallel_jobs: usize,
+    pub light_client: bool,
+    pub lock_withdrawal_address: bool,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GasPriceSource {
+    Instant,    // eth_gasPrice
+    FeeHistory, // eth_feeHistory over recent blocks
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnGasDeadline {
+    Prompt, // ask whether to send anyway, skip if not attended
+    Skip,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GasPolicy {
+    pub price_source: GasPriceSource,
+    pub fee_history_block_count: u64,
+    pub fee_history_reward_percentile: f64,
+    pub max_wait_in_sec: u64, // 0 waits forever
+    pub on_deadline: OnGasDeadline,
+    pub allowed_time_windows: Vec<String>, // local time "HH:MM-HH:MM", empty allows any time
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GnarkBackendKind {
+    Http,    // the gateway or a self-hosted prover with the same JSON protocol
+    Command, // a locally installed wrapper command
+    Stub,    // dummy proofs for offline testing, localnet only
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Gnark {
+    pub backend: GnarkBackendKind,
+    // tried in order after the `api` prover URLs when they fail
+    pub extra_withdrawal_prover_urls: Vec<String>,
+    pub extra_claim_prover_urls: Vec<String>,
+    pub command: String,
+    pub max_wait_in_sec: u64,
+    pub max_poll_interval_in_sec: u64,
+    pub max_resubmissions: u32,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProverSettings {
+    pub worker_listen_address: String,
+    pub low_memory: bool,
 }
 
 impl Settings {
+    /// Loads the embedded defaults of the network, overridden by the user config file and then
+    /// by `MINING_CLI__` environment variables.
     pub fn load() -> anyhow::Result<Self> {
-        let s = Config::builder()
-            .add_source(File::from(config_path(get_network())))
-            .build()?;
+        Self::load_network(get_network(), &SettingsSources::current()?)
+    }
+
+    fn load_network(network: Network, sources: &SettingsSources) -> anyhow::Result<Self> {
+        let s = settings_builder(network, sources)?.build()?;
         let s = s
             .try_deserialize()
             .map_err(|e| CLIError::ParseError(format!("Failed to parse config: {:?}", e)))?;
@@ -79,20 +193,253 @@ impl Settings {
     }
 }
 
-const BASE_CONFIG: &[u8] = include_bytes!("../../config/config.base.toml");
-const MAINNET_CONFIG: &[u8] = include_bytes!("../../config/config.mainnet.toml");
+const BASE_CONFIG: &str = include_str!("../../config/config.base.toml");
+const MAINNET_CONFIG: &str = include_str!("../../config/config.mainnet.toml");
 
+// localnet starts from the Base defaults, and its user config file sets the addresses
+fn default_config(network: Network) -> &'static str {
+    match network {
+        Network::Mainnet => MAINNET_CONFIG,
+        Network::Base | Network::Localnet => BASE_CONFIG,
+    }
+}
+
+fn settings_builder(
+    network: Network,
+    sources: &SettingsSources,
+) -> anyhow::Result<ConfigBuilder<DefaultState>> {
+    let user_config_path = sources.user_config_path(network);
+    if network == Network::Localnet && !user_config_path.exists() {
+        anyhow::bail!(
+            "Localnet settings not found. Write the contract addresses and URLs of the local network to {:?}",
+            user_config_path
+        );
+    }
+    let defaults = Config::builder()
+        .add_source(File::from_str(default_config(network), FileFormat::Toml))
+        .build()?;
+    let mut builder = Config::builder()
+        .add_source(File::from_str(default_config(network), FileFormat::Toml))
+        .add_source(File::from(user_config_path).required(false));
+    for (key, value, _) in sources.env_overrides() {
+        builder = if defaults.get_array(&key).is_ok() {
+            let list = value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect::<Vec<_>>();
+            builder.set_override(key, list)?
+        } else {
+            builder.set_override(key, value)?
+        };
+    }
+    Ok(builder)
+}
+
+/// Settings overridden by environment variables, as the dotted key, the value and the name of
+/// the variable.
+pub fn env_overrides() -> Vec<(String, String, String)> {
+    env_overrides_in(std::env::vars())
+}
+
+fn env_overrides_in(
+    env_vars: impl Iterator<Item = (String, String)>,
+) -> Vec<(String, String, String)> {
+    env_vars
+        .filter_map(|(name, value)| {
+            let key = name
+                .strip_prefix(SETTINGS_ENV_PREFIX)?
+                .to_lowercase()
+                .replace("__", ".");
+            Some((key, value, name))
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SettingSource {
+    Default,
+    UserFile(PathBuf),
+    Env(String), // name of the variable
+}
+
+impl Display for SettingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingSource::Default => write!(f, "default"),
+            SettingSource::UserFile(path) => write!(f, "{}", path.display()),
+            SettingSource::Env(name) => write!(f, "env {}", name),
+        }
+    }
+}
+
+/// Every setting of the network as `section.key`, its effective value as JSON and where the
+/// value was set, sorted by key.
+pub fn effective_settings(
+    network: Network,
+) -> anyhow::Result<Vec<(String, String, SettingSource)>> {
+    effective_settings_in(network, &SettingsSources::current()?)
+}
+
+fn effective_settings_in(
+    network: Network,
+    sources: &SettingsSources,
+) -> anyhow::Result<Vec<(String, String, SettingSource)>> {
+    let settings = Settings::load_network(network, sources)?;
+    let mut values = BTreeMap::new();
+    flatten_json("", serde_json::to_value(&settings)?, &mut values);
+
+    let user_config_path = sources.user_config_path(network);
+    let mut user_keys = Vec::new();
+    if user_config_path.exists() {
+        let user_config = Config::builder()
+            .add_source(File::from(user_config_path.clone()))
+            .build()?;
+        flatten_keys("", user_config.collect()?, &mut user_keys);
+    }
+    let env = sources.env_overrides();
+    Ok(values
+        .into_iter()
+        .map(|(key, value)| {
+            let env_name = env
+                .iter()
+                .find(|(k, _, _)| *k == key)
+                .map(|(_, _, name)| name);
+            let source = if let Some(name) = env_name {
+                SettingSource::Env(name.clone())
+            } else if user_keys.contains(&key) {
+                SettingSource::UserFile(user_config_path.clone())
+            } else {
+                SettingSource::Default
+            };
+            (key, value, source)
+        })
+        .collect())
+}
+
+fn flatten_json(prefix: &str, value: serde_json::Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                flatten_json(&join_key(prefix, &key), value, out);
+            }
+        }
+        value => {
+            out.insert(prefix.to_string(), value.to_string());
+        }
+    }
+}
+
+fn flatten_keys(prefix: &str, table: Map<String, Value>, out: &mut Vec<String>) {
+    for (key, value) in table {
+        let key = join_key(prefix, &key);
+        match value.clone().into_table() {
+            Ok(table) => flatten_keys(&key, table, out),
+            Err(_) => out.push(key),
+        }
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Creates the user config file of each network if it does not exist, and removes the copies
+/// of the defaults written by earlier versions, which were replaced on every start and so never
+/// held any edits.
 pub fn create_config_files() -> anyhow::Result<()> {
-    create_file_with_content(&config_path(Network::Base), BASE_CONFIG)?;
-    create_file_with_content(&config_path(Network::Mainnet), MAINNET_CONFIG)?;
+    create_config_files_in(&get_data_path()?)
+}
+
+fn create_config_files_in(data_path: &Path) -> anyhow::Result<()> {
+    for network in [Network::Base, Network::Mainnet] {
+        let legacy_path = legacy_config_path(data_path, network);
+        if legacy_path.exists() {
+            std::fs::remove_file(&legacy_path)?;
+        }
+        let path = user_config_path_in(data_path, network);
+        if !path.exists() {
+            create_file_with_content(&path, user_config_template(network).as_bytes())?;
+        }
+    }
     Ok(())
 }
 
+fn user_config_template(network: Network) -> String {
+    format!(
+        "# Overrides of the default settings on {}. The CLI never overwrites this file.\n\
+         # Copy the settings to change from `mining-cli config show --effective` into their\n\
+         # sections, e.g.\n\
+         #\n\
+         # [service]\n\
+         # max_parallel_jobs = 2\n",
+        network
+    )
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    // a data directory of the test's own, so that tests neither touch ~/.mining-cli nor see
+    // each other's environment
+    fn test_sources(name: &str, env_vars: &[(&str, &str)]) -> SettingsSources {
+        let data_path =
+            std::env::temp_dir().join(format!("mining-cli-config-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_path);
+        SettingsSources {
+            data_path,
+            env_vars: env_vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
 
     #[test]
     fn test_create_config_files() {
-        super::create_config_files().unwrap();
+        let sources = test_sources("create", &[]);
+        let legacy_path = legacy_config_path(&sources.data_path, Network::Base);
+        create_file_with_content(&legacy_path, b"[api]").unwrap();
+        create_config_files_in(&sources.data_path).unwrap();
+        assert!(!legacy_path.exists());
+        let user_config = std::fs::read_to_string(sources.user_config_path(Network::Base)).unwrap();
+        assert!(user_config.starts_with("# Overrides of the default settings on base."));
+        std::fs::remove_dir_all(&sources.data_path).unwrap();
+    }
+
+    #[test]
+    fn test_env_overrides_settings() {
+        let sources = test_sources(
+            "env",
+            &[(
+                "MINING_CLI__GNARK__EXTRA_CLAIM_PROVER_URLS",
+                "http://a, http://b",
+            )],
+        );
+        let settings = Settings::load_network(Network::Mainnet, &sources).unwrap();
+        assert_eq!(
+            settings.gnark.extra_claim_prover_urls,
+            vec!["http://a".to_string(), "http://b".to_string()]
+        );
+        let effective = effective_settings_in(Network::Mainnet, &sources).unwrap();
+        let (_, value, source) = effective
+            .iter()
+            .find(|(key, _, _)| key == "gnark.extra_claim_prover_urls")
+            .unwrap();
+        assert_eq!(value, r#"["http://a","http://b"]"#);
+        assert_eq!(
+            *source,
+            SettingSource::Env("MINING_CLI__GNARK__EXTRA_CLAIM_PROVER_URLS".to_string())
+        );
+        let (_, _, source) = effective
+            .iter()
+            .find(|(key, _, _)| key == "blockchain.chain_id")
+            .unwrap();
+        assert_eq!(*source, SettingSource::Default);
     }
 }
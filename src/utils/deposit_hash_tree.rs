@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use intmax2_zkp::{
     common::{deposit::Deposit, trees::deposit_tree::DepositMerkleProof},
@@ -7,13 +7,12 @@ use intmax2_zkp::{
     utils::{
         leafable::Leafable,
         leafable_hasher::KeccakLeafableHasher,
-        trees::{
-            incremental_merkle_tree::{IncrementalMerkleProof, IncrementalMerkleTree},
-            merkle_tree::MerkleProof,
-        },
+        trees::{incremental_merkle_tree::IncrementalMerkleProof, merkle_tree::MerkleProof},
     },
 };
 
+use super::{bulk_merkle_tree::BulkMerkleTree, light_merkle_tree::LightMerkleTree};
+
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct DepositHash(pub Bytes32);
 
@@ -30,9 +29,18 @@ impl Leafable for DepositHash {
 }
 
 #[derive(Debug, Clone)]
-pub struct DepositHashTree {
-    pub tree: IncrementalMerkleTree<DepositHash>,
-    pub hashes: HashMap<Bytes32, u32>,
+pub enum DepositHashTree {
+    Full {
+        tree: BulkMerkleTree<DepositHash>,
+        hashes: HashMap<Bytes32, u32>,
+    },
+    // keeps only the proofs of the tracked deposits, see `new_light`
+    Light {
+        root: Bytes32,
+        len: usize,
+        hashes: HashMap<Bytes32, u32>,
+        siblings: HashMap<u32, Vec<Bytes32>>,
+    },
 }
 
 impl Default for DepositHashTree {
@@ -43,39 +51,112 @@ impl Default for DepositHashTree {
 
 impl DepositHashTree {
     pub fn new() -> Self {
-        Self {
-            tree: IncrementalMerkleTree::new(DEPOSIT_TREE_HEIGHT),
-            hashes: HashMap::new(),
+        Self::from_tree(BulkMerkleTree::new(DEPOSIT_TREE_HEIGHT))
+    }
+
+    /// Indexes the leaves of a full tree. The first index of each hash is kept.
+    pub fn from_tree(tree: BulkMerkleTree<DepositHash>) -> Self {
+        let mut hashes = HashMap::with_capacity(tree.len());
+        for (index, leaf) in tree.leaves().iter().enumerate() {
+            hashes.entry(leaf.0).or_insert(index as u32);
         }
+        Self::Full { tree, hashes }
+    }
+
+    /// Builds the tree from the leaf hashes in one pass, keeping only the index and the proof of
+    /// the first occurrence of each hash in `tracked_hashes`. Stops at the first failed leaf.
+    pub fn new_light(
+        leaf_hashes: impl IntoIterator<Item = anyhow::Result<Bytes32>>,
+        tracked_hashes: &HashSet<Bytes32>,
+    ) -> anyhow::Result<Self> {
+        let mut tree = LightMerkleTree::<DepositHash>::new(DEPOSIT_TREE_HEIGHT);
+        let mut hashes = HashMap::new();
+        for hash in leaf_hashes {
+            let hash = hash?;
+            let track = tracked_hashes.contains(&hash) && !hashes.contains_key(&hash);
+            let index = tree.push(DepositHash(hash), track)?;
+            if track {
+                hashes.insert(hash, index as u32);
+            }
+        }
+        let proofs = tree.finish();
+        let siblings = proofs
+            .leaves
+            .into_iter()
+            .map(|(index, (_, siblings))| (index as u32, siblings))
+            .collect();
+        Ok(Self::Light {
+            root: proofs.root,
+            len: proofs.len as usize,
+            hashes,
+            siblings,
+        })
     }
 
     pub fn get_root(&self) -> Bytes32 {
-        self.tree.get_root()
+        match self {
+            Self::Full { tree, .. } => tree.get_root(),
+            Self::Light { root, .. } => *root,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Full { tree, .. } => tree.len(),
+            Self::Light { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    pub fn push(&mut self, hash: Bytes32) {
-        let index = self.tree.len();
-        self.tree.push(DepositHash(hash));
-        if let std::collections::hash_map::Entry::Vacant(e) = self.hashes.entry(hash) {
+    /// Appends a leaf to a full tree. Light trees are built at once with `new_light`.
+    pub fn push(&mut self, hash: Bytes32) -> anyhow::Result<()> {
+        let Self::Full { tree, hashes } = self else {
+            anyhow::bail!("Cannot push to a light deposit tree");
+        };
+        let index = tree.len();
+        tree.push(DepositHash(hash));
+        if let std::collections::hash_map::Entry::Vacant(e) = hashes.entry(hash) {
             e.insert(index as u32);
         } else {
             // don't overwrite the index if the hash already exists
         }
+        Ok(())
     }
 
     pub fn contains(&self, hash: Bytes32) -> bool {
-        self.hashes.contains_key(&hash)
+        self.get_index(hash).is_some()
     }
 
+    /// Index of the hash. Light trees only know the tracked hashes.
     pub fn get_index(&self, hash: Bytes32) -> Option<u32> {
-        self.hashes.get(&hash).copied()
+        match self {
+            Self::Full { hashes, .. } | Self::Light { hashes, .. } => hashes.get(&hash).copied(),
+        }
     }
 
-    pub fn prove(&self, index: u32) -> DepositMerkleProof {
-        let proof = self.tree.prove(index as usize);
-        IncrementalMerkleProof(MerkleProof {
-            siblings: proof.0.siblings,
-        })
+    /// Proof of the leaf at `index`, which must be a tracked leaf in a light tree.
+    pub fn prove(&self, index: u32) -> anyhow::Result<DepositMerkleProof> {
+        let siblings = match self {
+            Self::Full { tree, .. } => tree.prove(index as usize),
+            Self::Light { siblings, .. } => siblings
+                .get(&index)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Leaf {} is not tracked by the light deposit tree", index)
+                })?
+                .clone(),
+        };
+        Ok(IncrementalMerkleProof(MerkleProof { siblings }))
+    }
+
+    /// All leaf hashes of a full tree, or `None` for a light tree.
+    pub fn leaves(&self) -> Option<Vec<Bytes32>> {
+        match self {
+            Self::Full { tree, .. } => Some(tree.leaves().iter().map(|leaf| leaf.0).collect()),
+            Self::Light { .. } => None,
+        }
     }
 }
 
@@ -87,6 +168,7 @@ mod tests {
         utils::leafable::Leafable as _,
     };
     use rand::Rng as _;
+    use std::collections::HashSet;
 
     use super::DepositHashTree;
 
@@ -103,16 +185,47 @@ mod tests {
 
         let mut deposit_hash_tree = DepositHashTree::new();
         for deposit in &deposit_tree.leaves() {
-            deposit_hash_tree.push(deposit.hash());
+            deposit_hash_tree.push(deposit.hash()).unwrap();
         }
-        let root2 = deposit_hash_tree.tree.get_root();
+        let root2 = deposit_hash_tree.get_root();
         assert_eq!(root, root2);
 
         let rand_index = rng.gen_range(0..n);
         let deposit = deposit_tree.get_leaf(rand_index);
-        let proof = deposit_hash_tree.prove(rand_index as u32);
+        let proof = deposit_hash_tree.prove(rand_index as u32).unwrap();
         proof.verify(&deposit, rand_index, root).unwrap();
         let index = deposit_hash_tree.get_index(deposit.hash()).unwrap();
         assert_eq!(index, rand_index as u32);
     }
+
+    #[test]
+    fn test_light_deposit_hash_tree() {
+        let mut rng = rand::thread_rng();
+        let n = 10;
+
+        let mut deposit_hash_tree = DepositHashTree::new();
+        let mut hashes = Vec::new();
+        for _ in 0..n {
+            let hash = Deposit::rand(&mut rng).hash();
+            deposit_hash_tree.push(hash).unwrap();
+            hashes.push(hash);
+        }
+        let tracked = HashSet::from([hashes[2], hashes[7]]);
+        let light_tree =
+            DepositHashTree::new_light(hashes.iter().copied().map(Ok), &tracked).unwrap();
+        assert_eq!(light_tree.get_root(), deposit_hash_tree.get_root());
+        assert_eq!(light_tree.len(), n);
+        for hash in &tracked {
+            let index = light_tree.get_index(*hash).unwrap();
+            assert_eq!(Some(index), deposit_hash_tree.get_index(*hash));
+            assert_eq!(
+                light_tree.prove(index).unwrap().0.siblings,
+                deposit_hash_tree.prove(index).unwrap().0.siblings
+            );
+        }
+        assert_eq!(light_tree.get_index(hashes[0]), None);
+        // untracked leaves can't be proved, and light trees can't grow
+        assert!(light_tree.prove(0).is_err());
+        assert!(light_tree.clone().push(hashes[0]).is_err());
+    }
 }
@@ -1,12 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use intmax2_zkp::ethereum_types::bytes32::Bytes32;
-use mining_circuit_v1::eligible_tree::{EligibleLeaf, EligibleTree, ELIGIBLE_TREE_HEIGHT};
+use intmax2_zkp::{
+    ethereum_types::bytes32::Bytes32,
+    utils::trees::{incremental_merkle_tree::IncrementalMerkleProof, merkle_tree::MerkleProof},
+};
+use mining_circuit_v1::eligible_tree::{EligibleLeaf, ELIGIBLE_TREE_HEIGHT};
+
+use super::{
+    bulk_merkle_tree::BulkMerkleTree,
+    light_merkle_tree::{HashOut, LightMerkleTree},
+};
 
 #[derive(Debug, Clone)]
-pub struct EligibleTreeWithMap {
-    pub tree: EligibleTree,
-    pub map: HashMap<u32, u32>, // key: deposit_index, value: eligible index
+pub enum EligibleTreeWithMap {
+    Full {
+        tree: BulkMerkleTree<EligibleLeaf>,
+        map: HashMap<u32, u32>, // key: deposit_index, value: eligible index
+    },
+    // keeps only the leaves and proofs of the tracked deposits, see `new_light`
+    Light {
+        root: HashOut<EligibleLeaf>,
+        map: HashMap<u32, u32>, // key: deposit_index, value: eligible index
+        leaves: HashMap<u32, (EligibleLeaf, Vec<HashOut<EligibleLeaf>>)>,
+    },
 }
 
 impl Default for EligibleTreeWithMap {
@@ -17,22 +33,111 @@ impl Default for EligibleTreeWithMap {
 
 impl EligibleTreeWithMap {
     pub fn new() -> Self {
-        let tree = EligibleTree::new(ELIGIBLE_TREE_HEIGHT);
-        let map = HashMap::new();
-        Self { tree, map }
+        Self::from_tree(BulkMerkleTree::new(ELIGIBLE_TREE_HEIGHT))
+    }
+
+    /// Indexes the leaves of a full tree by deposit index.
+    pub fn from_tree(tree: BulkMerkleTree<EligibleLeaf>) -> Self {
+        let map = tree
+            .leaves()
+            .iter()
+            .enumerate()
+            .map(|(index, leaf)| (leaf.deposit_index, index as u32))
+            .collect();
+        Self::Full { tree, map }
     }
 
-    pub fn push(&mut self, leaf: EligibleLeaf) {
-        let leaf_index = self.tree.len() as u32;
-        self.map.insert(leaf.deposit_index, leaf_index);
-        self.tree.push(leaf);
+    /// Builds the tree from the leaves in one pass, keeping only the leaves and proofs of the
+    /// deposits in `tracked_deposit_indices`. Stops at the first failed leaf.
+    pub fn new_light(
+        leaves: impl IntoIterator<Item = anyhow::Result<EligibleLeaf>>,
+        tracked_deposit_indices: &HashSet<u32>,
+    ) -> anyhow::Result<Self> {
+        let mut tree = LightMerkleTree::<EligibleLeaf>::new(ELIGIBLE_TREE_HEIGHT);
+        let mut map = HashMap::new();
+        for leaf in leaves {
+            let leaf = leaf?;
+            let deposit_index = leaf.deposit_index;
+            let track = tracked_deposit_indices.contains(&deposit_index);
+            let leaf_index = tree.push(leaf, track)?;
+            if track {
+                map.insert(deposit_index, leaf_index as u32);
+            }
+        }
+        let proofs = tree.finish();
+        let leaves = proofs
+            .leaves
+            .into_iter()
+            .map(|(index, leaf)| (index as u32, leaf))
+            .collect();
+        Ok(Self::Light {
+            root: proofs.root,
+            map,
+            leaves,
+        })
+    }
+
+    /// Appends a leaf to a full tree. Light trees are built at once with `new_light`.
+    pub fn push(&mut self, leaf: EligibleLeaf) -> anyhow::Result<()> {
+        let Self::Full { tree, map } = self else {
+            anyhow::bail!("Cannot push to a light eligible tree");
+        };
+        let leaf_index = tree.len() as u32;
+        map.insert(leaf.deposit_index, leaf_index);
+        tree.push(leaf);
+        Ok(())
     }
 
     pub fn get_leaf_index(&self, deposit_index: u32) -> Option<u32> {
-        self.map.get(&deposit_index).copied()
+        match self {
+            Self::Full { map, .. } | Self::Light { map, .. } => map.get(&deposit_index).copied(),
+        }
+    }
+
+    /// Leaf at `leaf_index`, which must be a tracked leaf in a light tree.
+    pub fn get_leaf(&self, leaf_index: u32) -> anyhow::Result<EligibleLeaf> {
+        match self {
+            Self::Full { tree, .. } => Ok(tree.get_leaf(leaf_index as usize)),
+            Self::Light { leaves, .. } => Ok(leaves
+                .get(&leaf_index)
+                .ok_or_else(|| not_tracked(leaf_index))?
+                .0
+                .clone()),
+        }
+    }
+
+    /// Proof of the leaf at `leaf_index`, which must be a tracked leaf in a light tree.
+    pub fn prove(&self, leaf_index: u32) -> anyhow::Result<IncrementalMerkleProof<EligibleLeaf>> {
+        let siblings = match self {
+            Self::Full { tree, .. } => tree.prove(leaf_index as usize),
+            Self::Light { leaves, .. } => leaves
+                .get(&leaf_index)
+                .ok_or_else(|| not_tracked(leaf_index))?
+                .1
+                .clone(),
+        };
+        Ok(IncrementalMerkleProof(MerkleProof { siblings }))
     }
 
     pub fn get_root(&self) -> Bytes32 {
-        self.tree.get_root().into()
+        match self {
+            Self::Full { tree, .. } => tree.get_root().into(),
+            Self::Light { root, .. } => (*root).into(),
+        }
+    }
+
+    /// All leaves of a full tree, or `None` for a light tree.
+    pub fn leaves(&self) -> Option<Vec<EligibleLeaf>> {
+        match self {
+            Self::Full { tree, .. } => Some(tree.leaves().to_vec()),
+            Self::Light { .. } => None,
+        }
     }
 }
+
+fn not_tracked(leaf_index: u32) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Leaf {} is not tracked by the light eligible tree",
+        leaf_index
+    )
+}
@@ -1,34 +1,156 @@
 use aes_gcm::{aead::Aead, NewAead as _};
+use rand::RngCore as _;
 use serde::Serialize;
 use sha3::{Digest, Keccak256};
 
-const NONCE: &str = "intmaxmining";
+// Nonce of the legacy (unversioned) format, shared by every ciphertext.
+const LEGACY_NONCE: &str = "intmaxmining";
+
+// Versioned envelope layout:
+// MAGIC (4) | version (1) | log_n (1) | r (4, BE) | p (4, BE) | salt (16) | nonce (12) | ciphertext
+const MAGIC: &[u8; 4] = b"IMXE";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+// scrypt parameters for new ciphertexts (N = 2^15, r = 8, p = 1 uses 32 MiB of memory)
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+struct Header {
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.log_n);
+        bytes.extend_from_slice(&self.r.to_be_bytes());
+        bytes.extend_from_slice(&self.p.to_be_bytes());
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+
+    /// Parses the header. Returns `None` if the data is not in the versioned format.
+    fn from_bytes(data: &[u8]) -> anyhow::Result<Option<Self>> {
+        if data.len() < HEADER_LEN || &data[0..MAGIC.len()] != MAGIC {
+            return Ok(None);
+        }
+        let version = data[4];
+        if version != VERSION {
+            anyhow::bail!("Unsupported encryption version: {}", version);
+        }
+        let log_n = data[5];
+        let r = u32::from_be_bytes(data[6..10].try_into().unwrap());
+        let p = u32::from_be_bytes(data[10..14].try_into().unwrap());
+        // this version never writes costlier parameters, so larger ones mean a tampered blob that
+        // would make scrypt allocate gigabytes before the password is checked
+        if log_n > SCRYPT_LOG_N || r > SCRYPT_R || p > SCRYPT_P {
+            anyhow::bail!(
+                "Unsupported scrypt parameters: log_n = {}, r = {}, p = {}",
+                log_n,
+                r,
+                p
+            );
+        }
+        let salt = data[14..14 + SALT_LEN].try_into().unwrap();
+        let nonce = data[14 + SALT_LEN..HEADER_LEN].try_into().unwrap();
+        Ok(Some(Self {
+            log_n,
+            r,
+            p,
+            salt,
+            nonce,
+        }))
+    }
+
+    fn derive_key(&self, password: &str) -> anyhow::Result<[u8; 32]> {
+        let params = scrypt::Params::new(self.log_n, self.r, self.p)
+            .map_err(|e| anyhow::anyhow!("Invalid scrypt parameters: {}", e))?;
+        let mut key = [0u8; 32];
+        scrypt::scrypt(password.as_bytes(), &self.salt, &params, &mut key)
+            .map_err(|e| anyhow::anyhow!("Failed to derive key: {}", e))?;
+        Ok(key)
+    }
+}
 
 pub fn encrypt<T: Serialize>(password: &str, value: &T) -> anyhow::Result<Vec<u8>> {
-    let password_hash = keccak256_hash(password);
-    let key = aes_gcm::Key::from_slice(&password_hash);
-    let cipher = aes_gcm::Aes256Gcm::new(key);
-    let nonce = aes_gcm::Nonce::from_slice(NONCE.as_bytes());
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+    let header = Header {
+        log_n: SCRYPT_LOG_N,
+        r: SCRYPT_R,
+        p: SCRYPT_P,
+        salt,
+        nonce,
+    };
+    let key = header.derive_key(password)?;
+    let cipher = aes_gcm::Aes256Gcm::new(aes_gcm::Key::from_slice(&key));
     let private_data_str = serde_json::to_string(value)?;
     let ciphertext = cipher
-        .encrypt(nonce, private_data_str.as_bytes())
+        .encrypt(
+            aes_gcm::Nonce::from_slice(&header.nonce),
+            private_data_str.as_bytes(),
+        )
         .map_err(|_| anyhow::anyhow!("Failed to encrypt private data"))?;
-    Ok(ciphertext)
+    Ok([header.to_bytes(), ciphertext].concat())
 }
 
-pub fn decrypt<T>(password: &str, ciphertext: &[u8]) -> anyhow::Result<T>
+/// Decrypts data in either the versioned format or the legacy format.
+pub fn decrypt<T>(password: &str, data: &[u8]) -> anyhow::Result<T>
 where
     T: serde::de::DeserializeOwned,
 {
+    let plaintext = match Header::from_bytes(data)? {
+        Some(header) => {
+            let key = header.derive_key(password)?;
+            let cipher = aes_gcm::Aes256Gcm::new(aes_gcm::Key::from_slice(&key));
+            cipher
+                .decrypt(
+                    aes_gcm::Nonce::from_slice(&header.nonce),
+                    &data[HEADER_LEN..],
+                )
+                .map_err(|_| anyhow::anyhow!("Failed to decrypt private data"))?
+        }
+        None => decrypt_legacy(password, data)?,
+    };
+    let private_data: T = serde_json::from_slice(&plaintext)?;
+    Ok(private_data)
+}
+
+/// Returns true if the data was encrypted with the legacy fixed-nonce format
+/// and should be re-encrypted. Data with an unsupported header is not legacy.
+pub fn is_legacy_format(data: &[u8]) -> bool {
+    matches!(Header::from_bytes(data), Ok(None))
+}
+
+/// Checks that the header of the data is supported, so that a wrong password is not reported
+/// for data that can't be decrypted with any password.
+pub fn check_format(data: &[u8]) -> anyhow::Result<()> {
+    Header::from_bytes(data)?;
+    Ok(())
+}
+
+fn decrypt_legacy(password: &str, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
     let password_hash = keccak256_hash(password);
     let key = aes_gcm::Key::from_slice(&password_hash);
     let cipher = aes_gcm::Aes256Gcm::new(key);
-    let nonce = aes_gcm::Nonce::from_slice(NONCE.as_bytes());
-    let plaintext = cipher
+    let nonce = aes_gcm::Nonce::from_slice(LEGACY_NONCE.as_bytes());
+    cipher
         .decrypt(nonce, ciphertext)
-        .map_err(|_| anyhow::anyhow!("Failed to decrypt private data"))?;
-    let private_data: T = serde_json::from_slice(&plaintext)?;
-    Ok(private_data)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt private data"))
 }
 
 pub fn keccak256_hash(input: &str) -> [u8; 32] {
@@ -38,3 +160,57 @@ pub fn keccak256_hash(input: &str) -> [u8; 32] {
     let hash: [u8; 32] = result.into();
     hash
 }
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm::{aead::Aead as _, NewAead as _};
+    use alloy::primitives::B256;
+
+    use super::*;
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let key = B256::random();
+        let encrypted = encrypt("password", &key).unwrap();
+        assert!(!is_legacy_format(&encrypted));
+        let decrypted: B256 = decrypt("password", &encrypted).unwrap();
+        assert_eq!(key, decrypted);
+        assert!(decrypt::<B256>("wrong password", &encrypted).is_err());
+
+        // salt and nonce are random, so the same input never gives the same ciphertext
+        let encrypted2 = encrypt("password", &key).unwrap();
+        assert_ne!(encrypted, encrypted2);
+    }
+
+    #[test]
+    fn test_decrypt_legacy_format() {
+        let key = B256::random();
+        let password_hash = keccak256_hash("password");
+        let cipher = aes_gcm::Aes256Gcm::new(aes_gcm::Key::from_slice(&password_hash));
+        let legacy = cipher
+            .encrypt(
+                aes_gcm::Nonce::from_slice(LEGACY_NONCE.as_bytes()),
+                serde_json::to_string(&key).unwrap().as_bytes(),
+            )
+            .unwrap();
+        assert!(is_legacy_format(&legacy));
+        let decrypted: B256 = decrypt("password", &legacy).unwrap();
+        assert_eq!(key, decrypted);
+    }
+
+    #[test]
+    fn test_reject_unsupported_header() {
+        let encrypted = encrypt("password", &B256::random()).unwrap();
+
+        let mut costly = encrypted.clone();
+        costly[5] = 30; // log_n
+        assert!(check_format(&costly).is_err());
+        assert!(!is_legacy_format(&costly));
+        assert!(decrypt::<B256>("password", &costly).is_err());
+
+        let mut future = encrypted.clone();
+        future[4] = VERSION + 1;
+        assert!(check_format(&future).is_err());
+        assert!(!is_legacy_format(&future));
+    }
+}
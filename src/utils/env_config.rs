@@ -26,14 +26,18 @@ fn env_config_path(network: Network, i: usize) -> PathBuf {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct EnvConfig {
     pub network: Network,
-    pub rpc_url: String,
+    // ordered by preference. `rpc_url` is the single-URL field of older config files
+    #[serde(alias = "rpc_url", deserialize_with = "deserialize_rpc_urls")]
+    pub rpc_urls: Vec<String>,
     pub max_gas_price: U256,
     pub encrypt: bool,
     pub withdrawal_address: Address,
     pub withdrawal_private_key: Option<B256>,
     pub encrypted_withdrawal_private_key: Option<Vec<u8>>,
+    pub withdrawal_keystore_path: Option<PathBuf>, // V3 keystore used as the key source if set
     pub mining_unit: U256,
     pub mining_times: u64,
+    pub forward_to: Option<Address>, // claimed ITX is forwarded to this address if set
 }
 
 impl EnvConfig {
@@ -65,6 +69,17 @@ impl EnvConfig {
         Ok(config)
     }
 
+    /// Returns the index of the saved config file of the same network and withdrawal address.
+    pub fn find_saved_index(&self) -> anyhow::Result<Option<usize>> {
+        for i in Self::get_existing_indices(self.network) {
+            let saved = Self::load_from_file(self.network, i)?;
+            if saved.withdrawal_address == self.withdrawal_address {
+                return Ok(Some(i));
+            }
+        }
+        Ok(None)
+    }
+
     pub fn save_to_file(&self, i: usize) -> anyhow::Result<()> {
         let input = serde_json::to_vec_pretty(self)?;
         create_file_with_content(&env_config_path(self.network, i), &input)?;
@@ -78,7 +93,9 @@ impl EnvConfig {
         env::set_var("MAX_GAS_PRICE", &config_string.max_gas_price);
         env::set_var("WITHDRAWAL_ADDRESS", &config_string.withdrawal_address);
         env::set_var("ENCRYPT", &config_string.encrypt);
-        if self.encrypt {
+        if let Some(keystore_path) = config_string.withdrawal_keystore_path {
+            env::set_var("WITHDRAWAL_KEYSTORE_PATH", keystore_path);
+        } else if self.encrypt {
             env::set_var(
                 "ENCRYPTED_WITHDRAWAL_PRIVATE_KEY",
                 config_string.encrypted_withdrawal_private_key.unwrap(),
@@ -91,6 +108,10 @@ impl EnvConfig {
         }
         env::set_var("MINING_UNIT", &config_string.mining_unit);
         env::set_var("MINING_TIMES", &config_string.mining_times);
+        match &config_string.forward_to {
+            Some(forward_to) => env::set_var("FORWARD_TO", forward_to),
+            None => env::remove_var("FORWARD_TO"),
+        }
         Ok(())
     }
 
@@ -98,6 +119,7 @@ impl EnvConfig {
     pub fn import_from_env() -> anyhow::Result<Self> {
         let network = env::var("NETWORK").unwrap_or(Network::default().to_string());
         let default_env = Settings::load()?.env;
+        // comma separated list of RPC URLs
         let rpc_url = env::var("RPC_URL")
             .map_err(|_| anyhow::Error::msg("RPC_URL environment variable is not set"))?;
         let max_gas_price = env::var("MAX_GAS_PRICE").unwrap_or(default_env.default_max_gas_price);
@@ -107,9 +129,11 @@ impl EnvConfig {
         let encrypt = env::var("ENCRYPT").unwrap_or("true".to_string());
         let withdrawal_private_key = env::var("WITHDRAWAL_PRIVATE_KEY").ok();
         let encrypted_withdrawal_private_key = env::var("ENCRYPTED_WITHDRAWAL_PRIVATE_KEY").ok();
+        let withdrawal_keystore_path = env::var("WITHDRAWAL_KEYSTORE_PATH").ok();
         let mining_unit = env::var("MINING_UNIT").unwrap_or(default_env.default_mining_unit);
         let mining_times =
             env::var("MINING_TIMES").unwrap_or(default_env.default_mining_times.to_string());
+        let forward_to = env::var("FORWARD_TO").ok();
         let config_string = EnvConfigString {
             network,
             rpc_url,
@@ -118,8 +142,10 @@ impl EnvConfig {
             withdrawal_address,
             withdrawal_private_key,
             encrypted_withdrawal_private_key,
+            withdrawal_keystore_path,
             mining_unit,
             mining_times,
+            forward_to,
         };
         let config = EnvConfig::from_string(&config_string)?;
         Ok(config)
@@ -128,7 +154,9 @@ impl EnvConfig {
     fn to_string(&self) -> anyhow::Result<EnvConfigString> {
         let network = format!("{}", self.network);
         let max_gas_price = format_units(self.max_gas_price, "gwei").unwrap();
-        let encrypt = if self.withdrawal_private_key.is_some() {
+        let encrypt = if self.withdrawal_keystore_path.is_some() {
+            "true".to_string()
+        } else if self.withdrawal_private_key.is_some() {
             "false".to_string()
         } else if self.encrypted_withdrawal_private_key.is_some() {
             "true".to_string()
@@ -141,24 +169,40 @@ impl EnvConfig {
             .encrypted_withdrawal_private_key
             .clone()
             .map(hex::encode);
+        let withdrawal_keystore_path = self
+            .withdrawal_keystore_path
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string());
         let mining_unit = format_units(self.mining_unit, "ether").unwrap();
         let mining_times = self.mining_times.to_string();
+        let forward_to = self.forward_to.map(|address| format!("{:?}", address));
         Ok(EnvConfigString {
             network,
-            rpc_url: self.rpc_url.clone(),
+            rpc_url: self.rpc_urls.join(","),
             max_gas_price,
             encrypt,
             withdrawal_address,
             withdrawal_private_key,
             encrypted_withdrawal_private_key,
+            withdrawal_keystore_path,
             mining_unit,
             mining_times,
+            forward_to,
         })
     }
 
     fn from_string(value: &EnvConfigString) -> anyhow::Result<Self> {
         let network =
             Network::from_str(&value.network).map_err(|_| anyhow::anyhow!("Invalid network"))?;
+        let rpc_urls = value
+            .rpc_url
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect::<Vec<String>>();
+        if rpc_urls.is_empty() {
+            anyhow::bail!("RPC_URL is empty");
+        }
         let max_gas_price: U256 = parse_units(&value.max_gas_price, "gwei")
             .map_err(|_| anyhow::anyhow!("failed to parse MAX_GAS_PRICE"))?
             .into();
@@ -170,9 +214,13 @@ impl EnvConfig {
             anyhow::bail!("ENCRYPT must be either 'true' or 'false'");
         };
 
-        if !encrypt && value.withdrawal_private_key.is_none() {
+        let withdrawal_keystore_path = value.withdrawal_keystore_path.as_ref().map(PathBuf::from);
+        let use_keystore = withdrawal_keystore_path.is_some();
+        if use_keystore && !encrypt {
+            anyhow::bail!("ENCRYPT must be 'true' when WITHDRAWAL_KEYSTORE_PATH is set.");
+        } else if !encrypt && value.withdrawal_private_key.is_none() {
             anyhow::bail!("WITHDRAWAL_PRIVATE_KEY is not set.");
-        } else if encrypt && value.encrypted_withdrawal_private_key.is_none() {
+        } else if encrypt && !use_keystore && value.encrypted_withdrawal_private_key.is_none() {
             anyhow::bail!("ENCRYPTED_WITHDRAWAL_PRIVATE_KEY is not set.");
         }
         let withdrawal_address: Address = value
@@ -191,7 +239,7 @@ impl EnvConfig {
         } else {
             None
         };
-        let encrypted_withdrawal_private_key = if encrypt {
+        let encrypted_withdrawal_private_key = if encrypt && !use_keystore {
             let encrypted_withdrawal_private_key: Vec<u8> = hex::decode(
                 value.encrypted_withdrawal_private_key.as_ref().unwrap(),
             )
@@ -208,21 +256,45 @@ impl EnvConfig {
             .mining_times
             .parse()
             .map_err(|_| anyhow::anyhow!("failed to parse MINING_TIMES"))?;
+        let forward_to: Option<Address> = value
+            .forward_to
+            .as_ref()
+            .map(|address| address.parse())
+            .transpose()
+            .map_err(|_| anyhow::anyhow!("failed to parse FORWARD_TO"))?;
 
         Ok(EnvConfig {
             network,
-            rpc_url: value.rpc_url.clone(),
+            rpc_urls,
             max_gas_price,
             encrypt,
             withdrawal_address,
             withdrawal_private_key,
             encrypted_withdrawal_private_key,
+            withdrawal_keystore_path,
             mining_unit,
             mining_times,
+            forward_to,
         })
     }
 }
 
+fn deserialize_rpc_urls<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RpcUrls {
+        Single(String),
+        Multiple(Vec<String>),
+    }
+    Ok(match RpcUrls::deserialize(deserializer)? {
+        RpcUrls::Single(url) => vec![url],
+        RpcUrls::Multiple(urls) => urls,
+    })
+}
+
 // string version of EnvConfig
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct EnvConfigString {
@@ -233,8 +305,10 @@ struct EnvConfigString {
     withdrawal_address: String,
     withdrawal_private_key: Option<String>,
     encrypted_withdrawal_private_key: Option<String>,
+    withdrawal_keystore_path: Option<String>,
     mining_unit: String,
     mining_times: String,
+    forward_to: Option<String>,
 }
 
 #[cfg(test)]
@@ -255,47 +329,80 @@ mod tests {
         dbg!(config);
     }
 
-    #[test]
-    fn test_env_config_string_conversion() {
+    // config with a plain private key, for the tests to override what they check
+    fn test_env_config() -> super::EnvConfig {
         let key = B256::random();
-        let address = get_address_from_private_key(key);
-        let env_config = super::EnvConfig {
+        super::EnvConfig {
             network: Network::Localnet,
-            rpc_url: "http://localhost:8545".to_string(),
+            rpc_urls: vec!["http://localhost:8545".to_string()],
             max_gas_price: U256::from(30_000_000_000u64),
             encrypt: false,
-            withdrawal_address: address,
+            withdrawal_address: get_address_from_private_key(key),
             withdrawal_private_key: Some(key),
             encrypted_withdrawal_private_key: None,
+            withdrawal_keystore_path: None,
             mining_unit: U256::from(100_000_000_000_000_000u128),
             mining_times: 10,
-        };
+            forward_to: None,
+        }
+    }
+
+    #[test]
+    fn test_env_config_string_conversion() {
+        let env_config = test_env_config();
         let env_config_string = env_config.to_string().unwrap();
         let env_config_recovered = super::EnvConfig::from_string(&env_config_string).unwrap();
         assert_eq!(env_config, env_config_recovered);
     }
 
     #[test]
-    fn test_export_and_import_config() {
-        let key = B256::random();
-        let address = get_address_from_private_key(key);
+    fn test_keystore_env_config_string_conversion() {
         let env_config = super::EnvConfig {
-            network: Network::Localnet,
-            rpc_url: "http://localhost:8545".to_string(),
-            max_gas_price: U256::from(30_000_000_000u64),
-            withdrawal_address: address,
-            encrypt: false,
-            withdrawal_private_key: Some(key),
-            encrypted_withdrawal_private_key: None,
-            mining_unit: U256::from(100_000_000_000_000_000u128),
-            mining_times: 10,
+            encrypt: true,
+            withdrawal_private_key: None,
+            withdrawal_keystore_path: Some("keystores/withdrawal.json".into()),
+            ..test_env_config()
         };
+        let env_config_string = env_config.to_string().unwrap();
+        let env_config_recovered = super::EnvConfig::from_string(&env_config_string).unwrap();
+        assert_eq!(env_config, env_config_recovered);
+    }
+
+    #[test]
+    fn test_export_and_import_config() {
+        let env_config = test_env_config();
         env_config.export_to_env().unwrap();
 
         let env_config_recovered = super::EnvConfig::import_from_env().unwrap();
         assert_eq!(env_config, env_config_recovered);
     }
 
+    #[test]
+    fn test_multiple_rpc_urls() {
+        let env_config = super::EnvConfig {
+            rpc_urls: vec![
+                "http://localhost:8545".to_string(),
+                "http://localhost:8546".to_string(),
+            ],
+            ..test_env_config()
+        };
+        let env_config_string = env_config.to_string().unwrap();
+        assert_eq!(
+            env_config_string.rpc_url,
+            "http://localhost:8545,http://localhost:8546"
+        );
+        let env_config_recovered = super::EnvConfig::from_string(&env_config_string).unwrap();
+        assert_eq!(env_config, env_config_recovered);
+
+        // config files written before multiple RPC URLs were supported
+        let mut json = serde_json::to_value(&env_config).unwrap();
+        let object = json.as_object_mut().unwrap();
+        object.remove("rpc_urls");
+        object.insert("rpc_url".to_string(), "http://localhost:8545".into());
+        let legacy: super::EnvConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.rpc_urls, vec!["http://localhost:8545".to_string()]);
+    }
+
     #[test]
     fn mini_test() {
         let amount = U256::from(100000000000000000u128);
@@ -1,8 +1,8 @@
+use crate::{cli::console::print_warning, external_api::contracts::utils::connect_client};
 use alloy::{
     primitives::{utils::parse_ether, U256},
     providers::{Provider, ProviderBuilder},
 };
-use reqwest::Url;
 
 use super::{
     config::Settings,
@@ -20,9 +20,12 @@ pub fn get_allowed_mining_times() -> Vec<u64> {
 }
 
 pub async fn validate_env_config(env: &EnvConfig) -> anyhow::Result<()> {
-    validate_rpc_url(&env.rpc_url).await?;
+    validate_rpc_urls(&env.rpc_urls).await?;
     validate_mining_unit(env.mining_unit)?;
     validate_mining_times(env.mining_times)?;
+    if env.forward_to == Some(env.withdrawal_address) {
+        anyhow::bail!("FORWARD_TO must be different from WITHDRAWAL_ADDRESS");
+    }
     Ok(())
 }
 
@@ -46,12 +49,24 @@ fn validate_mining_times(mining_times: u64) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn validate_rpc_url(rpc_url: &str) -> anyhow::Result<()> {
-    let rpc_url: Url = rpc_url
-        .parse()
-        .map_err(|e| anyhow::anyhow!("Failed to parse RPC URL {}: {}", rpc_url, e))?;
+/// Fails if none of the RPC URLs is valid. Invalid secondary URLs are only warned about
+/// because the provider falls back to the valid ones.
+async fn validate_rpc_urls(rpc_urls: &[String]) -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+    for (i, rpc_url) in rpc_urls.iter().enumerate() {
+        if let Err(e) = validate_rpc_url(rpc_url).await {
+            print_warning(format!("RPC URL #{} is not usable: {}", i, e));
+            errors.push(e);
+        }
+    }
+    if errors.len() == rpc_urls.len() {
+        anyhow::bail!("None of the RPC URLs is usable: {}", errors[0]);
+    }
+    Ok(())
+}
 
-    let client = ProviderBuilder::new().connect_http(rpc_url);
+pub async fn validate_rpc_url(rpc_url: &str) -> anyhow::Result<()> {
+    let client = ProviderBuilder::new().connect_client(connect_client(rpc_url).await?);
     let chain_id = client.get_chain_id().await?;
     let setting = Settings::load()?;
     if chain_id != setting.blockchain.chain_id {
@@ -2,16 +2,59 @@ use std::{
     fs::{self, File},
     io::Write as _,
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
 use anyhow::Context as _;
 
+/// Environment variable that moves the data directory, overridden by `--data-dir`
+pub const DATA_DIR_ENV: &str = "MINING_CLI_DATA_DIR";
+
+// name of the data directory next to the executable in portable mode
+const PORTABLE_DATA_DIR: &str = "mining-cli-data";
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Uses `path` as the data directory for the rest of the process. Must be called before the
+/// data directory is first used.
+pub fn set_data_path(path: PathBuf) -> anyhow::Result<()> {
+    DATA_DIR
+        .set(path)
+        .map_err(|_| anyhow::anyhow!("Data directory is already set"))
+}
+
+/// Directory of the configs, logs, job journal and caches. In order of precedence: the path set
+/// by `set_data_path`, `MINING_CLI_DATA_DIR`, the portable data directory if it exists, and
+/// `~/.mining-cli`.
 pub fn get_data_path() -> anyhow::Result<PathBuf> {
+    if let Some(path) = DATA_DIR.get() {
+        return Ok(path.clone());
+    }
+    if let Some(path) = std::env::var_os(DATA_DIR_ENV).filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let portable_path = get_portable_data_path()?;
+    if portable_path.is_dir() {
+        return Ok(portable_path);
+    }
+    get_default_data_path()
+}
+
+pub fn get_default_data_path() -> anyhow::Result<PathBuf> {
     let home_dir =
         dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Failed to get home directory"))?;
     Ok(home_dir.join(".mining-cli"))
 }
 
+/// Data directory of the portable mode, next to the executable.
+pub fn get_portable_data_path() -> anyhow::Result<PathBuf> {
+    let exe_path = std::env::current_exe().context("Failed to get the executable path")?;
+    let exe_dir = exe_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get the executable directory"))?;
+    Ok(exe_dir.join(PORTABLE_DATA_DIR))
+}
+
 pub fn create_data_dir() -> anyhow::Result<()> {
     let data_path = get_data_path()?;
     fs::create_dir_all(&data_path)
@@ -33,11 +76,94 @@ pub fn create_file_with_content(path: &Path, content: &[u8]) -> anyhow::Result<(
     Ok(())
 }
 
+/// Writes the file through a temporary file renamed into place, so that readers and a crash
+/// midway never see a partly written file.
+pub fn write_file_atomically(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    create_file_with_content(&temp_path, content)?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to rename {:?} to {:?}", temp_path, path))?;
+    Ok(())
+}
+
+/// Moves the directory `from` to `to`, which must not exist. Falls back to copying when `to` is
+/// on another file system: the copy is written to a sibling of `to` and renamed into place once
+/// complete and `before_commit` has passed, and `from` is removed only after that.
+pub fn move_dir(
+    from: &Path,
+    to: &Path,
+    before_commit: impl FnOnce() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let mut partial = to.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    if partial.exists() {
+        // left by an interrupted move, and `from` is still complete
+        fs::remove_dir_all(&partial)
+            .with_context(|| format!("Failed to remove directory: {:?}", partial))?;
+    }
+    copy_dir(from, &partial)?;
+    if let Err(e) = before_commit() {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(e);
+    }
+    fs::rename(&partial, to)
+        .with_context(|| format!("Failed to rename {:?} to {:?}", partial, to))?;
+    fs::remove_dir_all(from).with_context(|| format!("Failed to remove directory: {:?}", from))?;
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("Failed to create directory: {:?}", to))?;
+    for entry in fs::read_dir(from).with_context(|| format!("Failed to read {:?}", from))? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {:?}", entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
 
+    #[test]
+    fn test_copy_and_move_dir() {
+        let root = std::env::temp_dir().join(format!("mining-cli-move-{}", std::process::id()));
+        let from = root.join("from");
+        create_file_with_content(&from.join("temp").join("claim_short_1.json"), b"{}").unwrap();
+        create_file_with_content(&from.join("agreement"), b"agreed").unwrap();
+
+        let copied = root.join("copied");
+        copy_dir(&from, &copied).unwrap();
+        assert_eq!(fs::read(copied.join("agreement")).unwrap(), b"agreed");
+        assert!(from.join("agreement").exists());
+
+        let to = root.join("nested").join("to");
+        move_dir(&from, &to, || Ok(())).unwrap();
+        assert!(!from.exists());
+        assert_eq!(
+            fs::read(to.join("temp").join("claim_short_1.json")).unwrap(),
+            b"{}"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_create_file_with_content() {
         let path = Path::new("test.txt");
@@ -0,0 +1,128 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read as _, Seek as _, SeekFrom, Write as _},
+    path::{Path, PathBuf},
+};
+
+use alloy::primitives::Address;
+use anyhow::Context as _;
+use fs2::FileExt as _;
+
+use super::file::{create_data_dir, get_data_path};
+
+/// Advisory lock held while the process runs, so that two instances don't share the job
+/// journal, nonces and caches. The lock file holds the PID of the owner. The OS releases the lock
+/// when the process dies, so a lock left by a crashed process is taken over.
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Takes the lock at `path`, failing with the PID of the owner if another process holds it.
+    /// `resource` names what is locked in the messages.
+    pub fn acquire(path: &Path, resource: &str) -> anyhow::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file: {:?}", path))?;
+        let mut owner = String::new();
+        // the owner may be writing its PID, in which case it is unknown
+        let _ = file.read_to_string(&mut owner);
+        let owner = owner.trim().parse::<u32>().ok();
+        if file.try_lock_exclusive().is_err() {
+            match owner {
+                Some(pid) => anyhow::bail!(
+                    "Another mining-cli instance (PID {}) is using {}. Stop it before starting a new one, or remove {:?} if no such process is running",
+                    pid,
+                    resource,
+                    path
+                ),
+                None => anyhow::bail!(
+                    "Another mining-cli instance is using {}. Stop it before starting a new one",
+                    resource
+                ),
+            }
+        }
+        if let Some(pid) = owner {
+            // a clean exit clears the PID, so the previous owner has crashed or been killed
+            log::warn!(
+                "Taking over the lock on {} left by PID {}, which did not exit cleanly",
+                resource,
+                pid
+            );
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // the file itself is kept: removing it would let a process waiting on the old file and
+        // one creating a new file both hold the lock
+        if let Err(e) = self.file.set_len(0) {
+            log::warn!("Failed to clear lock file {:?}: {}", self.path, e);
+        }
+        let _ = self.file.unlock();
+    }
+}
+
+/// Locks the data directory against other instances.
+pub fn lock_data_dir() -> anyhow::Result<InstanceLock> {
+    create_data_dir()?;
+    let data_path = get_data_path()?;
+    InstanceLock::acquire(
+        &data_path.join("mining-cli.lock"),
+        &format!("the data directory {:?}", data_path),
+    )
+}
+
+/// Locks the withdrawal address against instances using other data directories, whose
+/// transactions would race on the nonces of the same keys.
+pub fn lock_withdrawal_address(withdrawal_address: Address) -> anyhow::Result<InstanceLock> {
+    let path = std::env::temp_dir().join(format!("mining-cli-{:?}.lock", withdrawal_address));
+    InstanceLock::acquire(
+        &path,
+        &format!("the withdrawal address {}", withdrawal_address),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instance_lock() {
+        let path =
+            std::env::temp_dir().join(format!("mining-cli-test-{}.lock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let lock = InstanceLock::acquire(&path, "test").unwrap();
+        let pid = std::process::id().to_string();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), pid);
+        let e = InstanceLock::acquire(&path, "test").unwrap_err();
+        assert!(e.to_string().contains(&format!("PID {}", pid)));
+
+        // a clean exit clears the PID
+        drop(lock);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        // a lock left by a crashed process is taken over
+        std::fs::write(&path, "4294967295").unwrap();
+        let lock = InstanceLock::acquire(&path, "test").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), pid);
+        drop(lock);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
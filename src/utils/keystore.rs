@@ -0,0 +1,92 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use alloy::{
+    primitives::B256,
+    signers::local::{eth_keystore::KeystoreError, LocalSignerError, PrivateKeySigner},
+};
+use anyhow::Context as _;
+
+use super::file::get_data_path;
+
+/// Default directory where exported keystore files are written.
+pub fn default_keystore_dir() -> anyhow::Result<PathBuf> {
+    Ok(get_data_path()?.join("keystores"))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportKeystoreError {
+    #[error("Invalid password")]
+    InvalidPassword,
+    #[error("Failed to read keystore {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid keystore {0:?}: {1}")]
+    Invalid(PathBuf, String),
+}
+
+/// Decrypts a Web3 Secret Storage V3 keystore file (scrypt or pbkdf2) and returns the private key.
+pub fn import_keystore(path: &Path, password: &str) -> Result<B256, ImportKeystoreError> {
+    let signer = PrivateKeySigner::decrypt_keystore(path, password).map_err(|e| match e {
+        // the MAC only mismatches when the derived key, i.e. the password, is wrong
+        LocalSignerError::EthKeystoreError(KeystoreError::MacMismatch) => {
+            ImportKeystoreError::InvalidPassword
+        }
+        LocalSignerError::EthKeystoreError(KeystoreError::StdIo(e)) => {
+            ImportKeystoreError::Io(path.to_path_buf(), e)
+        }
+        LocalSignerError::IoError(e) => ImportKeystoreError::Io(path.to_path_buf(), e),
+        e => ImportKeystoreError::Invalid(path.to_path_buf(), e.to_string()),
+    })?;
+    Ok(signer.to_bytes())
+}
+
+/// Encrypts the private key as a Web3 Secret Storage V3 keystore file named `name` in `dir`.
+/// Returns the path of the written file.
+pub fn export_keystore(
+    dir: &Path,
+    name: &str,
+    private_key: B256,
+    password: &str,
+) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create directory: {:?}", dir))?;
+    let path = dir.join(name);
+    if path.exists() {
+        anyhow::bail!("Keystore file already exists at {:?}", path);
+    }
+    let mut rng = rand::thread_rng();
+    PrivateKeySigner::encrypt_keystore(dir, &mut rng, private_key, password, Some(name))
+        .map_err(|e| anyhow::anyhow!("Failed to write keystore {:?}: {}", path, e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::B256;
+
+    use super::{export_keystore, import_keystore, ImportKeystoreError};
+
+    #[test]
+    fn test_export_and_import_keystore() {
+        let dir = std::env::temp_dir().join(format!("mining-cli-keystore-{}", B256::random()));
+        let key = B256::random();
+        let path = export_keystore(&dir, "withdrawal.json", key, "password").unwrap();
+        let recovered = import_keystore(&path, "password").unwrap();
+        assert_eq!(key, recovered);
+        assert!(matches!(
+            import_keystore(&path, "wrong password"),
+            Err(ImportKeystoreError::InvalidPassword)
+        ));
+        assert!(matches!(
+            import_keystore(&dir.join("missing.json"), "password"),
+            Err(ImportKeystoreError::Io(..))
+        ));
+        std::fs::write(dir.join("broken.json"), b"{}").unwrap();
+        assert!(matches!(
+            import_keystore(&dir.join("broken.json"), "password"),
+            Err(ImportKeystoreError::Invalid(..))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
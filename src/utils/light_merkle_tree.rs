@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use intmax2_zkp::utils::{leafable::Leafable, leafable_hasher::LeafableHasher};
+
+pub type HashOut<V> = <<V as Leafable>::LeafableHasher as LeafableHasher>::HashOut;
+
+/// Computes the root of a merkle tree and the sibling paths of a few tracked leaves from the
+/// leaves pushed in order. Only the right edge of the tree and the siblings of the tracked
+/// leaves are kept, so memory grows with the number of tracked leaves, not with the tree.
+#[derive(Debug)]
+pub struct LightMerkleTree<V: Leafable> {
+    height: usize,
+    len: u64,
+    // hash of an empty subtree at each level
+    empty_hashes: Vec<HashOut<V>>,
+    // completed left nodes waiting for their right sibling, one per level
+    frontier: Vec<Option<HashOut<V>>>,
+    tracked: HashMap<u64, TrackedLeaf<V>>,
+    // right siblings that are not complete yet, and the tracked leaves waiting for them
+    waiting: HashMap<(usize, u64), Vec<u64>>,
+}
+
+#[derive(Debug)]
+struct TrackedLeaf<V: Leafable> {
+    leaf: V,
+    siblings: Vec<Option<HashOut<V>>>,
+}
+
+/// Root and tracked leaves of a [`LightMerkleTree`] after all leaves are pushed.
+#[derive(Debug, Clone)]
+pub struct LightMerkleProofs<V: Leafable> {
+    pub root: HashOut<V>,
+    pub len: u64,
+    // key: leaf index, value: leaf and its siblings from the bottom
+    pub leaves: HashMap<u64, (V, Vec<HashOut<V>>)>,
+}
+
+impl<V: Leafable> LightMerkleTree<V> {
+    pub fn new(height: usize) -> Self {
+        let mut empty_hashes = vec![V::empty_leaf().hash()];
+        for level in 0..height {
+            let h = empty_hashes[level].clone();
+            empty_hashes.push(V::LeafableHasher::two_to_one(h.clone(), h));
+        }
+        Self {
+            height,
+            len: 0,
+            empty_hashes,
+            frontier: vec![None; height + 1],
+            tracked: HashMap::new(),
+            waiting: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends the leaf and returns its index. The proof of the leaf is kept if `track` is set.
+    pub fn push(&mut self, leaf: V, track: bool) -> anyhow::Result<u64> {
+        let index = self.len;
+        anyhow::ensure!(
+            index < 1 << self.height,
+            "Merkle tree of height {} is full",
+            self.height
+        );
+        if track {
+            let mut siblings = vec![None; self.height];
+            for (level, sibling) in siblings.iter_mut().enumerate() {
+                let node_index = index >> level;
+                if node_index & 1 == 1 {
+                    // the left sibling is complete and waits in the frontier
+                    *sibling = self.frontier[level].clone();
+                } else {
+                    self.waiting
+                        .entry((level, node_index + 1))
+                        .or_default()
+                        .push(index);
+                }
+            }
+            self.tracked.insert(
+                index,
+                TrackedLeaf {
+                    leaf: leaf.clone(),
+                    siblings,
+                },
+            );
+        }
+        let mut hash = leaf.hash();
+        let mut level = 0;
+        let mut node_index = index;
+        loop {
+            self.complete_node(level, node_index, &hash);
+            if level == self.height || node_index & 1 == 0 {
+                self.frontier[level] = Some(hash);
+                break;
+            }
+            let left = self.frontier[level].take().unwrap();
+            hash = V::LeafableHasher::two_to_one(left, hash);
+            level += 1;
+            node_index >>= 1;
+        }
+        self.len += 1;
+        Ok(index)
+    }
+
+    /// Pads the tree with empty leaves and returns the root and the proofs of the tracked leaves.
+    pub fn finish(mut self) -> LightMerkleProofs<V> {
+        // the rightmost node of each level is partial and is carried up from the level below
+        let mut carry: Option<HashOut<V>> = None;
+        for level in 0..self.height {
+            let count = self.len.div_ceil(1 << level);
+            if count == 0 {
+                continue;
+            }
+            let last = count - 1;
+            if let Some(hash) = &carry {
+                self.complete_node(level, last, hash);
+            }
+            carry = if last & 1 == 0 {
+                let left = carry.or_else(|| self.frontier[level].take()).unwrap();
+                Some(V::LeafableHasher::two_to_one(
+                    left,
+                    self.empty_hashes[level].clone(),
+                ))
+            } else {
+                carry.map(|right| {
+                    let left = self.frontier[level].take().unwrap();
+                    V::LeafableHasher::two_to_one(left, right)
+                })
+            };
+        }
+        let root = carry
+            .or_else(|| self.frontier[self.height].take())
+            .unwrap_or_else(|| self.empty_hashes[self.height].clone());
+        // siblings that were never completed cover only empty leaves
+        let empty_hashes = self.empty_hashes;
+        let leaves = self
+            .tracked
+            .into_iter()
+            .map(|(index, tracked)| {
+                let siblings = tracked
+                    .siblings
+                    .into_iter()
+                    .zip(empty_hashes.iter())
+                    .map(|(sibling, empty)| sibling.unwrap_or_else(|| empty.clone()))
+                    .collect();
+                (index, (tracked.leaf, siblings))
+            })
+            .collect();
+        LightMerkleProofs {
+            root,
+            len: self.len,
+            leaves,
+        }
+    }
+
+    fn complete_node(&mut self, level: usize, node_index: u64, hash: &HashOut<V>) {
+        if let Some(waiting) = self.waiting.remove(&(level, node_index)) {
+            for index in waiting {
+                self.tracked.get_mut(&index).unwrap().siblings[level] = Some(hash.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use intmax2_zkp::{
+        ethereum_types::{bytes32::Bytes32, u32limb_trait::U32LimbTrait as _},
+        utils::trees::incremental_merkle_tree::IncrementalMerkleTree,
+    };
+
+    use crate::utils::deposit_hash_tree::DepositHash;
+
+    use super::LightMerkleTree;
+
+    #[test]
+    fn test_light_merkle_tree() {
+        let mut rng = rand::thread_rng();
+        let height = 8;
+        for n in [0, 1, 2, 3, 7, 100, 256] {
+            let mut tree = IncrementalMerkleTree::<DepositHash>::new(height);
+            let mut light_tree = LightMerkleTree::<DepositHash>::new(height);
+            for i in 0..n {
+                let leaf = DepositHash(Bytes32::rand(&mut rng));
+                tree.push(leaf.clone());
+                light_tree.push(leaf, i % 3 == 0).unwrap();
+            }
+            let proofs = light_tree.finish();
+            assert_eq!(proofs.root, tree.get_root());
+            assert_eq!(proofs.leaves.len(), n.div_ceil(3));
+            for (index, (leaf, siblings)) in &proofs.leaves {
+                assert_eq!(leaf, &tree.get_leaf(*index as usize));
+                assert_eq!(siblings, &tree.prove(*index as usize).0.siblings);
+            }
+        }
+        let mut full_tree = LightMerkleTree::<DepositHash>::new(2);
+        for _ in 0..4 {
+            full_tree
+                .push(DepositHash(Bytes32::rand(&mut rng)), false)
+                .unwrap();
+        }
+        assert!(full_tree.push(DepositHash::default(), false).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+/// Resident and peak resident memory of the process in bytes. Only available on Linux, where
+/// it is read from `/proc/self/status`.
+pub fn memory_usage() -> Option<(u64, u64)> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_memory_usage(&status)
+}
+
+fn parse_memory_usage(status: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().strip_suffix("kB"))
+            .and_then(|kb| kb.trim().parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+    };
+    Some((field("VmRSS:")?, field("VmHWM:")?))
+}
+
+/// Logs the memory usage of the process at the given phase
+pub fn log_memory_usage(phase: &str) {
+    match memory_usage() {
+        Some((resident, peak)) => log::info!(
+            "Memory {}: resident {} MB, peak {} MB",
+            phase,
+            resident / 1024 / 1024,
+            peak / 1024 / 1024
+        ),
+        None => log::info!("Memory {}: not available on this platform", phase),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_memory_usage;
+
+    #[test]
+    fn test_parse_memory_usage() {
+        let status = "Name:\tmining-cli\nVmHWM:\t  204800 kB\nVmRSS:\t  102400 kB\n";
+        assert_eq!(
+            parse_memory_usage(status),
+            Some((100 * 1024 * 1024, 200 * 1024 * 1024))
+        );
+        assert_eq!(parse_memory_usage("Name:\tmining-cli\n"), None);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod bin_parser;
+pub mod bulk_merkle_tree;
 pub mod cache;
 pub mod config;
 pub mod deposit_hash_tree;
@@ -9,7 +10,14 @@ pub mod env_config;
 pub mod env_validation;
 pub mod errors;
 pub mod file;
+pub mod instance_lock;
+pub mod keystore;
+pub mod light_merkle_tree;
+pub mod memory;
 pub mod network;
+pub mod progress;
 pub mod retry;
+pub mod shutdown;
 pub mod time;
+pub mod tx_ledger;
 pub mod update;
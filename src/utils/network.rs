@@ -33,6 +33,13 @@ impl FromStr for Network {
     }
 }
 
+impl Network {
+    /// Returns true if the network is an OP-stack L2 which charges an L1 data fee
+    pub fn is_op_stack(&self) -> bool {
+        *self == Network::Base
+    }
+}
+
 pub fn get_network() -> Network {
     let network = env::var("NETWORK").unwrap_or_else(|_| Network::default().to_string());
     Network::from_str(&network).expect("Invalid network")
@@ -0,0 +1,26 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Progress bar on stderr for a step of `len` items. Not drawn when stderr is not a terminal.
+pub fn progress_bar(len: u64, message: &str) -> ProgressBar {
+    let bar = ProgressBar::new(len).with_message(message.to_string());
+    bar.set_style(
+        ProgressStyle::with_template("{msg} [{bar:40}] {percent}% ({elapsed})")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar
+}
+
+/// Progress bar for a download of `len` bytes, or a spinner if the size is unknown.
+pub fn download_progress_bar(len: Option<u64>, message: &str) -> ProgressBar {
+    let Some(len) = len else {
+        return ProgressBar::new_spinner().with_message(message.to_string());
+    };
+    let bar = ProgressBar::new(len).with_message(message.to_string());
+    bar.set_style(
+        ProgressStyle::with_template("{msg} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec})")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar
+}
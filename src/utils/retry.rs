@@ -29,7 +29,10 @@ where
                     e.to_string(),
                     delay
                 );
-                sleep_for(delay.as_secs());
+                if sleep_for(delay.as_secs()).await.is_err() {
+                    // shutting down, so the last error is returned instead of retrying
+                    return Err(e);
+                }
                 retries += 1;
                 delay *= 2; // Exponential backoff
             }
@@ -0,0 +1,136 @@
+use std::{
+    future::Future,
+    sync::{Mutex, OnceLock},
+};
+
+use tokio::sync::watch;
+
+use crate::cli::console::print_warning;
+
+/// Returned by waits that were interrupted by a shutdown request.
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Cancelled by shutdown")]
+pub struct Cancelled;
+
+tokio::task_local! {
+    static FINISH_BEFORE_SHUTDOWN: ();
+}
+
+fn shutdown_sender() -> &'static watch::Sender<bool> {
+    static SHUTDOWN: OnceLock<watch::Sender<bool>> = OnceLock::new();
+    SHUTDOWN.get_or_init(|| watch::channel(false).0)
+}
+
+/// Asks every wait to stop. Waits in progress return `Cancelled`.
+pub fn request_shutdown() {
+    shutdown_sender().send_replace(true);
+}
+
+pub fn is_shutdown_requested() -> bool {
+    *shutdown_sender().borrow()
+}
+
+/// Completes once a shutdown is requested.
+pub async fn shutdown_requested() {
+    let mut receiver = shutdown_sender().subscribe();
+    // the sender is static, so this only fails if it is dropped, which never happens
+    let _ = receiver.wait_for(|requested| *requested).await;
+}
+
+/// Runs the future to the end even if a shutdown is requested meanwhile. For steps that must
+/// not stop halfway, such as submitting a withdrawal and recording that it was submitted.
+pub async fn finish_before_shutdown<F: Future>(future: F) -> F::Output {
+    FINISH_BEFORE_SHUTDOWN.scope((), future).await
+}
+
+fn is_finishing_before_shutdown() -> bool {
+    FINISH_BEFORE_SHUTDOWN.try_with(|_| ()).is_ok()
+}
+
+/// Whether the current task should stop, i.e. a shutdown is requested and the task is not in
+/// `finish_before_shutdown`.
+pub fn should_stop() -> bool {
+    is_shutdown_requested() && !is_finishing_before_shutdown()
+}
+
+/// Completes once the current task should stop, see `should_stop`.
+pub async fn stop_requested() {
+    if is_finishing_before_shutdown() {
+        std::future::pending::<()>().await;
+    }
+    shutdown_requested().await;
+}
+
+/// Returns `Cancelled` if the current task should stop. Called between the steps of a job,
+/// after its state is saved.
+pub fn checkpoint() -> Result<(), Cancelled> {
+    if should_stop() {
+        return Err(Cancelled);
+    }
+    Ok(())
+}
+
+/// Requests a shutdown on the first Ctrl+C and exits on the second.
+pub fn listen_for_ctrl_c() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        print_warning("Stopping after the current step... Press Ctrl+C again to exit immediately");
+        request_shutdown();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            log::logger().flush();
+            std::process::exit(130);
+        }
+    });
+}
+
+#[derive(Default)]
+struct JobSummary {
+    completed: Vec<String>,
+    // job and the step it resumes from
+    resumable: Vec<(String, String)>,
+}
+
+fn job_summary() -> &'static Mutex<JobSummary> {
+    static SUMMARY: OnceLock<Mutex<JobSummary>> = OnceLock::new();
+    SUMMARY.get_or_init(Default::default)
+}
+
+/// Records a job that finished in this run, for the summary printed on shutdown.
+pub fn record_completed_job(job: &str) {
+    job_summary()
+        .lock()
+        .unwrap()
+        .completed
+        .push(job.to_string());
+}
+
+/// Records a job that is left in the journal and resumes from `next_step` next time.
+pub fn record_resumable_job(job: &str, next_step: impl ToString) {
+    job_summary()
+        .lock()
+        .unwrap()
+        .resumable
+        .push((job.to_string(), next_step.to_string()));
+}
+
+/// Prints what was completed in this run and what resumes next time, and flushes the log.
+pub fn print_shutdown_summary() {
+    let summary = job_summary().lock().unwrap();
+    println!("Stopped.");
+    if summary.completed.is_empty() {
+        println!("No withdrawal or claim was completed in this run.");
+    } else {
+        println!("Completed: {}", summary.completed.join(", "));
+    }
+    for (job, next_step) in &summary.resumable {
+        println!("{} will resume from {} on the next run.", job, next_step);
+    }
+    log::info!(
+        "Stopped by shutdown request. Completed: {:?}, resumable: {:?}",
+        summary.completed,
+        summary.resumable
+    );
+    log::logger().flush();
+}
@@ -1,15 +1,42 @@
-pub fn sleep_until(target: u64) {
+use std::time::Duration;
+
+use super::shutdown::{should_stop, stop_requested, Cancelled};
+
+/// Waits until the unix time `target` without blocking the executor. The wall clock is checked
+/// every second, so the wait also ends on time after the computer wakes up from sleep, when the
+/// monotonic clock of tokio has stopped. Returns `Cancelled` as soon as the task should stop, see
+/// `should_stop`.
+pub async fn sleep_until(target: u64) -> Result<(), Cancelled> {
     loop {
+        if should_stop() {
+            return Err(Cancelled);
+        }
         let now = chrono::Utc::now().timestamp() as u64;
         if now >= target {
-            break;
+            return Ok(());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            _ = stop_requested() => return Err(Cancelled),
         }
-        std::thread::sleep(std::time::Duration::from_secs(1));
     }
 }
 
-// sleep avoiding hanging when PC is in sleep mode
-pub fn sleep_for(seconds: u64) {
+/// Waits for `seconds`, see `sleep_until`.
+pub async fn sleep_for(seconds: u64) -> Result<(), Cancelled> {
     let target = chrono::Utc::now().timestamp() as u64 + seconds;
-    sleep_until(target);
+    sleep_until(target).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sleep_for, sleep_until};
+
+    #[tokio::test]
+    async fn test_sleep_until() {
+        let start = std::time::Instant::now();
+        sleep_until(0).await.unwrap();
+        sleep_for(1).await.unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(3));
+    }
 }
@@ -0,0 +1,306 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use alloy::primitives::{Address, TxHash, U256};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    file::{get_data_path, write_file_atomically},
+    network::get_network,
+};
+
+// jobs running in parallel record their transactions concurrently, so every load-modify-save of
+// the ledger file holds this lock
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+fn ledger_path() -> anyhow::Result<PathBuf> {
+    Ok(get_data_path()?.join(format!("tx_ledger.{}.json", get_network())))
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TxStatus {
+    Pending,   // broadcast but not mined yet
+    Confirmed, // mined and succeeded
+    Failed,    // mined and reverted
+    Dropped,   // not mined, and the nonce was used by another transaction or discarded
+}
+
+impl Display for TxStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TxStatus::Pending => write!(f, "pending"),
+            TxStatus::Confirmed => write!(f, "confirmed"),
+            TxStatus::Failed => write!(f, "failed"),
+            TxStatus::Dropped => write!(f, "dropped"),
+        }
+    }
+}
+
+/// A transaction broadcast by the CLI, including the replacements sent by gas bumps
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TxLedgerEntry {
+    pub id: u64,
+    pub name: String,
+    pub from: Address,
+    pub nonce: u64,
+    pub tx_hashes: Vec<TxHash>, // replacement chain, oldest first
+    pub gas_limit: u64,
+    pub max_fee_per_gas: u128,          // of the latest replacement
+    pub max_priority_fee_per_gas: u128, // of the latest replacement
+    pub value: U256,
+    pub status: TxStatus,
+    pub mined_tx_hash: Option<TxHash>,
+    pub fee_paid: Option<U256>, // gas used * effective gas price
+    pub deposit_id: Option<u64>,
+    pub created_at: i64, // unix timestamp
+    pub updated_at: i64, // unix timestamp
+}
+
+/// Parameters of a broadcast transaction to record in the ledger
+#[derive(Debug, Clone)]
+pub struct BroadcastRecord {
+    pub name: String,
+    pub from: Address,
+    pub nonce: u64,
+    pub tx_hash: TxHash,
+    pub gas_limit: u64,
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+    pub value: U256,
+    pub deposit_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxLedger {
+    pub entries: Vec<TxLedgerEntry>,
+}
+
+impl TxLedger {
+    pub fn load() -> anyhow::Result<Self> {
+        let _lock = LEDGER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        Self::load_from(&ledger_path()?)
+    }
+
+    fn load_from(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = std::fs::read(path)?;
+        let ledger: Self = serde_json::from_slice(&file)
+            .map_err(|e| anyhow::anyhow!("Transaction ledger at {:?} is broken: {}", path, e))?;
+        Ok(ledger)
+    }
+
+    fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        let input = serde_json::to_vec_pretty(&self)?;
+        write_file_atomically(path, &input)
+    }
+
+    /// Loads, modifies and saves the ledger while holding the lock.
+    fn modify<T>(f: impl FnOnce(&mut Self) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        Self::modify_at(&ledger_path()?, f)
+    }
+
+    fn modify_at<T>(
+        path: &Path,
+        f: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let _lock = LEDGER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut ledger = Self::load_from(path)?;
+        let result = f(&mut ledger)?;
+        ledger.save_to(path)?;
+        Ok(result)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&TxLedgerEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn pending_entries(&self) -> Vec<TxLedgerEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.status == TxStatus::Pending)
+            .cloned()
+            .collect()
+    }
+
+    /// Records a transaction about to be broadcast and returns its ledger id. It is recorded
+    /// first so that a transaction broadcast just before the process dies is still known.
+    pub fn record_broadcast(record: BroadcastRecord) -> anyhow::Result<u64> {
+        Self::modify(|ledger| Ok(ledger.push_broadcast(record)))
+    }
+
+    fn push_broadcast(&mut self, record: BroadcastRecord) -> u64 {
+        let id = self
+            .entries
+            .iter()
+            .map(|entry| entry.id + 1)
+            .max()
+            .unwrap_or(0);
+        let now = chrono::Utc::now().timestamp();
+        self.entries.push(TxLedgerEntry {
+            id,
+            name: record.name,
+            from: record.from,
+            nonce: record.nonce,
+            tx_hashes: vec![record.tx_hash],
+            gas_limit: record.gas_limit,
+            max_fee_per_gas: record.max_fee_per_gas,
+            max_priority_fee_per_gas: record.max_priority_fee_per_gas,
+            value: record.value,
+            status: TxStatus::Pending,
+            mined_tx_hash: None,
+            fee_paid: None,
+            deposit_id: record.deposit_id,
+            created_at: now,
+            updated_at: now,
+        });
+        id
+    }
+
+    /// Appends a gas-bumped replacement about to be broadcast to the entry
+    pub fn record_replacement(
+        id: u64,
+        tx_hash: TxHash,
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+    ) -> anyhow::Result<()> {
+        Self::update(id, |entry| {
+            entry.tx_hashes.push(tx_hash);
+            entry.max_fee_per_gas = max_fee_per_gas;
+            entry.max_priority_fee_per_gas = max_priority_fee_per_gas;
+        })
+    }
+
+    /// Removes a replacement that failed to broadcast, restoring the fees of the transaction it
+    /// was to replace.
+    pub fn remove_replacement(
+        id: u64,
+        tx_hash: TxHash,
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+    ) -> anyhow::Result<()> {
+        Self::update(id, |entry| {
+            entry.tx_hashes.retain(|hash| *hash != tx_hash);
+            entry.max_fee_per_gas = max_fee_per_gas;
+            entry.max_priority_fee_per_gas = max_priority_fee_per_gas;
+        })
+    }
+
+    pub fn record_status(
+        id: u64,
+        status: TxStatus,
+        mined_tx_hash: Option<TxHash>,
+        fee_paid: Option<U256>,
+    ) -> anyhow::Result<()> {
+        Self::update(id, |entry| {
+            entry.status = status;
+            entry.mined_tx_hash = mined_tx_hash;
+            entry.fee_paid = fee_paid;
+        })
+    }
+
+    fn update<F: FnOnce(&mut TxLedgerEntry)>(id: u64, f: F) -> anyhow::Result<()> {
+        Self::modify(|ledger| ledger.update_entry(id, f))
+    }
+
+    fn update_entry<F: FnOnce(&mut TxLedgerEntry)>(&mut self, id: u64, f: F) -> anyhow::Result<()> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| anyhow::anyhow!("Ledger entry {} not found", id))?;
+        f(entry);
+        entry.updated_at = chrono::Utc::now().timestamp();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(nonce: u64) -> BroadcastRecord {
+        BroadcastRecord {
+            name: "claim".to_string(),
+            from: Address::ZERO,
+            nonce,
+            tx_hash: TxHash::random(),
+            gas_limit: 21000,
+            max_fee_per_gas: 2,
+            max_priority_fee_per_gas: 1,
+            value: U256::ZERO,
+            deposit_id: None,
+        }
+    }
+
+    fn temp_ledger_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "mining-cli-ledger-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_record_and_update_entries() {
+        let path = temp_ledger_path("update");
+        let id = TxLedger::modify_at(&path, |ledger| Ok(ledger.push_broadcast(record(0)))).unwrap();
+        let replacement = TxHash::random();
+        TxLedger::modify_at(&path, |ledger| {
+            ledger.update_entry(id, |entry| {
+                entry.tx_hashes.push(replacement);
+                entry.status = TxStatus::Confirmed;
+                entry.mined_tx_hash = Some(replacement);
+            })
+        })
+        .unwrap();
+
+        let ledger = TxLedger::load_from(&path).unwrap();
+        let entry = ledger.get(id).unwrap();
+        assert_eq!(entry.tx_hashes.len(), 2);
+        assert_eq!(entry.mined_tx_hash, Some(replacement));
+        assert!(ledger.pending_entries().is_empty());
+        assert!(TxLedger::modify_at(&path, |ledger| ledger.update_entry(id + 1, |_| {})).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_broadcasts_get_distinct_ids() {
+        let path = temp_ledger_path("concurrent");
+        let ids = std::thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|nonce| {
+                    let path = &path;
+                    scope.spawn(move || {
+                        TxLedger::modify_at(path, |ledger| Ok(ledger.push_broadcast(record(nonce))))
+                            .unwrap()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<std::collections::HashSet<_>>()
+        });
+        assert_eq!(ids.len(), 8);
+        assert_eq!(TxLedger::load_from(&path).unwrap().entries.len(), 8);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_broken_ledger() {
+        let path = temp_ledger_path("broken");
+        std::fs::write(&path, b"{").unwrap();
+        assert!(TxLedger::load_from(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}